@@ -0,0 +1,91 @@
+//! Collects the format strings of every `binlog!` invocation in the crate into a table,
+//! so that binary log frames only need to carry the index of their format string.
+//!
+//! Only this crate's `src` directory is scanned by default. Crates depending on this one can
+//! have their invocations collected by listing their source directories in `BINLOG_SOURCES`,
+//! separated like `PATH`, for example through the `[env]` table of `.cargo/config.toml`.
+//! Strings from this crate come first so that their indices don't depend on the other sources
+
+use std::{env, fs, path::{Path, PathBuf}};
+
+const MACRO: &str = "binlog!(";
+
+const SOURCES: &str = "BINLOG_SOURCES";
+
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed={SOURCES}");
+
+    let mut dirs = vec![PathBuf::from("src")];
+    if let Some(extra) = env::var_os(SOURCES) {
+        dirs.extend(env::split_paths(&extra).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+
+    let mut files = Vec::new();
+    for dir in &dirs[1..] {
+        println!("cargo:rerun-if-changed={}", dir.display());
+    }
+    for dir in &dirs {
+        let start = files.len();
+        collect_sources(dir, &mut files);
+        files[start..].sort();
+    }
+
+    let mut strings: Vec<String> = Vec::new();
+    for file in files {
+        let source = fs::read_to_string(&file).expect("Failed to read source file");
+        for literal in format_strings(&source) {
+            if !strings.contains(&literal) {
+                strings.push(literal);
+            }
+        }
+    }
+
+    let mut table = format!("pub static STRINGS: [&str ; {}] = [\n", strings.len());
+    for literal in &strings {
+        table.push_str(&format!("    \"{literal}\",\n"));
+    }
+    table.push_str("];\n");
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("binlog_strings.rs");
+    fs::write(out, table).expect("Failed to write format string table");
+}
+
+fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).expect("Failed to read source directory") {
+        let path = entry.expect("Failed to read source directory entry").path();
+        if path.is_dir() {
+            collect_sources(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+/// Find the first string literal after each invocation of the macro, returning its source text
+/// without the surrounding quotes so that escapes are preserved
+fn format_strings(source: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find(MACRO) {
+        rest = &rest[start + MACRO.len()..];
+        // Skip the macro's own definition
+        if rest.trim_start().starts_with('$') {
+            continue
+        }
+
+        let Some(open) = rest.find('"') else { break };
+        let literal = &rest[open + 1..];
+        let mut escaped = false;
+        let Some(len) = literal.find(|c| {
+            let end = c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+            end
+        }) else { break };
+
+        strings.push(literal[..len].to_owned());
+        rest = &literal[len + 1..];
+    }
+
+    strings
+}
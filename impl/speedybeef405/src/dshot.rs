@@ -0,0 +1,184 @@
+//! DShot output for the four motor pads, PB6 to PB9 on TIM4 channels 1 to 4.
+//!
+//! The compare values of every channel are interleaved in one buffer, which DMA1 stream 6
+//! writes to the timer's DMA burst register on each update event, one bit period at a time.
+
+use bingo_fc::{
+    interface::motor::MotorOutput,
+    peripheral::dshot::{self, Command, CommandSequence, DshotSpeed, Frame, BIT_TICKS, BUFFER_LEN},
+    Micros,
+};
+use stm32f4xx_hal::{
+    gpio::{Alternate, Pin},
+    pac::{DMA1, RCC, TIM4},
+    rcc::Clocks,
+};
+
+pub const MOTORS: usize = 4;
+
+/// Length of the interleaved compare value buffer for all motors
+pub const BURST_LEN: usize = BUFFER_LEN * MOTORS;
+
+/// DMA request channel of TIM4_UP on DMA1 stream 6
+const DMA_CHANNEL: u32 = 2;
+
+/// Offset of CCR1 in the timer's registers in words, where each burst starts
+const BURST_BASE: u8 = 0x34 / 4;
+
+/// Transfer complete, half transfer, transfer error, direct mode error and FIFO error flags of
+/// stream 6 in HIFCR
+const STREAM_FLAGS: u32 = 0x3D << 16;
+
+// DMA stream configuration register fields
+const CR_EN: u32 = 1 << 0;
+const CR_DIR_MEM_TO_PERIPH: u32 = 0b01 << 6;
+const CR_MINC: u32 = 1 << 10;
+const CR_PSIZE_16: u32 = 0b01 << 11;
+const CR_MSIZE_16: u32 = 0b01 << 13;
+const CR_PL_HIGH: u32 = 0b10 << 16;
+const CR_CHSEL_SHIFT: u32 = 25;
+
+pub type MotorPins = (
+    Pin<'B', 6, Alternate<2>>,
+    Pin<'B', 7, Alternate<2>>,
+    Pin<'B', 8, Alternate<2>>,
+    Pin<'B', 9, Alternate<2>>,
+);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DshotError {
+    /// The previous frame is still being sent
+    Busy,
+    /// The timer clock isn't a multiple of the speed's timer frequency, so the bit period would
+    /// be wrong. With the 84 MHz timer clock this is the case for DShot1200
+    Speed,
+}
+
+pub struct Dshot {
+    _tim: TIM4,
+    dma: DMA1,
+    _pins: MotorPins,
+    buffer: &'static mut [u16 ; BURST_LEN],
+    commands: CommandSequence,
+    clock: fn() -> Micros,
+}
+
+impl Dshot {
+    pub fn new(
+        tim: TIM4,
+        dma: DMA1,
+        pins: MotorPins,
+        buffer: &'static mut [u16 ; BURST_LEN],
+        speed: DshotSpeed,
+        clocks: &Clocks,
+        clock: fn() -> Micros,
+    ) -> Result<Self, DshotError> {
+        let timclk = clocks.timclk1().raw();
+        if timclk % speed.timer_frequency() != 0 {
+            return Err(DshotError::Speed)
+        }
+
+        // SAFETY: only the enable bits of peripherals owned here are modified
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.apb1enr().modify(|_, w| w.tim4en().set_bit());
+        rcc.ahb1enr().modify(|_, w| w.dma1en().set_bit());
+
+        let prescaler = timclk / speed.timer_frequency() - 1;
+        tim.cr1().write(|w| w.arpe().set_bit());
+        tim.psc().write(|w| w.psc().set(prescaler as u16));
+        tim.arr().write(|w| w.arr().set(BIT_TICKS - 1));
+        tim.ccmr1_output().write(|w| {
+            w.oc1m().pwm_mode1().oc1pe().set_bit()
+                .oc2m().pwm_mode1().oc2pe().set_bit()
+        });
+        tim.ccmr2_output().write(|w| {
+            w.oc3m().pwm_mode1().oc3pe().set_bit()
+                .oc4m().pwm_mode1().oc4pe().set_bit()
+        });
+        for channel in 0..4 {
+            tim.ccr(channel).write(|w| w.ccr().set(0));
+        }
+        tim.ccer().write(|w| w.cc1e().set_bit().cc2e().set_bit().cc3e().set_bit().cc4e().set_bit());
+        // Each update event requests a burst of four writes, to CCR1 through CCR4
+        tim.dcr().write(|w| unsafe { w.dba().bits(BURST_BASE).dbl().bits(MOTORS as u8 - 1) });
+        tim.dier().write(|w| w.ude().set_bit());
+        tim.egr().write(|w| w.ug().set_bit());
+        tim.cr1().modify(|_, w| w.cen().set_bit());
+
+        let stream = dma.st(6);
+        stream.par().write(|w| unsafe { w.bits(tim.dmar().as_ptr() as u32) });
+
+        Ok(Self {
+            _tim: tim,
+            dma,
+            _pins: pins,
+            buffer,
+            commands: CommandSequence::new(),
+            clock,
+        })
+    }
+
+    /// Begin sending a command to all ESCs, which is sent in place of the stop frames while the
+    /// motors are stopped
+    pub fn command(&mut self, command: Command) {
+        self.commands.start(command);
+    }
+
+    fn send(&mut self, frames: &[Frame ; MOTORS]) -> Result<(), DshotError> {
+        let stream = self.dma.st(6);
+        if stream.cr().read().bits() & CR_EN != 0 {
+            return Err(DshotError::Busy)
+        }
+
+        dshot::encode_burst(frames, &mut self.buffer[..]);
+
+        // SAFETY: the stream is disabled, and the buffer is only written while it is
+        self.dma.hifcr().write(|w| unsafe { w.bits(STREAM_FLAGS) });
+        stream.m0ar().write(|w| unsafe { w.bits(self.buffer.as_ptr() as u32) });
+        stream.ndtr().write(|w| unsafe { w.bits(BURST_LEN as u32) });
+        stream.cr().write(|w| unsafe {
+            w.bits(
+                DMA_CHANNEL << CR_CHSEL_SHIFT
+                    | CR_PL_HIGH
+                    | CR_MSIZE_16
+                    | CR_PSIZE_16
+                    | CR_MINC
+                    | CR_DIR_MEM_TO_PERIPH
+                    | CR_EN,
+            )
+        });
+
+        Ok(())
+    }
+}
+
+impl MotorOutput for Dshot {
+    type Error = DshotError;
+
+    fn set_throttle(&mut self, throttle: &[f32]) -> Result<(), Self::Error> {
+        // ESCs only act on commands while stopped, so hold the motors until one has been sent
+        if self.commands.is_busy() {
+            return self.stop()
+        }
+
+        let mut frames = [Frame::throttle(0f32, false) ; MOTORS];
+        for (frame, &throttle) in frames.iter_mut().zip(throttle) {
+            *frame = Frame::throttle(throttle, false);
+        }
+
+        self.send(&frames)
+    }
+
+    fn stop(&mut self) -> Result<(), Self::Error> {
+        let frame = self.commands.next((self.clock)()).unwrap_or(Frame::throttle(0f32, false));
+        self.send(&[frame ; MOTORS])
+    }
+
+    fn set_reversed(&mut self, reversed: bool) -> Result<bool, Self::Error> {
+        self.command(match reversed {
+            true => Command::SpinDirectionReversed,
+            false => Command::SpinDirectionNormal,
+        });
+        Ok(true)
+    }
+}
@@ -3,11 +3,38 @@
 
 use core::cell::{OnceCell, RefCell};
 
-use bingo_fc::peripheral::bmi270::{self, Bmi270};
+use bingo_fc::{
+    ahrs::MadgwickAhrs,
+    control::{LevelController, PidGains, RateController, Rates},
+    interface::alignment::{Aligned, Alignment, Rotation},
+    log::{flash::FlashLog, usb_serial::UsbSerialLogger},
+    mixer::table::{Geometry, TableMixer},
+    peripheral::{bmi270::Bmi270, dshot::DshotSpeed, spiflash::SpiFlash},
+    rc::{crsf::{self, Crsf}, mapping::RcMapper, modes::{ModeActivation, Modes}},
+    state::Event,
+    warn,
+    FlightControllerBuilder,
+    Micros,
+};
+#[cfg(feature = "usb-msc")]
+use bingo_fc::log::msc::{FatDisk, UsbMsc};
+#[cfg(not(feature = "usb-msc"))]
+use bingo_fc::log::blackbox::{self, Recorder};
 use cortex_m::interrupt::Mutex;
 use embedded_hal_bus::spi::ExclusiveDevice;
-use stm32f4xx_hal::{gpio::{GpioExt, Pin, PinSpeed, Speed}, otg_fs::USB, pac::{self, NVIC, SPI1}, prelude::*, rcc::RccExt, timer::SysDelay};
+use stm32f4xx_hal::{gpio::{GpioExt, Pin, Speed}, pac::{self, TIM2}, prelude::*, rcc::RccExt, serial, spi::{Mode, Phase, Polarity}};
 use synopsys_usb_otg::UsbBus;
+use usb_device::{device::{StringDescriptors, UsbDeviceBuilder}, LangID};
+use usbd_serial::USB_CLASS_CDC;
+
+mod dshot;
+mod receiver;
+
+/// Rate of the control loop in Hz, below the gyro's output rate so each step reads a new sample
+const LOOP_RATE: Micros = 1000;
+
+/// Mounting rotation of the BMI270 relative to the arrow on the board
+const GYRO_ALIGN: Rotation = Rotation::Cw270;
 
 
 static STATUS_LED: Mutex<OnceCell<RefCell<Pin<'C', 8, stm32f4xx_hal::gpio::Output>>>> = Mutex::new(OnceCell::new());
@@ -23,9 +50,16 @@ fn abort(_: &core::panic::PanicInfo) -> ! {
     loop { core::hint::spin_loop() }
 }
 
+/// Read the free-running microsecond counter on TIM2
+fn micros() -> Micros {
+    // SAFETY: TIM2 is only configured once at startup, and reading its counter has no side effects
+    unsafe { (*TIM2::ptr()).cnt().read().bits() }
+}
+
 #[cortex_m_rt::entry]
 fn main() -> ! {
     static mut USB_EP_BUF: &mut [u32] = &mut [0 ; 1024];
+    static mut DSHOT_BUF: [u16 ; dshot::BURST_LEN] = [0 ; dshot::BURST_LEN];
 
     let peripherals = pac::Peripherals::take().unwrap();
     let core_peripherals = pac::CorePeripherals::take().unwrap();
@@ -34,6 +68,8 @@ fn main() -> ! {
     let gpioc = peripherals.GPIOC.split();
     let mut pc8 = gpioc.pc8.into_push_pull_output();
     pc8.set_high();
+    // The beeper sounds while PC13 is low
+    let mut beeper = gpioc.pc13.into_push_pull_output_in_state(stm32f4xx_hal::gpio::PinState::High);
     cortex_m::interrupt::free(|cs| {
         let _ = STATUS_LED.borrow(cs).set(RefCell::new(pc8));
     });
@@ -48,7 +84,27 @@ fn main() -> ! {
         .require_pll48clk()
         .freeze();
 
+    // TIM2 is a 32-bit timer, so counting microseconds it wraps the same way as Micros
+    // SAFETY: only the enable bit of TIM2, which is owned here, is modified
+    unsafe { (*pac::RCC::ptr()).apb1enr().modify(|_, w| w.tim2en().set_bit()) };
+    let tim2 = peripherals.TIM2;
+    tim2.psc().write(|w| w.psc().set((clocks.timclk1().raw() / 1_000_000 - 1) as u16));
+    tim2.arr().write(|w| unsafe { w.bits(u32::MAX) });
+    tim2.egr().write(|w| w.ug().set_bit());
+    tim2.cr1().write(|w| w.cen().set_bit());
+
     let gpioa = peripherals.GPIOA.split();
+    let gpiob = peripherals.GPIOB.split();
+
+    let motors = dshot::Dshot::new(
+        peripherals.TIM4,
+        peripherals.DMA1,
+        (gpiob.pb6.into_alternate(), gpiob.pb7.into_alternate(), gpiob.pb8.into_alternate(), gpiob.pb9.into_alternate()),
+        DSHOT_BUF,
+        DshotSpeed::Dshot600,
+        &clocks,
+        micros,
+    ).expect("DShot speed doesn't divide the timer clock");
 
     let spi1_cs = gpioa.pa4.into_push_pull_output_in_state(stm32f4xx_hal::gpio::PinState::High);
 
@@ -63,7 +119,27 @@ fn main() -> ! {
 
     let usb_bus = UsbBus::new(usb, USB_EP_BUF);
 
-    let mut serial = SerialPort::new(&usb_bus);
+    let logger = UsbSerialLogger::<_>::new(&usb_bus);
+
+    // Onboard blackbox flash on SPI3. It is either exported read-only over USB or recorded to,
+    // as the disk is built from the log once at startup
+    let flash_log = {
+        let spi3 = peripherals.SPI3.spi(
+            (gpioc.pc10, gpioc.pc11, gpioc.pc12),
+            Mode {
+                polarity: Polarity::IdleLow,
+                phase: Phase::CaptureOnFirstTransition
+            },
+            21.MHz(),
+            &clocks
+        ).init();
+        let flash_cs = gpioa.pa15.into_push_pull_output_in_state(stm32f4xx_hal::gpio::PinState::High);
+        let spi3 = ExclusiveDevice::new_no_delay(spi3, flash_cs).expect("Failed to create exclusive SPI device for flash");
+        let flash = SpiFlash::new(spi3).expect("Failed to identify onboard flash");
+        FlashLog::<_>::mount(flash).expect("Failed to mount flash log")
+    };
+    #[cfg(feature = "usb-msc")]
+    let mut msc = UsbMsc::new(&usb_bus, FatDisk::from_log(flash_log));
 
     let mut device = UsbDeviceBuilder::new(&usb_bus, usb_device::device::UsbVidPid(0xbeef, 0x0911))
         .strings(&[
@@ -72,9 +148,10 @@ fn main() -> ! {
                 .product("Bingo Flight Controller")
                 .serial_number("BFC")
         ])
-        .map(|d|
-            d.device_class(USB_CLASS_CDC)
-            .build()
+        .map(|d| match cfg!(feature = "usb-msc") {
+            true => d.composite_with_iads(),
+            false => d.device_class(USB_CLASS_CDC),
+        }.build()
     ).unwrap();
     
     let mut miso = gpioa.pa6.into_push_pull_output();
@@ -102,41 +179,80 @@ fn main() -> ! {
     
     delay.delay_ms(200);
     let mut bmi = Bmi270::new(spi1, delay);
-    let mut istat = bmi.init().unwrap();
-    bmi.enable().unwrap();
+    bmi.init().expect("Failed to initialize BMI270");
+    bmi.enable().expect("Failed to enable BMI270");
 
+    // CRSF receiver on the RX2 pad
+    let rx = peripherals.USART2.rx(
+        gpioa.pa3,
+        serial::Config::default().baudrate(crsf::BAUD_RATE.bps()),
+        &clocks,
+    ).expect("Failed to configure receiver UART");
+    let mut rc = RcMapper::new(Crsf::new(receiver::SerialRx(rx)));
+    // Arm and record the blackbox on the high position of AUX1, angle and horizon on the high and middle of AUX2, and
+    // the beeper and turtle mode on the high positions of AUX3 and AUX4
+    let _ = rc.activations.push(ModeActivation::new(Modes::ARM, 4, 1700, 2100));
+    let _ = rc.activations.push(ModeActivation::new(Modes::BLACKBOX, 4, 1700, 2100));
+    let _ = rc.activations.push(ModeActivation::new(Modes::ANGLE, 5, 1700, 2100));
+    let _ = rc.activations.push(ModeActivation::new(Modes::HORIZON, 5, 1300, 1700));
+    let _ = rc.activations.push(ModeActivation::new(Modes::BEEPER, 6, 1700, 2100));
+    let _ = rc.activations.push(ModeActivation::new(Modes::TURTLE, 7, 1700, 2100));
 
+    // Conservative starting gains, to be tuned for the airframe
+    let rate = RateController::new(
+        [PidGains::new(0.04, 0.06, 0.0004), PidGains::new(0.04, 0.06, 0.0004), PidGains::new(0.06, 0.06, 0.0)],
+        Rates::default(),
+    );
+    let fc = FlightControllerBuilder::new()
+        .logger(logger)
+        .imu(Aligned::new(bmi, Alignment::new(GYRO_ALIGN)))
+        .estimator(MadgwickAhrs::new(0.1))
+        .rc(rc)
+        .controller(LevelController::new(rate, 55f32.to_radians(), 5.0))
+        .mixer(TableMixer::new(Geometry::QuadX))
+        .motors(motors);
+    // The blackbox records while the BLACKBOX mode is switched on
+    #[cfg(not(feature = "usb-msc"))]
+    let fc = fc.hooks(Recorder::new(flash_log, blackbox::Config {
+        looptime: 1_000_000 / LOOP_RATE,
+        ..Default::default()
+    }));
+    let mut fc = fc.build();
+    let _ = fc.handle(Event::BootComplete);
+
+    let mut last_step = micros();
     loop {
-        if !device.poll(&mut [&mut serial]) {
+        #[cfg(feature = "usb-msc")]
+        device.poll(&mut [fc.log(), &mut msc]);
+        #[cfg(not(feature = "usb-msc"))]
+        device.poll(&mut [fc.log()]);
+
+        // Program the flash between steps, writing out the last partial page once stopped
+        #[cfg(not(feature = "usb-msc"))]
+        {
+            let recorder = fc.state_mut().hooks_mut();
+            if !recorder.is_recording() {
+                recorder.sink_mut().flush();
+            }
+            if let Err(e) = recorder.sink_mut().poll() {
+                warn!(*fc.log(), "Blackbox flash write failed: {e:?}");
+            }
+        }
+
+        let now = micros();
+        if now.wrapping_sub(last_step) < 1_000_000 / LOOP_RATE {
             continue
         }
+        last_step = now;
 
-        let mut buf = [0u8 ; 256];
-        
-        match serial.read(&mut buf[..]) {
-            Ok(_) => {
-                if istat == bmi270::regs::InternalStatusMessage::NotInit || istat == bmi270::regs::InternalStatusMessage::DrvErr {
-                    istat = bmi.init().unwrap();
-                    if istat == bmi270::regs::InternalStatusMessage::InitOk {
-                        bmi.enable().unwrap();
-                    }
-                }
-                let status = bmi.status().unwrap().message();
-                let (measure, gyro) = bmi.data().unwrap();
-                let time = bmi.sensor_time().unwrap();
-                let intstat = bmi.status().unwrap();
-                let enabled = bmi.read::<bmi270::regs::PwrCtrl>().unwrap();
-                let interr = bmi.read::<bmi270::regs::InternalError>().unwrap();
-                let id = bmi.read::<bmi270::regs::ChipId>().unwrap().raw_value();
-                write!(&mut serial, "Accel is {measure:?} - gyro {gyro:?} - t{time} - stat {intstat} stat {status:?} - enabled {enabled} - err {interr} - upload {istat:?} - id {id:X}\r\n");
-                if !enabled.acc_en() {
-                    bmi.enable().unwrap();
-                }
-            },
-            Err(UsbError::WouldBlock) => continue,
-            Err(_) => {
-                let _ = serial.write_all(b"Failed to read serial from USB device");
-            }
+        // Disarmed steps send stop frames, which ESCs need to keep receiving to stay armed
+        if let Err(e) = fc.step(now) {
+            warn!(*fc.log(), "Control loop step failed: {e:?}");
+        }
+        #[cfg(not(feature = "usb-msc"))]
+        if let Some(snapshot) = fc.snapshot() {
+            fc.state_mut().hooks_mut().record(&snapshot);
         }
+        beeper.set_state((!fc.beeper()).into());
     }
 }
@@ -0,0 +1,26 @@
+//! Serial receiver input on USART2, with RX on PA3.
+
+use embedded_io::{ErrorType, Read, ReadReady};
+use stm32f4xx_hal::serial::{Error, Instance, Rx, RxISR};
+
+/// Receive half of a UART, read through the embedded-io traits the receiver drivers use
+pub struct SerialRx<U: Instance>(pub Rx<U>);
+
+impl<U: Instance> ErrorType for SerialRx<U> {
+    type Error = Error;
+}
+
+impl<U: Instance> Read for SerialRx<U> {
+    /// Wait for one byte, which is always waiting if [read_ready](ReadReady::read_ready) was true
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let Some(first) = buf.first_mut() else { return Ok(0) };
+        *first = nb::block!(embedded_hal_nb::serial::Read::read(&mut self.0))?;
+        Ok(1)
+    }
+}
+
+impl<U: Instance> ReadReady for SerialRx<U> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.0.is_rx_not_empty())
+    }
+}
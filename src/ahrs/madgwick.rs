@@ -1,6 +1,8 @@
-use nalgebra::{Matrix3x4, Quaternion, Vector3};
+use nalgebra::{Matrix3x4, Quaternion, UnitQuaternion, Vector3};
 use nalgebra as na;
 
+use super::AttitudeEstimator;
+
 
 pub struct MadgwickAhrs<T> {
     q: Quaternion<T>,
@@ -16,7 +18,7 @@ impl<T> MadgwickAhrs<T>
     pub fn new(beta: T) -> Self {
         let two = T::one() + T::one();
         Self {
-            q: Quaternion::new(T::zero(), T::zero(), T::zero(), T::one()),
+            q: Quaternion::identity(),
             four: two + two,
             half: T::one() / two,
             two,
@@ -29,10 +31,16 @@ impl<T> MadgwickAhrs<T>
     }
 
     pub fn update(&mut self, gyro: Vector3<T>, accel: Vector3<T>, deltat: T) {
-        let q_rates = Quaternion::from_imag(gyro) * self.q; 
-        let grad = self.objective_jacobian(self.q).transpose() * self.objective(self.q, accel);
-        let step = grad.normalize();
-        self.q += (q_rates - Quaternion::from_vector(step * self.beta)) * deltat;
+        let mut q_dot = self.q * Quaternion::from_imag(gyro) * self.half;
+        if let Some(accel) = accel.try_normalize(T::zero()) {
+            let grad = self.objective_jacobian(self.q).transpose() * self.objective(self.q, accel);
+            if let Some(step) = grad.try_normalize(T::zero()) {
+                // Jacobian columns are ordered w, i, j, k but nalgebra stores quaternions as i, j, k, w
+                q_dot -= Quaternion::new(step[0], step[1], step[2], step[3]) * self.beta;
+            }
+        }
+
+        self.q += q_dot * deltat;
         self.q = self.q.normalize();
     }
 
@@ -54,6 +62,22 @@ impl<T> MadgwickAhrs<T>
 
 }
 
+impl<T> AttitudeEstimator<T> for MadgwickAhrs<T>
+where T: na::RealField + Copy
+{
+    fn update(&mut self, gyro: Vector3<T>, accel: Vector3<T>, deltat: T) {
+        MadgwickAhrs::update(self, gyro, accel, deltat)
+    }
+
+    fn attitude(&self) -> UnitQuaternion<T> {
+        UnitQuaternion::new_normalize(self.q)
+    }
+
+    fn reset(&mut self) {
+        self.q = Quaternion::identity();
+    }
+}
+
 impl<T> Default for MadgwickAhrs<T>
 where T: na::RealField + Copy
 {
@@ -70,17 +94,33 @@ mod tests {
 
     #[test]
     fn test_madgwick() {
-        let R2D: fn(f32) -> f32 = |v| v * 180f32 / std::f32::consts::PI;
+        let r2d: fn(f32) -> f32 = |v| v * 180f32 / std::f32::consts::PI;
         let mut filter = MadgwickAhrs::<f32>::default();
         let gravity = Vector3::new(1f32, 1f32, 0f32).normalize();
         for i in 0..100000 {
             filter.update(Vector3::new(0f32, 0f32, 0f32), gravity, 1f32 / 1000f32);
             let (roll, pitch, yaw) = UnitQuaternion::from_quaternion(*filter.quat()).euler_angles();
-            let (roll, pitch, yaw) = (R2D(roll), R2D(pitch), R2D(yaw));
+            let (roll, pitch, yaw) = (r2d(roll), r2d(pitch), r2d(yaw));
             let transformed = UnitQuaternion::from_quaternion(*filter.quat()).transform_vector(&gravity);
-            println!("i{i:3} - r{roll:4.2} p{pitch:4.2} y{yaw:4.2} - {transformed}");
+            if i % 10000 == 0 {
+                println!("i{i:3} - r{roll:4.2} p{pitch:4.2} y{yaw:4.2} - {transformed}");
+            }
         }
-        panic!()
+
+        let predicted = filter.attitude().inverse_transform_vector(&Vector3::z());
+        assert!((predicted - gravity).norm() < 1e-3, "filter converged to {predicted}");
+    }
+
+    #[test]
+    fn test_madgwick_gyro_integration() {
+        let mut filter = MadgwickAhrs::<f32>::new(0f32);
+        let rate = core::f32::consts::FRAC_PI_2;
+        for _ in 0..1000 {
+            filter.update(Vector3::new(0f32, 0f32, rate), Vector3::z(), 1f32 / 1000f32);
+        }
+
+        let (_, _, yaw) = filter.attitude().euler_angles();
+        assert!((yaw - rate).abs() < 1e-3, "integrated yaw was {yaw}");
     }
 
 }
@@ -1,3 +1,17 @@
+use nalgebra::{UnitQuaternion, Vector3};
+
 pub mod madgwick;
 
 pub use madgwick::MadgwickAhrs;
+
+/// Filter that fuses IMU measurements into an estimate of the vehicle's attitude
+pub trait AttitudeEstimator<T> {
+    /// Update the estimate with angular velocity in rad/s and acceleration in g measured over `deltat` seconds
+    fn update(&mut self, gyro: Vector3<T>, accel: Vector3<T>, deltat: T);
+
+    /// Get the current attitude, rotating vectors from the body frame to the earth frame
+    fn attitude(&self) -> UnitQuaternion<T>;
+
+    /// Reset the estimate to level
+    fn reset(&mut self);
+}
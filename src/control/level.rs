@@ -0,0 +1,245 @@
+//! Self-levelling flight modes, where the sticks command an attitude rather than a rotation
+
+use nalgebra::{Quaternion, Unit, UnitQuaternion, Vector3};
+use nalgebra as na;
+
+use crate::interface::rc::Modes;
+
+use super::{ControlInput, ControlTelemetry, Controller, RateController};
+
+/// How roll and pitch stick positions are interpreted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LevelMode {
+    /// Sticks command angular velocity directly
+    #[default]
+    Acro,
+    /// Sticks command a tilt angle, returning level when centred
+    Angle,
+    /// Angle mode near the centre of the sticks, blending to acro towards full deflection
+    Horizon,
+}
+
+impl From<Modes> for LevelMode {
+    /// Get the self-levelling mode selected, with angle taking priority over horizon
+    fn from(modes: Modes) -> Self {
+        match (modes.contains(Modes::ANGLE), modes.contains(Modes::HORIZON)) {
+            (true, _) => Self::Angle,
+            (false, true) => Self::Horizon,
+            (false, false) => Self::Acro,
+        }
+    }
+}
+
+/// Outer attitude loop, converting stick positions into a target tilt and driving the
+/// [RateController] towards it.
+///
+/// The error is found from the rotation between the current and target attitudes rather than
+/// from Euler angles, so it stays well defined at any tilt. Heading is left to the yaw stick,
+/// which always commands a rate.
+pub struct LevelController<T> {
+    pub rate: RateController<T>,
+    pub mode: LevelMode,
+    /// Tilt from level at full stick deflection in radians
+    pub max_angle: T,
+    /// Angular velocity commanded per radian of attitude error in 1/s
+    pub gain: T,
+    /// Stick deflection in (0, 1] at which horizon mode stops levelling entirely
+    pub horizon_transition: T,
+}
+
+impl<T> LevelController<T>
+    where T: na::RealField + Copy
+{
+    pub fn new(rate: RateController<T>, max_angle: T, gain: T) -> Self {
+        Self {
+            rate,
+            mode: LevelMode::Acro,
+            max_angle,
+            gain,
+            horizon_transition: T::one(),
+        }
+    }
+
+    /// Angular velocity in rad/s that rotates `attitude` towards the tilt commanded by the
+    /// roll and pitch sticks, keeping the current heading
+    pub fn level_rate(&self, attitude: &UnitQuaternion<T>, roll: T, pitch: T) -> Vector3<T> {
+        // Split off the rotation about the vertical, leaving the tilt from level
+        let q = attitude.quaternion();
+        let heading = Unit::try_new(Quaternion::new(q.w, T::zero(), T::zero(), q.k), T::default_epsilon())
+            .unwrap_or_else(UnitQuaternion::identity);
+        let tilt = heading.inverse() * attitude;
+
+        // Deflecting the stick tilts the vehicle the same way an acro command would rotate it,
+        // limiting the total tilt rather than each axis so diagonals don't exceed the maximum
+        let mut axis = Vector3::new(roll, pitch, T::zero()) * self.max_angle;
+        if axis.norm() > self.max_angle {
+            axis.set_magnitude(self.max_angle);
+        }
+        let target = UnitQuaternion::from_scaled_axis(axis);
+
+        (tilt.inverse() * target).scaled_axis() * self.gain
+    }
+
+    /// Strength of levelling in horizon mode, from 1 with centred sticks to 0 at the transition
+    fn horizon_strength(&self, roll: T, pitch: T) -> T {
+        let deflection = roll.abs().max(pitch.abs());
+        (T::one() - deflection / self.horizon_transition).clamp(T::zero(), T::one())
+    }
+}
+
+impl<T> Controller<T> for LevelController<T>
+    where T: na::RealField + Copy
+{
+    fn update(&mut self, input: &ControlInput<T>) -> Vector3<T> {
+        let command = &input.command;
+        let strength = match self.mode {
+            LevelMode::Acro => return self.rate.update(input),
+            LevelMode::Angle => T::one(),
+            LevelMode::Horizon => self.horizon_strength(command.roll, command.pitch),
+        };
+
+        let acro = self.rate.rates.setpoint(command);
+        let level = self.level_rate(&input.attitude, command.roll, command.pitch);
+        let mut setpoint = level * strength + acro * (T::one() - strength);
+        setpoint.z = acro.z + level.z * strength;
+
+        self.rate.track(setpoint, input.gyro, input.dt)
+    }
+
+    fn reset(&mut self) {
+        self.rate.reset();
+    }
+
+    fn throttle(&self, throttle: T) -> T {
+        self.rate.throttle(throttle)
+    }
+
+    fn set_level_mode(&mut self, mode: LevelMode) {
+        self.mode = mode;
+    }
+
+    fn telemetry(&self) -> Option<ControlTelemetry<T>> {
+        self.rate.telemetry()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+    use crate::{control::{PidGains, RateCurve, Rates}, interface::rc::RcCommand};
+
+    const MAX_ANGLE: f32 = 55f32 * PI / 180f32;
+    const GAIN: f32 = 5f32;
+
+    fn controller(mode: LevelMode) -> LevelController<f32> {
+        let rate = RateController::new([PidGains::new(0.1, 0.0, 0.0) ; 3], Rates::new(RateCurve::linear(600f32)));
+        let mut controller = LevelController::new(rate, MAX_ANGLE, GAIN);
+        controller.mode = mode;
+        controller
+    }
+
+    fn setpoint(controller: &mut LevelController<f32>, attitude: UnitQuaternion<f32>, roll: f32, pitch: f32) -> Vector3<f32> {
+        controller.update(&ControlInput {
+            command: RcCommand { roll, pitch, yaw: 0.0, throttle: 0.5 },
+            attitude,
+            gyro: Vector3::zeros(),
+            dt: 0.001,
+        });
+        controller.telemetry().unwrap().setpoint
+    }
+
+    #[test]
+    fn test_mode_selection() {
+        assert_eq!(LevelMode::from(Modes::HORIZON | Modes::ARM), LevelMode::Horizon);
+        assert_eq!(LevelMode::from(Modes::ANGLE | Modes::HORIZON), LevelMode::Angle);
+        assert_eq!(LevelMode::from(Modes::empty()), LevelMode::Acro);
+    }
+
+    #[test]
+    fn test_angle_mode_levels() {
+        let mut controller = controller(LevelMode::Angle);
+        let level = setpoint(&mut controller, UnitQuaternion::identity(), 0.0, 0.0);
+        assert!(level.norm() < 1e-6);
+
+        let rolled = UnitQuaternion::from_scaled_axis(Vector3::x() * 0.2);
+        let correction = setpoint(&mut controller, rolled, 0.0, 0.0);
+        assert!((correction - Vector3::new(-0.2 * GAIN, 0.0, 0.0)).norm() < 1e-4, "{correction}");
+
+        // Full stick holds the vehicle at the maximum angle
+        let tilted = UnitQuaternion::from_scaled_axis(Vector3::y() * -MAX_ANGLE);
+        assert!(setpoint(&mut controller, tilted, 0.0, -1.0).norm() < 1e-4);
+
+        // Diagonals are limited to the same total tilt
+        let diagonal = UnitQuaternion::from_scaled_axis(Vector3::new(1f32, 1f32, 0f32).normalize() * MAX_ANGLE);
+        assert!(setpoint(&mut controller, diagonal, 1.0, 1.0).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_angle_mode_ignores_heading() {
+        let mut controller = controller(LevelMode::Angle);
+        let rolled = UnitQuaternion::from_scaled_axis(Vector3::x() * 0.3);
+        let expected = setpoint(&mut controller, rolled, 0.5, 0.0);
+        for heading in [0.5f32, FRAC_PI_2, PI, -2.5] {
+            let attitude = UnitQuaternion::from_scaled_axis(Vector3::z() * heading) * rolled;
+            let actual = setpoint(&mut controller, attitude, 0.5, 0.0);
+            assert!((actual - expected).norm() < 1e-4, "heading {heading}: {actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_angle_mode_high_tilt() {
+        let mut controller = controller(LevelMode::Angle);
+        // Pitched straight up, where the Euler roll and yaw angles are degenerate
+        let vertical = UnitQuaternion::from_scaled_axis(Vector3::y() * FRAC_PI_2);
+        let correction = setpoint(&mut controller, vertical, 0.0, 0.0);
+        assert!((correction - Vector3::new(0.0, -FRAC_PI_2 * GAIN, 0.0)).norm() < 1e-3, "{correction}");
+
+        // Nearly inverted, rolling back the shorter way
+        let inverted = UnitQuaternion::from_scaled_axis(Vector3::x() * 3f32);
+        let correction = setpoint(&mut controller, inverted, 0.0, 0.0);
+        assert!(correction.x < -2.9 * GAIN && correction.yz().norm() < 1e-3, "{correction}");
+    }
+
+    #[test]
+    fn test_angle_mode_converges() {
+        let mut controller = controller(LevelMode::Angle);
+        let mut attitude = UnitQuaternion::from_euler_angles(2.5f32, -0.8, 1.0);
+        // Assume the rate loop tracks its setpoint perfectly
+        let dt = 0.001f32;
+        for _ in 0..3000 {
+            let rate = setpoint(&mut controller, attitude, 0.6, -0.3);
+            attitude *= UnitQuaternion::from_scaled_axis(rate * dt);
+        }
+
+        let up = attitude.inverse() * Vector3::z();
+        let expected = UnitQuaternion::from_scaled_axis(Vector3::new(0.6, -0.3, 0.0) * MAX_ANGLE).inverse() * Vector3::z();
+        assert!((up - expected).norm() < 1e-3, "{up} != {expected}");
+    }
+
+    #[test]
+    fn test_horizon_mode_blend() {
+        let mut controller = controller(LevelMode::Horizon);
+        let rolled = UnitQuaternion::from_scaled_axis(Vector3::x() * 0.2);
+        let mut angle = self::controller(LevelMode::Angle);
+        assert_eq!(setpoint(&mut controller, rolled, 0.0, 0.0), setpoint(&mut angle, rolled, 0.0, 0.0));
+
+        // Full deflection gives acro rates
+        let acro = setpoint(&mut controller, rolled, 1.0, 0.0);
+        assert!((acro - Vector3::new(600f32.to_radians(), 0.0, 0.0)).norm() < 1e-4, "{acro}");
+
+        // Halfway, the levelling strength is halved
+        let half = setpoint(&mut controller, UnitQuaternion::identity(), 0.0, 0.5);
+        let level = angle.level_rate(&UnitQuaternion::identity(), 0.0, 0.5);
+        assert!((half - (level * 0.5 + Vector3::new(0.0, 150f32.to_radians(), 0.0))).norm() < 1e-4, "{half}");
+    }
+
+    #[test]
+    fn test_acro_mode() {
+        let mut controller = controller(LevelMode::Acro);
+        let rolled = UnitQuaternion::from_scaled_axis(Vector3::x() * 0.2);
+        let acro = setpoint(&mut controller, rolled, 0.0, -0.5);
+        assert!((acro - Vector3::new(0.0, -300f32.to_radians(), 0.0)).norm() < 1e-4, "{acro}");
+    }
+}
@@ -0,0 +1,71 @@
+use nalgebra::{Scalar, UnitQuaternion, Vector3};
+use num_traits::Zero;
+
+use crate::interface::rc::RcCommand;
+
+pub mod level;
+pub mod pid;
+pub mod rates;
+
+pub use level::{LevelController, LevelMode};
+pub use pid::{Pid, PidGains, RateController};
+pub use rates::{RateCurve, Rates};
+
+/// Vehicle state and pilot command for one control cycle
+#[derive(Clone, Copy, Debug)]
+pub struct ControlInput<T> {
+    pub command: RcCommand<T>,
+    /// Estimated attitude, rotating vectors from the body frame to the earth frame
+    pub attitude: UnitQuaternion<T>,
+    /// Measured angular velocity in rad/s
+    pub gyro: Vector3<T>,
+    /// Time since the previous control cycle in seconds
+    pub dt: T,
+}
+
+/// Internal values of a control law from its last update, recorded for tuning
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControlTelemetry<T> {
+    /// Target angular velocity in rad/s
+    pub setpoint: Vector3<T>,
+    /// Proportional, integral, derivative and feedforward contributions to the demand
+    pub p: Vector3<T>,
+    pub i: Vector3<T>,
+    pub d: Vector3<T>,
+    pub f: Vector3<T>,
+}
+
+impl<T: Scalar + Zero> Default for ControlTelemetry<T> {
+    fn default() -> Self {
+        Self {
+            setpoint: Vector3::zeros(),
+            p: Vector3::zeros(),
+            i: Vector3::zeros(),
+            d: Vector3::zeros(),
+            f: Vector3::zeros(),
+        }
+    }
+}
+
+/// Control law converting pilot commands into roll, pitch and yaw demands for the mixer
+pub trait Controller<T> {
+    /// Compute the roll, pitch and yaw demand for this cycle, each in [-1, 1]
+    fn update(&mut self, input: &ControlInput<T>) -> Vector3<T>;
+
+    /// Clear any accumulated state, called while disarmed
+    fn reset(&mut self);
+
+    /// Shape the throttle stick position in [0, 1] into the throttle passed to the mixer
+    fn throttle(&self, throttle: T) -> T {
+        throttle
+    }
+
+    /// Select how roll and pitch sticks are interpreted, ignored by control laws that can't
+    /// self-level
+    fn set_level_mode(&mut self, _mode: LevelMode) {}
+
+    /// Get the internal values from the last update, if the control law has any to report
+    fn telemetry(&self) -> Option<ControlTelemetry<T>> {
+        None
+    }
+}
@@ -0,0 +1,325 @@
+//! Rate PID controller, driving the measured angular velocity of each axis to a setpoint
+
+use nalgebra::Vector3;
+use nalgebra as na;
+
+use crate::math::filter::Pt1;
+
+use super::{ControlInput, ControlTelemetry, Controller, Rates};
+
+/// Gains and limits of the PID controller for one axis. Rates are in rad/s and outputs are
+/// fractions of full scale, so a `p` of 0.1 gives a demand of 0.1 for an error of 1 rad/s
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PidGains<T> {
+    pub p: T,
+    pub i: T,
+    pub d: T,
+    /// Gain applied to the rate of change of the setpoint
+    pub ff: T,
+    /// Largest magnitude of the integral term
+    pub i_limit: T,
+    /// Largest magnitude of the total output
+    pub output_limit: T,
+    /// Cutoff frequency of the lowpass on the derivative term in Hz, or zero to disable it
+    pub d_cutoff: T,
+    /// Cutoff frequency in Hz used to find how quickly the setpoint is changing for I-term
+    /// relax, or zero to disable it
+    pub relax_cutoff: T,
+    /// Setpoint change in rad/s, above the setpoint's lowpass, at which the integral stops
+    /// accumulating. The integral accumulates less as the change approaches this threshold
+    pub relax_threshold: T,
+}
+
+impl<T: na::RealField + Copy> PidGains<T> {
+    /// Create gains with no feedforward, I-term relax or D-term lowpass, and limits of 1
+    pub fn new(p: T, i: T, d: T) -> Self {
+        Self {
+            p,
+            i,
+            d,
+            ff: T::zero(),
+            i_limit: T::one(),
+            output_limit: T::one(),
+            d_cutoff: T::zero(),
+            relax_cutoff: T::zero(),
+            relax_threshold: T::one(),
+        }
+    }
+}
+
+/// Contribution of each term to the output of the last update
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PidTerms<T> {
+    pub p: T,
+    pub i: T,
+    pub d: T,
+    pub f: T,
+}
+
+/// PID controller for one axis.
+///
+/// The derivative is taken on the measurement rather than the error, so setpoint steps don't
+/// cause a kick, and feedforward from the setpoint's rate of change is added instead. The
+/// integral is clamped to its limit and stops accumulating while the output is saturated.
+pub struct Pid<T> {
+    pub gains: PidGains<T>,
+    integral: T,
+    last_measurement: Option<T>,
+    last_setpoint: Option<T>,
+    d_filter: Pt1<T>,
+    relax_filter: Pt1<T>,
+    terms: PidTerms<T>,
+}
+
+impl<T> Pid<T>
+    where T: na::RealField + Copy
+{
+    pub fn new(gains: PidGains<T>) -> Self {
+        Self {
+            gains,
+            integral: T::zero(),
+            last_measurement: None,
+            last_setpoint: None,
+            d_filter: Pt1::new(),
+            relax_filter: Pt1::new(),
+            terms: PidTerms {
+                p: T::zero(),
+                i: T::zero(),
+                d: T::zero(),
+                f: T::zero(),
+            },
+        }
+    }
+
+    /// Compute the output for a setpoint and measurement taken `dt` seconds after the last
+    pub fn update(&mut self, setpoint: T, measurement: T, dt: T) -> T {
+        let gains = self.gains;
+        let error = setpoint - measurement;
+        let p = gains.p * error;
+
+        let valid_dt = dt > T::zero();
+        let derivative = match (self.last_measurement, valid_dt) {
+            (Some(last), true) => -(measurement - last) / dt,
+            _ => T::zero(),
+        };
+        let d = gains.d * self.d_filter.update(derivative, gains.d_cutoff, dt);
+
+        let f = match (self.last_setpoint, valid_dt) {
+            (Some(last), true) => gains.ff * (setpoint - last) / dt,
+            _ => T::zero(),
+        };
+        self.last_measurement = Some(measurement);
+        self.last_setpoint = Some(setpoint);
+
+        // Reduce accumulation while the setpoint is moving quickly, as the error is then due to
+        // the vehicle's response lagging rather than a steady disturbance
+        let relax = match gains.relax_cutoff > T::zero() {
+            true => {
+                let change = (setpoint - self.relax_filter.update(setpoint, gains.relax_cutoff, dt)).abs();
+                (T::one() - change / gains.relax_threshold).max(T::zero())
+            },
+            false => T::one(),
+        };
+
+        let unclamped = p + self.integral + d + f;
+        let saturated = unclamped.abs() >= gains.output_limit && error.signum() == unclamped.signum();
+        if !saturated {
+            self.integral += gains.i * error * relax * dt;
+        }
+        self.integral = self.integral.clamp(-gains.i_limit, gains.i_limit);
+
+        self.terms = PidTerms { p, i: self.integral, d, f };
+        (p + self.integral + d + f).clamp(-gains.output_limit, gains.output_limit)
+    }
+
+    /// Get the contribution of each term to the last output
+    pub const fn terms(&self) -> PidTerms<T> {
+        self.terms
+    }
+
+    /// Clear the integral and the history used by the derivative and feedforward
+    pub fn reset(&mut self) {
+        *self = Self::new(self.gains);
+    }
+}
+
+/// Acro mode controller, commanding an angular velocity from the stick deflection on each axis
+/// through its rate curve and tracking it with a [Pid] per axis
+pub struct RateController<T> {
+    /// Roll, pitch and yaw controllers
+    pub axes: [Pid<T> ; 3],
+    /// Curves converting stick deflection into the angular velocity commanded on each axis in
+    /// rad/s, and the throttle curve
+    pub rates: Rates<T>,
+    setpoint: Vector3<T>,
+}
+
+impl<T> RateController<T>
+    where T: na::RealField + Copy
+{
+    pub fn new(gains: [PidGains<T> ; 3], rates: Rates<T>) -> Self {
+        Self {
+            axes: gains.map(Pid::new),
+            rates,
+            setpoint: Vector3::zeros(),
+        }
+    }
+
+    /// Drive the angular velocity to `setpoint` in rad/s, returning the roll, pitch and yaw demand
+    pub fn track(&mut self, setpoint: Vector3<T>, gyro: Vector3<T>, dt: T) -> Vector3<T> {
+        self.setpoint = setpoint;
+        Vector3::from_fn(|axis, _| self.axes[axis].update(setpoint[axis], gyro[axis], dt))
+    }
+}
+
+impl<T> Controller<T> for RateController<T>
+    where T: na::RealField + Copy
+{
+    fn update(&mut self, input: &ControlInput<T>) -> Vector3<T> {
+        self.track(self.rates.setpoint(&input.command), input.gyro, input.dt)
+    }
+
+    fn reset(&mut self) {
+        self.axes.iter_mut().for_each(Pid::reset);
+        self.setpoint = Vector3::zeros();
+    }
+
+    fn throttle(&self, throttle: T) -> T {
+        self.rates.throttle(throttle)
+    }
+
+    fn telemetry(&self) -> Option<ControlTelemetry<T>> {
+        let terms = self.axes.each_ref().map(Pid::terms);
+        Some(ControlTelemetry {
+            setpoint: self.setpoint,
+            p: Vector3::from_fn(|axis, _| terms[axis].p),
+            i: Vector3::from_fn(|axis, _| terms[axis].i),
+            d: Vector3::from_fn(|axis, _| terms[axis].d),
+            f: Vector3::from_fn(|axis, _| terms[axis].f),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::UnitQuaternion;
+
+    use super::*;
+    use crate::{control::RateCurve, interface::rc::RcCommand};
+
+    const DT: f32 = 0.000_25;
+
+    /// Simulate an axis whose angular acceleration is proportional to the controller output,
+    /// returning the measured rate after each step
+    fn simulate(pid: &mut Pid<f32>, setpoint: impl Fn(usize) -> f32, steps: usize, disturbance: f32) -> Vec<f32> {
+        let authority = 200f32;
+        let mut rate = 0f32;
+        (0..steps).map(|step| {
+            let output = pid.update(setpoint(step), rate, DT);
+            rate += (output * authority + disturbance) * DT;
+            rate
+        }).collect()
+    }
+
+    #[test]
+    fn test_proportional_step() {
+        let mut pid = Pid::new(PidGains::new(0.1f32, 0f32, 0f32));
+        assert_eq!(pid.update(2f32, 0f32, DT), 0.2f32);
+        assert_eq!(pid.update(2f32, 1f32, DT), 0.1f32);
+        assert_eq!(pid.update(40f32, 0f32, DT), 1f32);
+    }
+
+    #[test]
+    fn test_closed_loop_step_response() {
+        let mut gains = PidGains::new(0.3f32, 1f32, 0.002f32);
+        gains.d_cutoff = 100f32;
+        let mut pid = Pid::new(gains);
+
+        // A constant disturbance is only rejected by the integral term
+        let response = simulate(&mut pid, |_| 5f32, 8000, -20f32);
+        let overshoot = response.iter().copied().fold(0f32, f32::max);
+        let settled = response.iter().position(|&r| r > 4.75f32).unwrap();
+        assert!(overshoot < 5.5f32, "overshoot {overshoot}");
+        assert!((settled as f32 * DT) < 0.2f32, "settled after {settled} steps");
+        assert!((response.last().unwrap() - 5f32).abs() < 0.05f32);
+        assert!(pid.terms().i > 0f32);
+    }
+
+    #[test]
+    fn test_derivative_on_measurement() {
+        let mut pid = Pid::new(PidGains::new(0f32, 0f32, 0.01f32));
+        pid.update(0f32, 0f32, DT);
+        // A setpoint step doesn't kick the derivative
+        assert_eq!(pid.update(10f32, 0f32, DT), 0f32);
+        // A change in measurement is opposed
+        assert!(pid.update(10f32, 0.01f32, DT) < 0f32);
+    }
+
+    #[test]
+    fn test_integral_windup_limits() {
+        let mut gains = PidGains::new(0.01f32, 10f32, 0f32);
+        gains.i_limit = 0.3f32;
+        let mut pid = Pid::new(gains);
+        for _ in 0..10_000 {
+            pid.update(10f32, 0f32, DT);
+        }
+        assert_eq!(pid.terms().i, 0.3f32);
+
+        // With the output saturated the integral stops growing
+        let mut gains = PidGains::new(1f32, 10f32, 0f32);
+        gains.output_limit = 0.5f32;
+        let mut pid = Pid::new(gains);
+        for _ in 0..10_000 {
+            assert_eq!(pid.update(10f32, 0f32, DT), 0.5f32);
+        }
+        assert_eq!(pid.terms().i, 0f32);
+    }
+
+    #[test]
+    fn test_iterm_relax_and_feedforward() {
+        let mut gains = PidGains::new(0f32, 10f32, 0f32);
+        gains.ff = 0.01f32;
+        gains.relax_cutoff = 15f32;
+        gains.relax_threshold = 0.5f32;
+        let mut pid = Pid::new(gains);
+        pid.update(0f32, 0f32, DT);
+
+        // Ramping the setpoint at 1000 rad/s² is far above the relax threshold
+        for step in 1..100 {
+            pid.update(step as f32 * 1000f32 * DT, 0f32, DT);
+        }
+        let terms = pid.terms();
+        assert_eq!(terms.i, 0f32);
+        assert!((terms.f - 10f32).abs() < 1e-2, "{}", terms.f);
+
+        // Once the setpoint settles the integral accumulates again
+        for _ in 0..2000 {
+            pid.update(25f32, 0f32, DT);
+        }
+        assert!(pid.terms().i > 0.5f32);
+        assert_eq!(pid.terms().f, 0f32);
+    }
+
+    #[test]
+    fn test_rate_controller_reset() {
+        let gains = PidGains::new(0.1f32, 1f32, 0f32);
+        let mut rates = Rates::new(RateCurve::linear(573f32));
+        rates.axes[2].curve = RateCurve::linear(286.5f32);
+        let mut controller = RateController::new([gains ; 3], rates);
+        let input = ControlInput {
+            command: RcCommand { roll: 0.5, pitch: 0.0, yaw: -1.0, throttle: 0.5 },
+            attitude: UnitQuaternion::identity(),
+            gyro: Vector3::zeros(),
+            dt: DT,
+        };
+        let demand = controller.update(&input);
+        assert!((demand - Vector3::new(0.5f32, 0f32, -0.5f32)).norm() < 0.01);
+
+        let telemetry = controller.telemetry().unwrap();
+        assert!((telemetry.setpoint - Vector3::new(5f32, 0f32, -5f32)).norm() < 0.01);
+        assert!(telemetry.i.x > 0f32);
+
+        controller.reset();
+        assert_eq!(controller.telemetry().unwrap().i, Vector3::zeros());
+    }
+}
@@ -0,0 +1,286 @@
+//! Stick to angular velocity curves, following the rate models pilots know from other firmwares
+//! so their settings carry over unchanged
+
+use nalgebra::Vector3;
+use nalgebra as na;
+
+use crate::interface::rc::RcCommand;
+
+/// Largest angular velocity any curve commands in deg/s, matching Betaflight's rate limit
+pub const MAX_RATE: f64 = 1998f64;
+
+fn c<T: na::RealField + Copy>(value: f64) -> T {
+    na::convert::<f64, T>(value)
+}
+
+/// Curve mapping stick deflection to angular velocity on one axis. Parameters use the same
+/// units and scale the firmware they come from shows in its configurator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateCurve<T> {
+    /// Betaflight rates, where full stick gives `200 * rc_rate / (1 - super_rate)` deg/s
+    Betaflight { rc_rate: T, super_rate: T, expo: T },
+    /// Actual rates, setting the rate near centre stick and at full stick in deg/s directly
+    Actual { center: T, max: T, expo: T },
+    /// KISS rates, where full stick gives `200 * rc_rate / (1 - rate)` deg/s
+    Kiss { rc_rate: T, rate: T, curve: T },
+    /// Quick rates, with the rate near centre stick set by `rc_rate` like Betaflight rates and
+    /// the rate at full stick given in deg/s
+    Quick { rc_rate: T, max: T, expo: T },
+}
+
+impl<T> RateCurve<T>
+    where T: na::RealField + Copy
+{
+    /// Curve with the angular velocity proportional to stick deflection, reaching `max` deg/s
+    pub fn linear(max: T) -> Self {
+        Self::Actual { center: max, max, expo: T::zero() }
+    }
+
+    /// Get the angular velocity in deg/s for a stick deflection in [-1, 1]
+    pub fn rate(&self, stick: T) -> T {
+        let stick = stick.clamp(-T::one(), T::one());
+        let abs = stick.abs();
+        // Factor growing the rate towards full stick, limited as the firmwares do so it stays finite
+        let boost = |amount: T| T::one() / (T::one() - amount).clamp(c(0.01), T::one());
+
+        let rate = match *self {
+            Self::Betaflight { rc_rate, super_rate, expo } => {
+                // Above 2, each step of rc_rate adds much more, so high rates are reachable
+                let rc_rate = match rc_rate > c(2.0) {
+                    true => rc_rate + c::<T>(14.54) * (rc_rate - c(2.0)),
+                    false => rc_rate,
+                };
+                let curved = stick * abs.powi(3) * expo + stick * (T::one() - expo);
+                let rate = c::<T>(200.0) * rc_rate * curved;
+                match super_rate > T::zero() {
+                    true => rate * boost(abs * super_rate),
+                    false => rate,
+                }
+            },
+            Self::Actual { center, max, expo } => {
+                let curved = abs * (stick.powi(5) * expo + stick * (T::one() - expo));
+                stick * center + (max - center).max(T::zero()) * curved
+            },
+            Self::Kiss { rc_rate, rate, curve } => {
+                let curved = stick.powi(3) * curve + stick * (T::one() - curve);
+                c::<T>(200.0) * rc_rate * curved * boost(abs * rate)
+            },
+            Self::Quick { rc_rate, max, expo } => {
+                let center = c::<T>(200.0) * rc_rate;
+                let max = max.max(center);
+                let super_factor = (max / center - T::one()) / (max / center);
+                let curved = abs.powi(3) * expo + abs * (T::one() - expo);
+                stick * center * boost(curved * super_factor)
+            },
+        };
+
+        rate.clamp(-c::<T>(MAX_RATE), c(MAX_RATE))
+    }
+}
+
+impl<T> Default for RateCurve<T>
+    where T: na::RealField + Copy
+{
+    /// Betaflight's defaults, which are Actual rates of 70 deg/s at centre and 670 deg/s at full stick
+    fn default() -> Self {
+        Self::Actual { center: c(70.0), max: c(670.0), expo: c(0.54) }
+    }
+}
+
+/// Rate curve and deadband of one axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AxisRates<T> {
+    pub curve: RateCurve<T>,
+    /// Stick deflection around centre that is ignored. The rest of the stick's travel is
+    /// rescaled so full deflection still gives the full rate
+    pub deadband: T,
+}
+
+impl<T> AxisRates<T>
+    where T: na::RealField + Copy
+{
+    /// Get the angular velocity in rad/s for a stick deflection in [-1, 1]
+    pub fn rate(&self, stick: T) -> T {
+        let travel = (stick.abs() - self.deadband).max(T::zero()) / (T::one() - self.deadband);
+        self.curve.rate(travel.min(T::one()) * stick.signum()) * T::pi() / c(180.0)
+    }
+}
+
+impl<T> Default for AxisRates<T>
+    where T: na::RealField + Copy
+{
+    fn default() -> Self {
+        Self { curve: RateCurve::default(), deadband: T::zero() }
+    }
+}
+
+/// Throttle curve, flattening the response around `mid` as `expo` increases from 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThrottleCurve<T> {
+    pub mid: T,
+    pub expo: T,
+}
+
+impl<T> ThrottleCurve<T>
+    where T: na::RealField + Copy
+{
+    /// Get the throttle for a stick position in [0, 1]
+    pub fn apply(&self, throttle: T) -> T {
+        let offset = throttle.clamp(T::zero(), T::one()) - self.mid;
+        let span = match offset > T::zero() {
+            true => T::one() - self.mid,
+            false => self.mid,
+        };
+        if span <= T::zero() {
+            return self.mid
+        }
+
+        let curve = T::one() - self.expo + self.expo * (offset * offset) / (span * span);
+        (self.mid + offset * curve).clamp(T::zero(), T::one())
+    }
+}
+
+impl<T> Default for ThrottleCurve<T>
+    where T: na::RealField + Copy
+{
+    fn default() -> Self {
+        Self { mid: c(0.5), expo: T::zero() }
+    }
+}
+
+/// Rate curves for roll, pitch and yaw, and the throttle curve
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rates<T> {
+    pub axes: [AxisRates<T> ; 3],
+    pub throttle: ThrottleCurve<T>,
+}
+
+impl<T> Rates<T>
+    where T: na::RealField + Copy
+{
+    /// Rates with the same curve and no deadband on every axis
+    pub fn new(curve: RateCurve<T>) -> Self {
+        Self {
+            axes: [AxisRates { curve, deadband: T::zero() } ; 3],
+            throttle: ThrottleCurve::default(),
+        }
+    }
+
+    /// Get the throttle for a stick position in [0, 1] through the throttle curve
+    pub fn throttle(&self, throttle: T) -> T {
+        self.throttle.apply(throttle)
+    }
+
+    /// Get the roll, pitch and yaw angular velocity in rad/s commanded by the sticks
+    pub fn setpoint(&self, command: &RcCommand<T>) -> Vector3<T> {
+        Vector3::new(
+            self.axes[0].rate(command.roll),
+            self.axes[1].rate(command.pitch),
+            self.axes[2].rate(command.yaw),
+        )
+    }
+}
+
+impl<T> Default for Rates<T>
+    where T: na::RealField + Copy
+{
+    fn default() -> Self {
+        Self::new(RateCurve::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rates(curve: RateCurve<f32>, expected: &[(f32, f32)]) {
+        for &(stick, rate) in expected {
+            let actual = curve.rate(stick);
+            assert!((actual - rate).abs() < 0.05, "{curve:?} at {stick}: {actual} != {rate}");
+            assert_eq!(curve.rate(-stick), -actual);
+        }
+    }
+
+    // Reference values are from each firmware's own calculation with the same settings
+
+    #[test]
+    fn test_betaflight_rates() {
+        let curve = RateCurve::Betaflight { rc_rate: 1.0, super_rate: 0.7, expo: 0.0 };
+        assert_rates(curve, &[(0.0, 0.0), (0.25, 60.606), (0.5, 153.846), (1.0, 666.667)]);
+
+        let curve = RateCurve::Betaflight { rc_rate: 1.2, super_rate: 0.65, expo: 0.3 };
+        assert_rates(curve, &[(0.5, 131.111), (1.0, 685.714)]);
+
+        // Beyond 2, rc_rate grows faster, and the result is capped at the rate limit
+        let curve = RateCurve::Betaflight { rc_rate: 2.2, super_rate: 0.0, expo: 0.0 };
+        assert_rates(curve, &[(0.5, 510.8), (1.0, 1021.6)]);
+        let curve = RateCurve::Betaflight { rc_rate: 2.55, super_rate: 0.99, expo: 0.0 };
+        assert_rates(curve, &[(1.0, 1998.0)]);
+    }
+
+    #[test]
+    fn test_actual_rates() {
+        assert_rates(RateCurve::default(), &[(0.0, 0.0), (0.5, 109.063), (1.0, 670.0)]);
+
+        let curve = RateCurve::Actual { center: 200.0, max: 800.0, expo: 0.0 };
+        assert_rates(curve, &[(0.5, 250.0), (1.0, 800.0)]);
+        assert_rates(RateCurve::linear(400.0), &[(0.3, 120.0), (1.0, 400.0)]);
+    }
+
+    #[test]
+    fn test_kiss_rates() {
+        let curve = RateCurve::Kiss { rc_rate: 1.0, rate: 0.7, curve: 0.0 };
+        assert_rates(curve, &[(0.5, 153.846), (1.0, 666.667)]);
+
+        let curve = RateCurve::Kiss { rc_rate: 0.8, rate: 0.6, curve: 0.4 };
+        assert_rates(curve, &[(0.5, 80.0), (1.0, 400.0)]);
+    }
+
+    #[test]
+    fn test_quick_rates() {
+        let curve = RateCurve::Quick { rc_rate: 1.0, max: 670.0, expo: 0.0 };
+        assert_rates(curve, &[(0.5, 154.023), (1.0, 670.0)]);
+
+        let curve = RateCurve::Quick { rc_rate: 1.5, max: 1000.0, expo: 0.5 };
+        assert_rates(curve, &[(0.5, 192.0), (1.0, 1000.0)]);
+
+        // A maximum below the centre rate gives a linear curve
+        let curve = RateCurve::Quick { rc_rate: 1.0, max: 100.0, expo: 0.0 };
+        assert_rates(curve, &[(0.5, 100.0), (1.0, 200.0)]);
+    }
+
+    #[test]
+    fn test_deadband() {
+        let axis = AxisRates { curve: RateCurve::linear(500f32), deadband: 0.1 };
+        assert_eq!(axis.rate(0.05), 0.0);
+        assert_eq!(axis.rate(-0.1), 0.0);
+        assert!((axis.rate(0.55) - 250f32.to_radians()).abs() < 1e-4);
+        assert!((axis.rate(-1.0) + 500f32.to_radians()).abs() < 1e-4);
+
+        let mut rates = Rates::new(RateCurve::linear(360f32));
+        rates.axes[2].deadband = 0.5;
+        let command = RcCommand { roll: 0.5, pitch: -1.0, yaw: 0.25, throttle: 0.0 };
+        let expected = Vector3::new(180f32, -360f32, 0f32).map(f32::to_radians);
+        assert!((rates.setpoint(&command) - expected).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_throttle_curve() {
+        let linear = ThrottleCurve::default();
+        for throttle in [0f32, 0.2, 0.5, 0.9, 1.0] {
+            assert!((linear.apply(throttle) - throttle).abs() < 1e-6);
+        }
+
+        // Points on Betaflight's throttle lookup table for thr_mid 50 and thr_expo 100
+        let curve = ThrottleCurve { mid: 0.5f32, expo: 1.0 };
+        for (throttle, expected) in [(0.0, 0.0), (0.2, 0.392), (0.5, 0.5), (0.8, 0.608), (1.0, 1.0)] {
+            assert!((curve.apply(throttle) - expected).abs() < 1e-4, "{throttle}: {}", curve.apply(throttle));
+        }
+
+        // thr_mid 30 and thr_expo 40
+        let curve = ThrottleCurve { mid: 0.3f32, expo: 0.4 };
+        for (throttle, expected) in [(0.1, 0.1444), (0.6, 0.5020), (1.0, 1.0)] {
+            assert!((curve.apply(throttle) - expected).abs() < 1e-4, "{throttle}: {}", curve.apply(throttle));
+        }
+    }
+}
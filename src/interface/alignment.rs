@@ -0,0 +1,301 @@
+//! Rotation of sensor axes into the airframe's axes, for sensors that are not mounted
+//! with their axes matching the airframe
+
+use nalgebra::{Matrix3, Rotation3, Vector3};
+use nalgebra as na;
+
+use super::{
+    imu::{Accelerometer, Gyroscope, Imu, ImuSample, Sample},
+    mag::{MagCalibration, Magnetometer},
+    Sensor,
+};
+
+/// Standard sensor orientations, named the same way as in Betaflight.
+/// The flipped variants are mounted upside down
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    Cw0,
+    Cw90,
+    Cw180,
+    Cw270,
+    Cw0Flip,
+    Cw90Flip,
+    Cw180Flip,
+    Cw270Flip,
+}
+
+impl Rotation {
+    /// Get the matrix that maps a vector in the sensor's axes to the board's axes
+    pub fn matrix<T: na::RealField + Copy>(&self) -> Matrix3<T> {
+        let (o, l, n) = (T::zero(), T::one(), -T::one());
+        match self {
+            Self::Cw0 => Matrix3::new(
+                l, o, o,
+                o, l, o,
+                o, o, l,
+            ),
+            Self::Cw90 => Matrix3::new(
+                o, l, o,
+                n, o, o,
+                o, o, l,
+            ),
+            Self::Cw180 => Matrix3::new(
+                n, o, o,
+                o, n, o,
+                o, o, l,
+            ),
+            Self::Cw270 => Matrix3::new(
+                o, n, o,
+                l, o, o,
+                o, o, l,
+            ),
+            Self::Cw0Flip => Matrix3::new(
+                n, o, o,
+                o, l, o,
+                o, o, n,
+            ),
+            Self::Cw90Flip => Matrix3::new(
+                o, l, o,
+                l, o, o,
+                o, o, n,
+            ),
+            Self::Cw180Flip => Matrix3::new(
+                l, o, o,
+                o, n, o,
+                o, o, n,
+            ),
+            Self::Cw270Flip => Matrix3::new(
+                o, n, o,
+                n, o, o,
+                o, o, n,
+            ),
+        }
+    }
+}
+
+/// Rotation applied to every vector read from a sensor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alignment<T> {
+    matrix: Matrix3<T>,
+}
+
+impl<T: na::RealField + Copy> Alignment<T> {
+    /// Create an alignment from one of the standard sensor orientations
+    pub fn new(rotation: Rotation) -> Self {
+        Self { matrix: rotation.matrix() }
+    }
+
+    /// Create an alignment from roll, pitch, and yaw angles in radians.
+    /// Angles follow Betaflight's custom alignment convention, so a yaw of 90 degrees
+    /// is the same as [Rotation::Cw90] and a pitch of 180 degrees is [Rotation::Cw0Flip]
+    pub fn from_euler(roll: T, pitch: T, yaw: T) -> Self {
+        Self {
+            matrix: Rotation3::from_euler_angles(-roll, -pitch, -yaw).into_inner(),
+        }
+    }
+
+    /// Apply the board's alignment in the airframe after this alignment
+    pub fn with_board(self, board: Self) -> Self {
+        Self { matrix: board.matrix * self.matrix }
+    }
+
+    /// Get the matrix that maps a vector in the sensor's axes to the airframe's axes
+    pub const fn matrix(&self) -> &Matrix3<T> {
+        &self.matrix
+    }
+
+    /// Rotate a vector from the sensor's axes to the airframe's axes
+    pub fn apply(&self, v: Vector3<T>) -> Vector3<T> {
+        self.matrix * v
+    }
+
+    fn apply_sample(&self, sample: Sample<T>) -> Sample<T> {
+        Sample {
+            value: self.apply(sample.value),
+            timestamp: sample.timestamp,
+        }
+    }
+}
+
+impl<T: na::RealField + Copy> Default for Alignment<T> {
+    fn default() -> Self {
+        Self::new(Rotation::Cw0)
+    }
+}
+
+/// Wrapper around a sensor that rotates all readings into the airframe's axes
+pub struct Aligned<S, T> {
+    sensor: S,
+    alignment: Alignment<T>,
+}
+
+impl<S, T> Aligned<S, T> {
+    /// Wrap the given sensor, rotating its readings with `alignment`
+    pub const fn new(sensor: S, alignment: Alignment<T>) -> Self {
+        Self { sensor, alignment }
+    }
+
+    /// Get the alignment applied to readings
+    pub const fn alignment(&self) -> &Alignment<T> {
+        &self.alignment
+    }
+
+    /// Replace the alignment applied to readings
+    pub fn set_alignment(&mut self, alignment: Alignment<T>) {
+        self.alignment = alignment;
+    }
+
+    /// Get a reference to the wrapped sensor
+    pub const fn inner(&self) -> &S {
+        &self.sensor
+    }
+
+    /// Get a mutable reference to the wrapped sensor
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.sensor
+    }
+
+    /// Unwrap the sensor
+    pub fn into_inner(self) -> S {
+        self.sensor
+    }
+}
+
+impl<S: Sensor, T> Sensor for Aligned<S, T> {
+    type Error = S::Error;
+}
+
+impl<S, T> Accelerometer<T> for Aligned<S, T>
+where
+    S: Accelerometer<T>,
+    T: na::RealField + Copy,
+{
+    fn linear_acceleration(&mut self) -> Result<Sample<T>, Self::Error> {
+        Ok(self.alignment.apply_sample(self.sensor.linear_acceleration()?))
+    }
+
+    fn accel_ready(&mut self) -> Result<bool, Self::Error> {
+        self.sensor.accel_ready()
+    }
+
+    fn accel_sample_rate(&self) -> T {
+        self.sensor.accel_sample_rate()
+    }
+}
+
+impl<S, T> Gyroscope<T> for Aligned<S, T>
+where
+    S: Gyroscope<T>,
+    T: na::RealField + Copy,
+{
+    fn angular_velocity(&mut self) -> Result<Sample<T>, Self::Error> {
+        Ok(self.alignment.apply_sample(self.sensor.angular_velocity()?))
+    }
+
+    fn gyro_ready(&mut self) -> Result<bool, Self::Error> {
+        self.sensor.gyro_ready()
+    }
+
+    fn gyro_sample_rate(&self) -> T {
+        self.sensor.gyro_sample_rate()
+    }
+}
+
+impl<S, T> Imu<T> for Aligned<S, T>
+where
+    S: Imu<T>,
+    T: na::RealField + Copy,
+{
+    fn read(&mut self) -> Result<ImuSample<T>, Self::Error> {
+        let sample = self.sensor.read()?;
+        Ok(ImuSample {
+            accel: self.alignment.apply(sample.accel),
+            gyro: self.alignment.apply(sample.gyro),
+            timestamp: sample.timestamp,
+        })
+    }
+}
+
+/// Calibration is performed by the wrapped sensor in its own axes, before the alignment is applied
+impl<S, T> Magnetometer<T> for Aligned<S, T>
+where
+    S: Magnetometer<T>,
+    T: na::RealField + Copy,
+{
+    fn raw_field(&mut self) -> Result<Sample<T>, Self::Error> {
+        Ok(self.alignment.apply_sample(self.sensor.raw_field()?))
+    }
+
+    fn calibration(&self) -> MagCalibration<T> {
+        self.sensor.calibration()
+    }
+
+    fn set_calibration(&mut self, calibration: MagCalibration<T>) {
+        self.sensor.set_calibration(calibration);
+    }
+
+    fn magnetic_field(&mut self) -> Result<Sample<T>, Self::Error> {
+        Ok(self.alignment.apply_sample(self.sensor.magnetic_field()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+
+    use super::*;
+    use crate::interface::mock::{MockImu, MockMagnetometer};
+
+    #[test]
+    fn test_presets_match_euler() {
+        let presets = [
+            (Rotation::Cw0, 0f32, 0f32),
+            (Rotation::Cw90, 0f32, 90f32),
+            (Rotation::Cw180, 0f32, 180f32),
+            (Rotation::Cw270, 0f32, 270f32),
+            (Rotation::Cw0Flip, 180f32, 0f32),
+            (Rotation::Cw90Flip, 180f32, 90f32),
+            (Rotation::Cw180Flip, 180f32, 180f32),
+            (Rotation::Cw270Flip, 180f32, 270f32),
+        ];
+
+        for (preset, pitch, yaw) in presets {
+            let euler = Alignment::from_euler(0f32, pitch.to_radians(), yaw.to_radians());
+            let diff = euler.matrix() - Alignment::new(preset).matrix();
+            assert!(diff.amax() < 1e-6, "{preset:?} differs from euler angles by {diff}");
+        }
+    }
+
+    #[test]
+    fn test_aligned_imu() {
+        let mut imu = MockImu::new(1000f32);
+        imu.accel = Vector3::new(1f32, 0f32, 0f32);
+        imu.gyro = Vector3::new(0f32, 2f32, 0f32);
+
+        let mut aligned = Aligned::new(imu, Alignment::new(Rotation::Cw90));
+        let sample = aligned.read().unwrap();
+        assert_eq!(sample.accel, Vector3::new(0f32, -1f32, 0f32));
+        assert_eq!(sample.gyro, Vector3::new(2f32, 0f32, 0f32));
+        assert_eq!(aligned.linear_acceleration().unwrap().value, sample.accel);
+        assert_eq!(aligned.angular_velocity().unwrap().value, sample.gyro);
+    }
+
+    #[test]
+    fn test_board_alignment() {
+        let alignment = Alignment::new(Rotation::Cw90)
+            .with_board(Alignment::from_euler(0f32, 0f32, -PI / 2f32));
+        let v = alignment.apply(Vector3::new(1f32, 2f32, 3f32));
+        assert!((v - Vector3::new(1f32, 2f32, 3f32)).amax() < 1e-6);
+    }
+
+    #[test]
+    fn test_aligned_magnetometer_calibrates_first() {
+        let mut mag = MockMagnetometer::new(Vector3::new(15f32, 0f32, 0f32));
+        mag.calibration.hard_iron = Vector3::new(5f32, 0f32, 0f32);
+
+        let mut aligned = Aligned::new(mag, Alignment::new(Rotation::Cw180));
+        assert_eq!(aligned.magnetic_field().unwrap().value, Vector3::new(-10f32, 0f32, 0f32));
+        assert_eq!(aligned.raw_field().unwrap().value, Vector3::new(-15f32, 0f32, 0f32));
+    }
+}
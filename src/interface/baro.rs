@@ -0,0 +1,41 @@
+use nalgebra as na;
+
+use super::Sensor;
+
+/// Standard sea level pressure in Pa
+pub const STANDARD_SEA_LEVEL_PA: f32 = 101_325f32;
+
+pub trait Barometer<T>: Sensor {
+    /// Read the static air pressure in Pa
+    fn pressure(&mut self) -> Result<T, Self::Error>;
+
+    /// Read the temperature of the sensor in degrees Celsius
+    fn temperature(&mut self) -> Result<T, Self::Error>;
+
+    /// Read the pressure altitude in meters above the given sea level pressure in Pa
+    fn altitude(&mut self, sea_level: T) -> Result<T, Self::Error>
+    where T: na::RealField + Copy
+    {
+        Ok(pressure_altitude(self.pressure()?, sea_level))
+    }
+}
+
+/// Convert a pressure in Pa to an altitude in meters above the given sea level pressure
+/// using the international standard atmosphere model
+pub fn pressure_altitude<T: na::RealField + Copy>(pressure: T, sea_level: T) -> T {
+    let exponent = na::convert::<f64, T>(1f64 / 5.255f64);
+    na::convert::<f64, T>(44_330f64) * (T::one() - (pressure / sea_level).powf(exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_altitude() {
+        let sea_level = STANDARD_SEA_LEVEL_PA;
+        assert!(pressure_altitude(sea_level, sea_level).abs() < 1e-3);
+        assert!((pressure_altitude(89_874.6f32, sea_level) - 1000f32).abs() < 1f32);
+        assert!((pressure_altitude(54_019.9f32, sea_level) - 5000f32).abs() < 5f32);
+    }
+}
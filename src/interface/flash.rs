@@ -0,0 +1,35 @@
+/// Value of every byte in an erased region of NOR flash
+pub const ERASED: u8 = 0xFF;
+
+/// NOR flash memory, where programming can only clear bits and erasing sets a whole region
+/// back to [ERASED].
+///
+/// Program and erase operations return once started, and the memory can't be accessed again
+/// until [NorFlash::is_busy] returns false, so slow operations don't block the caller.
+pub trait NorFlash {
+    type Error: core::fmt::Debug;
+
+    /// Get the size of the memory in bytes
+    fn capacity(&self) -> u32;
+
+    /// Get the size of the smallest region that can be erased, which regions are aligned to
+    fn erase_size(&self) -> u32;
+
+    /// Get the size of the largest region that can be programmed in one operation.
+    /// A single program must not cross a page boundary
+    fn page_size(&self) -> u32;
+
+    /// Check if a program or erase operation is in progress
+    fn is_busy(&mut self) -> Result<bool, Self::Error>;
+
+    fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Start programming `data` at `address`, which must be within one page
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Start erasing the region containing `address`
+    fn erase(&mut self, address: u32) -> Result<(), Self::Error>;
+
+    /// Start erasing the entire memory
+    fn erase_all(&mut self) -> Result<(), Self::Error>;
+}
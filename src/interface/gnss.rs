@@ -0,0 +1,51 @@
+use nalgebra::Vector3;
+
+use super::Sensor;
+
+/// Quality of the position solution reported by a receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FixType {
+    NoFix,
+    Fix2D,
+    Fix3D,
+}
+
+/// Geodetic position reported by a receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GnssPosition {
+    /// Latitude in units of 1e-7 degrees
+    pub latitude: i32,
+    /// Longitude in units of 1e-7 degrees
+    pub longitude: i32,
+    /// Height above mean sea level in mm
+    pub altitude_msl: i32,
+}
+
+/// UTC time reported by a receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GnssTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millisecond: u16,
+}
+
+pub trait GnssReceiver<T>: Sensor {
+    /// Get the quality of the current solution
+    fn fix(&mut self) -> Result<FixType, Self::Error>;
+
+    /// Get the current position, if the receiver has a fix
+    fn position(&mut self) -> Result<Option<GnssPosition>, Self::Error>;
+
+    /// Get the current velocity in m/s in the north-east-down frame, if the receiver has a fix
+    fn velocity(&mut self) -> Result<Option<Vector3<T>>, Self::Error>;
+
+    /// Get the current UTC time, if the receiver has resolved it
+    fn time(&mut self) -> Result<Option<GnssTime>, Self::Error>;
+
+    /// Get the number of satellites used in the solution
+    fn satellites(&mut self) -> Result<u8, Self::Error>;
+}
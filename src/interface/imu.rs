@@ -1,7 +1,76 @@
-use nalgebra::VectorView3;
+use nalgebra::Vector3;
 
+use super::Sensor;
 
+/// Point in time that a sample was captured at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Ticks of the sensor's free-running clock, with the frequency of the clock in Hz
+    Sensor { ticks: u32, hz: u32 },
+    /// Ticks of the MCU clock, recorded when the sample was read
+    Mcu(u32),
+}
+
+impl Timestamp {
+    /// Get the number of whole microseconds represented by a sensor timestamp
+    pub const fn sensor_micros(&self) -> Option<u64> {
+        match self {
+            Self::Sensor { ticks, hz } => Some(*ticks as u64 * 1_000_000 / *hz as u64),
+            Self::Mcu(_) => None,
+        }
+    }
+}
+
+/// A single three-axis measurement with the time it was captured
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample<T> {
+    pub value: Vector3<T>,
+    pub timestamp: Timestamp,
+}
+
+/// Accelerometer and gyroscope measurements captured together
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImuSample<T> {
+    /// Linear acceleration in g
+    pub accel: Vector3<T>,
+    /// Angular velocity in rad/s
+    pub gyro: Vector3<T>,
+    pub timestamp: Timestamp,
+}
+
+pub trait Accelerometer<T>: Sensor {
+    /// Read the current linear acceleration in g
+    fn linear_acceleration(&mut self) -> Result<Sample<T>, Self::Error>;
+
+    /// Check if a new acceleration sample is available since the last read
+    fn accel_ready(&mut self) -> Result<bool, Self::Error>;
+
+    /// Get the rate that new acceleration samples are produced at in Hz
+    fn accel_sample_rate(&self) -> T;
+}
+
+pub trait Gyroscope<T>: Sensor {
+    /// Read the current angular velocity in rad/s
+    fn angular_velocity(&mut self) -> Result<Sample<T>, Self::Error>;
+
+    /// Check if a new angular velocity sample is available since the last read
+    fn gyro_ready(&mut self) -> Result<bool, Self::Error>;
+
+    /// Get the rate that new angular velocity samples are produced at in Hz
+    fn gyro_sample_rate(&self) -> T;
+}
 
-pub trait Accelerometer<T> {
-    fn linear_acceleration(&mut self) -> VectorView3<T>;
+/// Combined accelerometer and gyroscope that can be read in a single operation
+pub trait Imu<T>: Accelerometer<T> + Gyroscope<T> {
+    /// Read both the acceleration and angular velocity.
+    /// The default implementation performs two reads and uses the gyroscope timestamp
+    fn read(&mut self) -> Result<ImuSample<T>, Self::Error> {
+        let accel = self.linear_acceleration()?;
+        let gyro = self.angular_velocity()?;
+        Ok(ImuSample {
+            accel: accel.value,
+            gyro: gyro.value,
+            timestamp: gyro.timestamp,
+        })
+    }
 }
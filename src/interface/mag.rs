@@ -0,0 +1,51 @@
+use nalgebra::{Matrix3, Vector3};
+use nalgebra as na;
+
+use super::{imu::Sample, Sensor};
+
+/// Hard and soft iron corrections applied to raw magnetometer readings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MagCalibration<T> {
+    /// Offset subtracted from the raw field in µT
+    pub hard_iron: Vector3<T>,
+    /// Matrix applied to the offset-corrected field
+    pub soft_iron: Matrix3<T>,
+}
+
+impl<T: na::RealField + Copy> MagCalibration<T> {
+    /// Apply the calibration to a raw field reading
+    pub fn apply(&self, raw: Vector3<T>) -> Vector3<T> {
+        self.soft_iron * (raw - self.hard_iron)
+    }
+}
+
+impl<T: na::RealField + Copy> Default for MagCalibration<T> {
+    fn default() -> Self {
+        Self {
+            hard_iron: Vector3::zeros(),
+            soft_iron: Matrix3::identity(),
+        }
+    }
+}
+
+pub trait Magnetometer<T>: Sensor {
+    /// Read the uncalibrated magnetic field in µT
+    fn raw_field(&mut self) -> Result<Sample<T>, Self::Error>;
+
+    /// Get the calibration currently applied to readings
+    fn calibration(&self) -> MagCalibration<T>;
+
+    /// Replace the calibration applied to readings
+    fn set_calibration(&mut self, calibration: MagCalibration<T>);
+
+    /// Read the calibrated magnetic field in µT
+    fn magnetic_field(&mut self) -> Result<Sample<T>, Self::Error>
+    where T: na::RealField + Copy
+    {
+        let raw = self.raw_field()?;
+        Ok(Sample {
+            value: self.calibration().apply(raw.value),
+            timestamp: raw.timestamp,
+        })
+    }
+}
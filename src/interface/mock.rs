@@ -0,0 +1,480 @@
+//! Sensor implementations returning fixed values, a RAM-backed flash memory and a UART, for
+//! exercising code written against the `interface` and `embedded_io` traits without hardware
+
+use heapless::{Deque, Vec};
+use nalgebra::Vector3;
+use nalgebra as na;
+
+use super::{
+    baro::Barometer,
+    flash::{NorFlash, ERASED},
+    gnss::{FixType, GnssPosition, GnssReceiver, GnssTime},
+    imu::{Accelerometer, Gyroscope, Imu, Sample, Timestamp},
+    mag::{MagCalibration, Magnetometer},
+    Sensor,
+};
+
+/// Error returned by a mock sensor when it has been told to fail
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MockError;
+
+impl embedded_io::Error for MockError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+/// IMU reporting a fixed acceleration and angular velocity
+#[derive(Clone, Copy, Debug)]
+pub struct MockImu<T> {
+    pub accel: Vector3<T>,
+    pub gyro: Vector3<T>,
+    pub timestamp: Timestamp,
+    pub sample_rate: T,
+    pub fail: bool,
+}
+
+impl<T: na::RealField + Copy> MockImu<T> {
+    /// Create a level, stationary IMU sampling at the given rate
+    pub fn new(sample_rate: T) -> Self {
+        Self {
+            accel: Vector3::z(),
+            gyro: Vector3::zeros(),
+            timestamp: Timestamp::Mcu(0),
+            sample_rate,
+            fail: false,
+        }
+    }
+}
+
+impl<T> Sensor for MockImu<T> {
+    type Error = MockError;
+}
+
+impl<T: Copy> Accelerometer<T> for MockImu<T> {
+    fn linear_acceleration(&mut self) -> Result<Sample<T>, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(Sample { value: self.accel, timestamp: self.timestamp }),
+        }
+    }
+
+    fn accel_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.fail)
+    }
+
+    fn accel_sample_rate(&self) -> T {
+        self.sample_rate
+    }
+}
+
+impl<T: Copy> Gyroscope<T> for MockImu<T> {
+    fn angular_velocity(&mut self) -> Result<Sample<T>, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(Sample { value: self.gyro, timestamp: self.timestamp }),
+        }
+    }
+
+    fn gyro_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.fail)
+    }
+
+    fn gyro_sample_rate(&self) -> T {
+        self.sample_rate
+    }
+}
+
+impl<T: Copy> Imu<T> for MockImu<T> {}
+
+/// Barometer reporting a fixed pressure and temperature
+#[derive(Clone, Copy, Debug)]
+pub struct MockBarometer<T> {
+    pub pressure: T,
+    pub temperature: T,
+    pub fail: bool,
+}
+
+impl<T> MockBarometer<T> {
+    pub const fn new(pressure: T, temperature: T) -> Self {
+        Self { pressure, temperature, fail: false }
+    }
+}
+
+impl<T> Sensor for MockBarometer<T> {
+    type Error = MockError;
+}
+
+impl<T: Copy> Barometer<T> for MockBarometer<T> {
+    fn pressure(&mut self) -> Result<T, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(self.pressure),
+        }
+    }
+
+    fn temperature(&mut self) -> Result<T, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(self.temperature),
+        }
+    }
+}
+
+/// Magnetometer reporting a fixed raw field
+#[derive(Clone, Copy, Debug)]
+pub struct MockMagnetometer<T> {
+    pub field: Vector3<T>,
+    pub calibration: MagCalibration<T>,
+    pub fail: bool,
+}
+
+impl<T: na::RealField + Copy> MockMagnetometer<T> {
+    pub fn new(field: Vector3<T>) -> Self {
+        Self { field, calibration: MagCalibration::default(), fail: false }
+    }
+}
+
+impl<T> Sensor for MockMagnetometer<T> {
+    type Error = MockError;
+}
+
+impl<T: Copy> Magnetometer<T> for MockMagnetometer<T> {
+    fn raw_field(&mut self) -> Result<Sample<T>, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(Sample { value: self.field, timestamp: Timestamp::Mcu(0) }),
+        }
+    }
+
+    fn calibration(&self) -> MagCalibration<T> {
+        self.calibration
+    }
+
+    fn set_calibration(&mut self, calibration: MagCalibration<T>) {
+        self.calibration = calibration;
+    }
+}
+
+/// GNSS receiver reporting a fixed solution
+#[derive(Clone, Copy, Debug)]
+pub struct MockGnss<T> {
+    pub fix: FixType,
+    pub position: GnssPosition,
+    pub velocity: Vector3<T>,
+    pub time: Option<GnssTime>,
+    pub satellites: u8,
+    pub fail: bool,
+}
+
+impl<T: na::RealField + Copy> MockGnss<T> {
+    /// Create a receiver with no fix and no satellites in view
+    pub fn new() -> Self {
+        Self {
+            fix: FixType::NoFix,
+            position: GnssPosition { latitude: 0, longitude: 0, altitude_msl: 0 },
+            velocity: Vector3::zeros(),
+            time: None,
+            satellites: 0,
+            fail: false,
+        }
+    }
+}
+
+impl<T: na::RealField + Copy> Default for MockGnss<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Sensor for MockGnss<T> {
+    type Error = MockError;
+}
+
+impl<T: Copy> GnssReceiver<T> for MockGnss<T> {
+    fn fix(&mut self) -> Result<FixType, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(self.fix),
+        }
+    }
+
+    fn position(&mut self) -> Result<Option<GnssPosition>, Self::Error> {
+        Ok((self.fix()? != FixType::NoFix).then_some(self.position))
+    }
+
+    fn velocity(&mut self) -> Result<Option<Vector3<T>>, Self::Error> {
+        Ok((self.fix()? != FixType::NoFix).then_some(self.velocity))
+    }
+
+    fn time(&mut self) -> Result<Option<GnssTime>, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(self.time),
+        }
+    }
+
+    fn satellites(&mut self) -> Result<u8, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(self.satellites),
+        }
+    }
+}
+
+/// Error returned by [MockFlash] when used in a way the real memory would not allow
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MockFlashError {
+    /// An operation was started while the previous one was in progress
+    Busy,
+    OutOfRange,
+    /// A program crossed a page boundary
+    PageBoundary,
+    /// The memory was told to fail
+    Fail,
+}
+
+/// NOR flash memory simulated in RAM, which stays busy after each program or erase for a
+/// configurable number of calls to [NorFlash::is_busy]
+pub struct MockFlash<const N: usize> {
+    pub memory: [u8 ; N],
+    pub erase_size: u32,
+    pub page_size: u32,
+    /// Number of busy polls that a program or erase takes
+    pub latency: u32,
+    pub fail: bool,
+    busy: u32,
+}
+
+impl<const N: usize> MockFlash<N> {
+    /// Create an erased memory with 4 KiB erase regions and 256 byte pages
+    pub const fn new() -> Self {
+        Self {
+            memory: [ERASED ; N],
+            erase_size: 4096,
+            page_size: 256,
+            latency: 0,
+            fail: false,
+            busy: 0,
+        }
+    }
+
+    /// Check that the memory is idle and the range is valid
+    fn check(&self, address: u32, len: usize) -> Result<(), MockFlashError> {
+        if self.fail {
+            return Err(MockFlashError::Fail)
+        }
+        if self.busy > 0 {
+            return Err(MockFlashError::Busy)
+        }
+        if address as usize + len > N {
+            return Err(MockFlashError::OutOfRange)
+        }
+        Ok(())
+    }
+
+    /// Start an operation on a range, making the memory busy
+    fn start(&mut self, address: u32, len: usize) -> Result<usize, MockFlashError> {
+        self.check(address, len)?;
+        self.busy = self.latency;
+        Ok(address as usize)
+    }
+}
+
+impl<const N: usize> Default for MockFlash<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> NorFlash for MockFlash<N> {
+    type Error = MockFlashError;
+
+    fn capacity(&self) -> u32 {
+        N as u32
+    }
+
+    fn erase_size(&self) -> u32 {
+        self.erase_size
+    }
+
+    fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    fn is_busy(&mut self) -> Result<bool, Self::Error> {
+        if self.fail {
+            return Err(MockFlashError::Fail)
+        }
+
+        self.busy = self.busy.saturating_sub(1);
+        Ok(self.busy > 0)
+    }
+
+    fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.check(address, buf.len())?;
+        let start = address as usize;
+        buf.copy_from_slice(&self.memory[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error> {
+        if (address % self.page_size) as usize + data.len() > self.page_size as usize {
+            return Err(MockFlashError::PageBoundary)
+        }
+
+        let start = self.start(address, data.len())?;
+        // Programming can only clear bits
+        for (byte, data) in self.memory[start..].iter_mut().zip(data) {
+            *byte &= data;
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, address: u32) -> Result<(), Self::Error> {
+        let address = address - address % self.erase_size;
+        let start = self.start(address, self.erase_size as usize)?;
+        self.memory[start..start + self.erase_size as usize].fill(ERASED);
+        Ok(())
+    }
+
+    fn erase_all(&mut self) -> Result<(), Self::Error> {
+        self.start(0, N)?;
+        self.memory.fill(ERASED);
+        Ok(())
+    }
+}
+
+/// UART with FIFOs of `N` bytes, reading the bytes given to [receive](Self::receive) and
+/// collecting the bytes written to it in `tx`
+pub struct MockUart<const N: usize> {
+    pub rx: Deque<u8, N>,
+    pub tx: Vec<u8, N>,
+    pub fail: bool,
+}
+
+impl<const N: usize> MockUart<N> {
+    pub const fn new() -> Self {
+        Self { rx: Deque::new(), tx: Vec::new(), fail: false }
+    }
+
+    /// Queue bytes to be read, dropping any that don't fit as an overrun would
+    pub fn receive(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let _ = self.rx.push_back(byte);
+        }
+    }
+}
+
+impl<const N: usize> Default for MockUart<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> embedded_io::ErrorType for MockUart<N> {
+    type Error = MockError;
+}
+
+impl<const N: usize> embedded_io::Read for MockUart<N> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.fail {
+            return Err(MockError)
+        }
+
+        let mut len = 0;
+        while let (Some(b), Some(byte)) = (buf.get_mut(len), self.rx.front()) {
+            *b = *byte;
+            self.rx.pop_front();
+            len += 1;
+        }
+
+        Ok(len)
+    }
+}
+
+impl<const N: usize> embedded_io::ReadReady for MockUart<N> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        match self.fail {
+            true => Err(MockError),
+            false => Ok(!self.rx.is_empty()),
+        }
+    }
+}
+
+impl<const N: usize> embedded_io::Write for MockUart<N> {
+    /// Write the bytes that fit in `tx`, dropping the rest
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.fail {
+            return Err(MockError)
+        }
+
+        let len = buf.len().min(N - self.tx.len());
+        let _ = self.tx.extend_from_slice(&buf[..len]);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Matrix3;
+
+    use super::*;
+    use crate::interface::baro::STANDARD_SEA_LEVEL_PA;
+
+    #[test]
+    fn test_mock_barometer_altitude() {
+        let mut baro = MockBarometer::new(89_874.6f32, 15f32);
+        let altitude = baro.altitude(STANDARD_SEA_LEVEL_PA).unwrap();
+        assert!((altitude - 1000f32).abs() < 1f32);
+
+        baro.fail = true;
+        assert_eq!(baro.altitude(STANDARD_SEA_LEVEL_PA), Err(MockError));
+    }
+
+    #[test]
+    fn test_mock_magnetometer_calibration() {
+        let mut mag = MockMagnetometer::new(Vector3::new(30f32, -10f32, 45f32));
+        mag.set_calibration(MagCalibration {
+            hard_iron: Vector3::new(10f32, -10f32, 5f32),
+            soft_iron: Matrix3::from_diagonal(&Vector3::new(0.5f32, 1f32, 2f32)),
+        });
+
+        assert_eq!(mag.raw_field().unwrap().value, Vector3::new(30f32, -10f32, 45f32));
+        assert_eq!(mag.magnetic_field().unwrap().value, Vector3::new(10f32, 0f32, 80f32));
+    }
+
+    #[test]
+    fn test_mock_gnss_without_fix() {
+        let mut gnss = MockGnss::<f32>::new();
+        assert_eq!(gnss.position(), Ok(None));
+
+        gnss.fix = FixType::Fix3D;
+        gnss.satellites = 12;
+        assert_eq!(gnss.position().unwrap().map(|p| p.latitude), Some(0));
+        assert_eq!(gnss.satellites(), Ok(12));
+    }
+
+    #[test]
+    fn test_mock_flash_behaves_like_nor() {
+        let mut flash = MockFlash::<8192>::new();
+        flash.latency = 2;
+        flash.program(0, &[0xF0, 0x0F]).unwrap();
+        assert_eq!(flash.program(2, &[0]), Err(MockFlashError::Busy));
+        assert_eq!(flash.is_busy(), Ok(true));
+        assert_eq!(flash.is_busy(), Ok(false));
+
+        flash.program(0, &[0x3C]).unwrap();
+        while flash.is_busy().unwrap() {}
+        assert_eq!(&flash.memory[..3], &[0x30, 0x0F, 0xFF]);
+        assert_eq!(flash.program(255, &[0, 0]), Err(MockFlashError::PageBoundary));
+
+        flash.erase(100).unwrap();
+        while flash.is_busy().unwrap() {}
+        assert!(flash.memory[..4096].iter().all(|&b| b == ERASED));
+    }
+}
@@ -1 +1,15 @@
 pub mod imu;
+pub mod baro;
+pub mod mag;
+pub mod gnss;
+pub mod alignment;
+pub mod rc;
+pub mod motor;
+pub mod flash;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+/// Common error type shared by all of a sensor's interface traits
+pub trait Sensor {
+    type Error: core::fmt::Debug;
+}
@@ -0,0 +1,16 @@
+/// Output stage driving the ESCs of all motors
+pub trait MotorOutput {
+    type Error: core::fmt::Debug;
+
+    /// Set the throttle of each motor in [0, 1]
+    fn set_throttle(&mut self, throttle: &[f32]) -> Result<(), Self::Error>;
+
+    /// Stop all motors, used while disarmed
+    fn stop(&mut self) -> Result<(), Self::Error>;
+
+    /// Reverse the spin direction of every motor, or restore it, for turtle mode. Returns false
+    /// if the output can't reverse the motors
+    fn set_reversed(&mut self, _reversed: bool) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+}
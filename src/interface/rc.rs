@@ -0,0 +1,47 @@
+use bitflags::bitflags;
+
+use crate::Micros;
+
+/// Pilot stick positions after decoding and mapping receiver channels
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RcCommand<T> {
+    /// Roll stick position in [-1, 1], positive to the right
+    pub roll: T,
+    /// Pitch stick position in [-1, 1], positive forward
+    pub pitch: T,
+    /// Yaw stick position in [-1, 1], positive to the right
+    pub yaw: T,
+    /// Throttle stick position in [0, 1]
+    pub throttle: T,
+}
+
+bitflags! {
+    /// Modes switched on by the pilot
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Modes: u32 {
+        /// Drive the motors, armed when switched on and disarmed when switched off
+        const ARM = 1 << 0;
+        /// Self-level, with the sticks commanding a tilt angle
+        const ANGLE = 1 << 1;
+        /// Self-level near the centre of the sticks only
+        const HORIZON = 1 << 2;
+        /// Sound the beeper to find the vehicle
+        const BEEPER = 1 << 3;
+        /// Record to the blackbox log
+        const BLACKBOX = 1 << 4;
+        /// Reverse the motors to flip the vehicle back over after a crash
+        const TURTLE = 1 << 5;
+    }
+}
+
+/// Source of pilot commands for the flight controller
+pub trait RcInput<T> {
+    /// Get the latest pilot command, or `None` if the link to the transmitter has been lost
+    fn command(&mut self, now: Micros) -> Option<RcCommand<T>>;
+
+    /// Get the modes switched on with the latest command, or `None` if this input has no mode
+    /// switches or the link has been lost
+    fn modes(&self) -> Option<Modes> {
+        None
+    }
+}
@@ -1,6 +1,11 @@
 #![cfg_attr(not(test), no_std)]
 
-use interface::imu::Accelerometer;
+use ahrs::AttitudeEstimator;
+use control::{ControlInput, Controller};
+use interface::{imu::{Imu, ImuSample}, motor::MotorOutput, rc::{Modes, RcCommand, RcInput}};
+use mixer::Mixer;
+use nalgebra::{UnitQuaternion, Vector3};
+use state::{ArmingDisabled, Event, FlightState, StateHooks, StateMachine, TransitionError};
 
 
 pub mod interface;
@@ -9,8 +14,737 @@ pub mod state;
 pub mod log;
 pub mod ahrs;
 pub mod math;
+pub mod control;
+pub mod mixer;
+pub mod rc;
 
-pub struct FlightController<L: log::Logger, A: Accelerometer<f32>> {
+/// Time in microseconds from a free-running MCU timer, wrapping on overflow
+pub type Micros = u32;
+
+/// Maximum number of motor outputs the flight controller can drive
+pub const MAX_MOTORS: usize = 8;
+
+/// Highest throttle stick position that the vehicle may be armed at
+pub const ARM_THROTTLE_MAX: f32 = 0.05;
+
+/// Cosine of the largest tilt from level, 25 degrees, that the vehicle may be armed at
+pub const ARM_TILT_COS_MIN: f32 = 0.906_307_8;
+
+/// Core of the flight stack, running the estimator, control law and mixer on each step
+pub struct FlightController<L, I, E, R, C, M, O, H = ()>
+where
+    L: log::Logger,
+    I: Imu<f32>,
+    E: AttitudeEstimator<f32>,
+    R: RcInput<f32>,
+    C: Controller<f32>,
+    M: Mixer<f32>,
+    O: MotorOutput,
+    H: StateHooks,
+{
     log: L,
-    accel: A,
+    imu: I,
+    estimator: E,
+    rc: R,
+    controller: C,
+    mixer: M,
+    motors: O,
+    state: StateMachine<H>,
+    last_step: Option<Micros>,
+    sample: Option<ImuSample<f32>>,
+    command: Option<RcCommand<f32>>,
+    modes: Option<Modes>,
+    /// Modes last reported to the hooks, kept while the RC link is lost
+    reported_modes: Modes,
+    outputs: [f32 ; MAX_MOTORS],
+}
+
+impl<L, I, E, R, C, M, O, H> FlightController<L, I, E, R, C, M, O, H>
+where
+    L: log::Logger,
+    I: Imu<f32>,
+    E: AttitudeEstimator<f32>,
+    R: RcInput<f32>,
+    C: Controller<f32>,
+    M: Mixer<f32>,
+    O: MotorOutput,
+    H: StateHooks,
+{
+    /// Run one control cycle: read the IMU, update the attitude estimate, read pilot commands,
+    /// and drive the motors if armed. `now` is the current time in microseconds
+    pub fn step(&mut self, now: Micros) -> Result<(), StepError<I::Error, O::Error>> {
+        let dt = match self.last_step {
+            Some(last) => now.wrapping_sub(last) as f32 / 1_000_000f32,
+            None => 1f32 / self.imu.gyro_sample_rate(),
+        };
+        self.last_step = Some(now);
+
+        // Without a gyro the control loop is blind, so stop the motors rather than leave them at
+        // their last outputs
+        let sample = match self.imu.read() {
+            Ok(sample) => sample,
+            Err(e) => {
+                self.state.set_arming_disabled(ArmingDisabled::NO_GYRO, true);
+                if self.is_armed() {
+                    let _ = self.disarm();
+                }
+                let _ = self.motors.stop();
+                return Err(StepError::Imu(e))
+            },
+        };
+        self.state.set_arming_disabled(ArmingDisabled::NO_GYRO, false);
+        self.estimator.update(sample.gyro, sample.accel, dt);
+        self.sample = Some(sample);
+
+        self.command = self.rc.command(now);
+        match self.command {
+            Some(command) => {
+                if self.state.arming_disabled().contains(ArmingDisabled::RX_LOSS) {
+                    let _ = self.handle(Event::LinkRecovered);
+                }
+                self.state.set_arming_disabled(ArmingDisabled::THROTTLE, command.throttle > ARM_THROTTLE_MAX);
+            },
+            None => {
+                let _ = self.handle(Event::LinkLost);
+            },
+        }
+
+        let up = self.estimator.attitude() * Vector3::z();
+        self.state.set_arming_disabled(ArmingDisabled::ANGLE, up.z < ARM_TILT_COS_MIN);
+
+        match (self.command, self.rc.modes()) {
+            (Some(_), Some(modes)) => self.update_modes(modes),
+            _ => self.modes = None,
+        }
+
+        let turtle = self.state.state() == FlightState::Turtle;
+        let (Some(command), true) = (self.command, self.is_armed() || turtle) else {
+            return self.motors.stop().map_err(StepError::Motor)
+        };
+
+        let outputs = &mut self.outputs[..self.mixer.motor_count().min(MAX_MOTORS)];
+        if turtle {
+            self.mixer.mix_turtle(Vector3::new(command.roll, command.pitch, command.yaw), outputs);
+        } else {
+            let demand = self.controller.update(&ControlInput {
+                command,
+                attitude: self.estimator.attitude(),
+                gyro: sample.gyro,
+                dt,
+            });
+            self.mixer.mix(demand, self.controller.throttle(command.throttle), outputs);
+        }
+        self.motors.set_throttle(outputs).map_err(StepError::Motor)
+    }
+
+    /// Arm, in turtle mode if it's switched on, and disarm on the edges of the arm switch, select
+    /// the level mode, and report changed modes to the hooks
+    fn update_modes(&mut self, modes: Modes) {
+        let arm = modes.contains(Modes::ARM);
+        let request = match modes.contains(Modes::TURTLE) {
+            true => Event::TurtleRequested,
+            false => Event::ArmRequested,
+        };
+        match (self.modes.map(|modes| modes.contains(Modes::ARM)), arm) {
+            // A switch already on when the link comes up, or a refused request, must be cycled
+            (None, true) => self.state.set_arming_disabled(ArmingDisabled::ARM_SWITCH, true),
+            (Some(false), true) if !self.is_armed() && self.handle(request).is_err() => {
+                self.state.set_arming_disabled(ArmingDisabled::ARM_SWITCH, true);
+            },
+            (Some(true), false) if self.is_armed() || self.state.state() == FlightState::Turtle => {
+                let _ = self.disarm();
+            },
+            _ => {},
+        }
+        if !arm {
+            self.state.set_arming_disabled(ArmingDisabled::ARM_SWITCH, false);
+        }
+
+        self.controller.set_level_mode(modes.into());
+        self.modes = Some(modes);
+        if modes != self.reported_modes {
+            self.state.hooks_mut().on_modes_changed(self.reported_modes, modes);
+            self.reported_modes = modes;
+        }
+    }
+
+    /// Pass an event to the flight state machine, logging any transition it causes
+    pub fn handle(&mut self, event: Event) -> Result<FlightState, TransitionError> {
+        let prev = self.state.state();
+        let next = match self.state.handle(event) {
+            Ok(next) => next,
+            Err(e) => {
+                if let TransitionError::ArmingDisabled(reasons) = e {
+                    warn!(self.log, "Arming disabled: {reasons:?}");
+                }
+                return Err(e)
+            }
+        };
+
+        if prev != next {
+            info!(self.log, "{prev:?} -> {next:?} on {event:?}");
+            if prev.is_armed() != next.is_armed() {
+                self.controller.reset();
+                self.outputs = [0f32 ; MAX_MOTORS];
+            }
+
+            // The motors keep spinning the wrong way unless restored after turtle mode
+            if next == FlightState::Turtle {
+                self.outputs = [0f32 ; MAX_MOTORS];
+                if !matches!(self.motors.set_reversed(true), Ok(true)) {
+                    warn!(self.log, "Motors can't be reversed for turtle mode");
+                    let _ = self.handle(Event::DisarmRequested);
+                    return Err(TransitionError::MotorsNotReversible)
+                }
+            } else if prev == FlightState::Turtle {
+                self.outputs = [0f32 ; MAX_MOTORS];
+                if let Err(e) = self.motors.set_reversed(false) {
+                    warn!(self.log, "Failed to restore motor direction: {e:?}");
+                }
+            }
+        }
+
+        Ok(next)
+    }
+
+    /// Request that the vehicle arms, starting to drive the motors on the next step
+    pub fn arm(&mut self) -> Result<(), TransitionError> {
+        self.handle(Event::ArmRequested).map(|_| ())
+    }
+
+    /// Request that the vehicle arms in turtle mode, reversing the motors so the sticks can flip
+    /// it back over. Fails with [TransitionError::MotorsNotReversible], leaving the vehicle
+    /// disarmed, if the motors can't be reversed
+    pub fn turtle(&mut self) -> Result<(), TransitionError> {
+        self.handle(Event::TurtleRequested).map(|_| ())
+    }
+
+    /// Request that the vehicle disarms, stopping the motors on the next step
+    pub fn disarm(&mut self) -> Result<(), TransitionError> {
+        self.handle(Event::DisarmRequested).map(|_| ())
+    }
+
+    pub const fn is_armed(&self) -> bool {
+        self.state.state().is_armed()
+    }
+
+    /// Get the flight state machine, to query the state and reasons preventing arming
+    pub const fn state(&self) -> &StateMachine<H> {
+        &self.state
+    }
+
+    /// Get the flight state machine, to set additional reasons preventing arming
+    pub fn state_mut(&mut self) -> &mut StateMachine<H> {
+        &mut self.state
+    }
+
+    /// Get the current attitude estimate
+    pub fn attitude(&self) -> UnitQuaternion<f32> {
+        self.estimator.attitude()
+    }
+
+    /// Get the pilot command read on the last step, or `None` if the RC link was lost
+    pub const fn command(&self) -> Option<RcCommand<f32>> {
+        self.command
+    }
+
+    /// Get the modes switched on by the pilot on the last step, or `None` if the RC link was lost
+    /// or has no mode switches
+    pub const fn modes(&self) -> Option<Modes> {
+        self.modes
+    }
+
+    /// Whether the beeper should sound, because the pilot switched it on or to help find a
+    /// vehicle that lost its RC link while armed
+    pub fn beeper(&self) -> bool {
+        self.modes.is_some_and(|modes| modes.contains(Modes::BEEPER))
+            || self.state.state() == FlightState::Failsafe
+    }
+
+    /// Get the motor outputs computed on the last step
+    pub fn outputs(&self) -> &[f32] {
+        &self.outputs[..self.mixer.motor_count().min(MAX_MOTORS)]
+    }
+
+    /// Capture the values from the last step for the blackbox recorder,
+    /// or `None` if no step has read the IMU yet
+    pub fn snapshot(&self) -> Option<log::blackbox::Snapshot> {
+        let (Some(time), Some(sample)) = (self.last_step, self.sample) else { return None };
+        Some(log::blackbox::Snapshot {
+            time,
+            gyro: sample.gyro,
+            accel: sample.accel,
+            attitude: self.estimator.attitude(),
+            command: self.command.unwrap_or_default(),
+            control: self.controller.telemetry().unwrap_or_default(),
+            motors: self.outputs,
+        })
+    }
+
+    /// Get the logger used to report events
+    pub fn log(&mut self) -> &mut L {
+        &mut self.log
+    }
+}
+
+/// Builder for a [FlightController], setting each of its components in any order
+pub struct FlightControllerBuilder<L, I, E, R, C, M, O, H = ()> {
+    log: L,
+    imu: I,
+    estimator: E,
+    rc: R,
+    controller: C,
+    mixer: M,
+    motors: O,
+    hooks: H,
+}
+
+impl FlightControllerBuilder<(), (), (), (), (), (), ()> {
+    /// Create a builder with no components set
+    pub const fn new() -> Self {
+        Self {
+            log: (),
+            imu: (),
+            estimator: (),
+            rc: (),
+            controller: (),
+            mixer: (),
+            motors: (),
+            hooks: (),
+        }
+    }
+}
+
+impl Default for FlightControllerBuilder<(), (), (), (), (), (), ()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L, I, E, R, C, M, O, H> FlightControllerBuilder<L, I, E, R, C, M, O, H> {
+    pub fn logger<L2: log::Logger>(self, log: L2) -> FlightControllerBuilder<L2, I, E, R, C, M, O, H> {
+        let Self { imu, estimator, rc, controller, mixer, motors, hooks, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+
+    pub fn imu<I2: Imu<f32>>(self, imu: I2) -> FlightControllerBuilder<L, I2, E, R, C, M, O, H> {
+        let Self { log, estimator, rc, controller, mixer, motors, hooks, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+
+    pub fn estimator<E2: AttitudeEstimator<f32>>(self, estimator: E2) -> FlightControllerBuilder<L, I, E2, R, C, M, O, H> {
+        let Self { log, imu, rc, controller, mixer, motors, hooks, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+
+    pub fn rc<R2: RcInput<f32>>(self, rc: R2) -> FlightControllerBuilder<L, I, E, R2, C, M, O, H> {
+        let Self { log, imu, estimator, controller, mixer, motors, hooks, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+
+    pub fn controller<C2: Controller<f32>>(self, controller: C2) -> FlightControllerBuilder<L, I, E, R, C2, M, O, H> {
+        let Self { log, imu, estimator, rc, mixer, motors, hooks, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+
+    pub fn mixer<M2: Mixer<f32>>(self, mixer: M2) -> FlightControllerBuilder<L, I, E, R, C, M2, O, H> {
+        let Self { log, imu, estimator, rc, controller, motors, hooks, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+
+    pub fn motors<O2: MotorOutput>(self, motors: O2) -> FlightControllerBuilder<L, I, E, R, C, M, O2, H> {
+        let Self { log, imu, estimator, rc, controller, mixer, hooks, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+
+    /// Set the callbacks invoked by the flight state machine
+    pub fn hooks<H2: StateHooks>(self, hooks: H2) -> FlightControllerBuilder<L, I, E, R, C, M, O, H2> {
+        let Self { log, imu, estimator, rc, controller, mixer, motors, .. } = self;
+        FlightControllerBuilder { log, imu, estimator, rc, controller, mixer, motors, hooks }
+    }
+}
+
+impl<L, I, E, R, C, M, O, H> FlightControllerBuilder<L, I, E, R, C, M, O, H>
+where
+    L: log::Logger,
+    I: Imu<f32>,
+    E: AttitudeEstimator<f32>,
+    R: RcInput<f32>,
+    C: Controller<f32>,
+    M: Mixer<f32>,
+    O: MotorOutput,
+    H: StateHooks,
+{
+    /// Create a flight controller in the booting state from the configured components
+    pub fn build(self) -> FlightController<L, I, E, R, C, M, O, H> {
+        FlightController {
+            log: self.log,
+            imu: self.imu,
+            estimator: self.estimator,
+            rc: self.rc,
+            controller: self.controller,
+            mixer: self.mixer,
+            motors: self.motors,
+            state: StateMachine::new(self.hooks),
+            last_step: None,
+            sample: None,
+            command: None,
+            modes: None,
+            reported_modes: Modes::empty(),
+            outputs: [0f32 ; MAX_MOTORS],
+        }
+    }
+}
+
+/// Error encountered while running a control cycle
+#[derive(Debug)]
+pub enum StepError<I: core::fmt::Debug, O: core::fmt::Debug> {
+    Imu(I),
+    Motor(O),
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::{
+        ahrs::MadgwickAhrs,
+        control::{rates::ThrottleCurve, PidGains, RateController, Rates},
+        interface::mock::{MockError, MockImu},
+    };
+
+    #[derive(Default)]
+    struct TestLog(String);
+
+    impl core::fmt::Write for TestLog {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            self.0.write_str(s)
+        }
+    }
+
+    impl log::Logger for TestLog {}
+
+    impl TestLog {
+        /// Whether `text` was logged, or messages at `level` are compiled out.
+        fn logged(&self, level: log::Level, text: &str) -> bool {
+            !log::STATIC_MAX_LEVEL.allows(level) || self.0.contains(text)
+        }
+    }
+
+    struct TestRc(Option<RcCommand<f32>>, Option<Modes>);
+
+    impl RcInput<f32> for TestRc {
+        fn command(&mut self, _: Micros) -> Option<RcCommand<f32>> {
+            self.0
+        }
+
+        fn modes(&self) -> Option<Modes> {
+            self.1
+        }
+    }
+
+    /// Passes stick positions straight through as demands
+    struct TestController;
+
+    impl Controller<f32> for TestController {
+        fn update(&mut self, input: &ControlInput<f32>) -> Vector3<f32> {
+            Vector3::new(input.command.roll, input.command.pitch, input.command.yaw)
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    /// Two motors on the roll axis
+    struct TestMixer;
+
+    impl Mixer<f32> for TestMixer {
+        fn motor_count(&self) -> usize {
+            2
+        }
+
+        fn mix(&mut self, demand: Vector3<f32>, throttle: f32, outputs: &mut [f32]) {
+            outputs[0] = throttle + demand.x;
+            outputs[1] = throttle - demand.x;
+        }
+
+        fn mix_turtle(&mut self, sticks: Vector3<f32>, outputs: &mut [f32]) {
+            outputs[0] = sticks.x.max(0.0);
+            outputs[1] = (-sticks.x).max(0.0);
+        }
+    }
+
+    /// Last throttle set, or `None` if stopped, and the spin direction if it can be reversed
+    #[derive(Default)]
+    struct TestMotors(Option<Vec<f32>>, Option<bool>);
+
+    impl MotorOutput for TestMotors {
+        type Error = MockError;
+
+        fn set_throttle(&mut self, throttle: &[f32]) -> Result<(), Self::Error> {
+            self.0 = Some(throttle.to_vec());
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), Self::Error> {
+            self.0 = None;
+            Ok(())
+        }
+
+        fn set_reversed(&mut self, reversed: bool) -> Result<bool, Self::Error> {
+            let Some(direction) = &mut self.1 else { return Ok(false) };
+            *direction = reversed;
+            Ok(true)
+        }
+    }
+
+    fn controller(command: Option<RcCommand<f32>>) -> FlightController<TestLog, MockImu<f32>, MadgwickAhrs<f32>, TestRc, TestController, TestMixer, TestMotors> {
+        let mut fc = FlightControllerBuilder::new()
+            .motors(TestMotors::default())
+            .logger(TestLog::default())
+            .imu(MockImu::new(1000f32))
+            .estimator(MadgwickAhrs::default())
+            .rc(TestRc(command, None))
+            .controller(TestController)
+            .mixer(TestMixer)
+            .build();
+        fc.handle(Event::BootComplete).unwrap();
+        fc
+    }
+
+    #[test]
+    fn test_step_disarmed() {
+        let mut fc = controller(Some(RcCommand { throttle: 0.5, ..Default::default() }));
+        fc.step(0).unwrap();
+        assert!(fc.motors.0.is_none());
+        assert_eq!(fc.outputs(), &[0f32, 0f32]);
+    }
+
+    #[test]
+    fn test_step_armed() {
+        let mut fc = controller(Some(RcCommand::default()));
+        fc.step(0).unwrap();
+        fc.arm().unwrap();
+
+        fc.rc.0 = Some(RcCommand { roll: 0.25, throttle: 0.5, ..Default::default() });
+        fc.step(1000).unwrap();
+        assert_eq!(fc.motors.0.as_deref(), Some(&[0.75f32, 0.25f32][..]));
+
+        let snapshot = fc.snapshot().unwrap();
+        assert_eq!(snapshot.time, 1000);
+        assert_eq!(snapshot.command.roll, 0.25);
+        assert_eq!(snapshot.motors[..2], [0.75f32, 0.25f32]);
+    }
+
+    #[test]
+    fn test_throttle_curve() {
+        let rates = Rates { throttle: ThrottleCurve { mid: 0.5, expo: 1.0 }, ..Default::default() };
+        let mut fc = FlightControllerBuilder::new()
+            .motors(TestMotors::default())
+            .logger(TestLog::default())
+            .imu(MockImu::new(1000f32))
+            .estimator(MadgwickAhrs::default())
+            .rc(TestRc(Some(RcCommand::default()), None))
+            .controller(RateController::new([PidGains::new(0f32, 0f32, 0f32) ; 3], rates))
+            .mixer(TestMixer)
+            .build();
+        fc.handle(Event::BootComplete).unwrap();
+        fc.step(0).unwrap();
+        fc.arm().unwrap();
+
+        // The curve flattens around the midpoint, so a quarter stick gives more throttle
+        fc.rc.0 = Some(RcCommand { throttle: 0.25, ..Default::default() });
+        fc.step(1000).unwrap();
+        assert_eq!(fc.motors.0.as_deref(), Some(&[0.4375f32, 0.4375f32][..]));
+        assert_eq!(fc.command().map(|command| command.throttle), Some(0.25));
+    }
+
+    #[test]
+    fn test_arming_prevented() {
+        let mut fc = controller(None);
+        fc.step(0).unwrap();
+        assert_eq!(fc.arm(), Err(TransitionError::ArmingDisabled(ArmingDisabled::RX_LOSS)));
+
+        fc.rc.0 = Some(RcCommand { throttle: 0.5, ..Default::default() });
+        fc.step(1000).unwrap();
+        assert_eq!(fc.state().can_arm(), Err(ArmingDisabled::THROTTLE));
+
+        fc.imu.accel = Vector3::x();
+        for t in 2..1000 {
+            fc.step(t * 1000).unwrap();
+        }
+        assert_eq!(fc.state().can_arm(), Err(ArmingDisabled::THROTTLE | ArmingDisabled::ANGLE));
+        assert!(fc.log.logged(log::Level::Warn, "Arming disabled"));
+    }
+
+    #[test]
+    fn test_link_loss_failsafe() {
+        let mut fc = controller(Some(RcCommand::default()));
+        fc.step(0).unwrap();
+        fc.arm().unwrap();
+        fc.step(1000).unwrap();
+        assert!(fc.motors.0.is_some());
+
+        fc.rc.0 = None;
+        fc.step(2000).unwrap();
+        assert_eq!(fc.state().state(), FlightState::Failsafe);
+        assert!(fc.motors.0.is_none());
+        assert!(fc.log.logged(log::Level::Info, "Armed -> Failsafe"));
+
+        fc.rc.0 = Some(RcCommand::default());
+        fc.step(3000).unwrap();
+        assert_eq!(fc.state().state(), FlightState::Disarmed);
+        assert_eq!(fc.arm(), Err(TransitionError::ArmingDisabled(ArmingDisabled::ARM_SWITCH)));
+    }
+
+    #[test]
+    fn test_arm_switch() {
+        // The switch is on when the link comes up, so it must be cycled
+        let mut fc = controller(Some(RcCommand::default()));
+        fc.rc.1 = Some(Modes::ARM);
+        fc.step(0).unwrap();
+        assert!(!fc.is_armed());
+        assert_eq!(fc.state().can_arm(), Err(ArmingDisabled::ARM_SWITCH));
+
+        fc.rc.1 = Some(Modes::empty());
+        fc.step(1000).unwrap();
+        fc.rc.1 = Some(Modes::ARM | Modes::ANGLE);
+        fc.step(2000).unwrap();
+        assert!(fc.is_armed());
+        assert_eq!(fc.modes(), Some(Modes::ARM | Modes::ANGLE));
+
+        fc.rc.1 = Some(Modes::empty());
+        fc.step(3000).unwrap();
+        assert!(!fc.is_armed());
+
+        // A request refused for raised throttle isn't retried when the throttle is lowered
+        fc.rc = TestRc(Some(RcCommand { throttle: 0.5, ..Default::default() }), Some(Modes::ARM));
+        fc.step(4000).unwrap();
+        assert_eq!(fc.state().can_arm(), Err(ArmingDisabled::THROTTLE | ArmingDisabled::ARM_SWITCH));
+        fc.rc.0 = Some(RcCommand::default());
+        fc.step(5000).unwrap();
+        assert!(!fc.is_armed());
+
+        // Losing the link forgets the switch, which must be cycled once it returns
+        fc.rc.1 = Some(Modes::empty());
+        fc.step(6000).unwrap();
+        fc.rc.0 = None;
+        fc.step(7000).unwrap();
+        assert_eq!(fc.modes(), None);
+        fc.rc = TestRc(Some(RcCommand::default()), Some(Modes::ARM));
+        fc.step(8000).unwrap();
+        assert_eq!(fc.state().can_arm(), Err(ArmingDisabled::ARM_SWITCH));
+    }
+
+    #[test]
+    fn test_imu_error() {
+        let mut fc = controller(Some(RcCommand::default()));
+        fc.step(0).unwrap();
+        fc.arm().unwrap();
+        fc.step(1000).unwrap();
+        assert!(fc.motors.0.is_some());
+
+        fc.imu.fail = true;
+        assert!(matches!(fc.step(2000), Err(StepError::Imu(MockError))));
+        assert!(fc.motors.0.is_none());
+        assert!(!fc.is_armed());
+        assert_eq!(fc.arm(), Err(TransitionError::ArmingDisabled(ArmingDisabled::NO_GYRO)));
+
+        fc.imu.fail = false;
+        fc.step(3000).unwrap();
+        assert_eq!(fc.state().can_arm(), Ok(()));
+    }
+
+    #[test]
+    fn test_turtle() {
+        let mut fc = controller(Some(RcCommand::default()));
+        fc.motors.1 = Some(false);
+        fc.rc.1 = Some(Modes::TURTLE);
+        fc.step(0).unwrap();
+
+        // Turtle mode arms upside down and drives the motors straight from the sticks
+        fc.imu.accel = Vector3::new(1.0, 0.0, -1.0);
+        for t in 1..1000 {
+            fc.step(t * 1000).unwrap();
+        }
+        assert_eq!(fc.state().can_arm(), Err(ArmingDisabled::ANGLE));
+        fc.rc.1 = Some(Modes::TURTLE | Modes::ARM);
+        fc.step(1_000_000).unwrap();
+        assert_eq!(fc.state().state(), FlightState::Turtle);
+        assert!(!fc.is_armed());
+        assert_eq!(fc.motors.1, Some(true));
+
+        fc.rc.0 = Some(RcCommand { roll: -0.5, throttle: 0.5, ..Default::default() });
+        fc.step(1_001_000).unwrap();
+        assert_eq!(fc.motors.0.as_deref(), Some(&[0f32, 0.5f32][..]));
+
+        fc.rc.1 = Some(Modes::TURTLE);
+        fc.step(1_002_000).unwrap();
+        assert_eq!(fc.state().state(), FlightState::Disarmed);
+        assert_eq!(fc.motors.1, Some(false));
+        assert!(fc.motors.0.is_none());
+
+        // Motors that can't be reversed refuse turtle mode and leave the vehicle disarmed
+        let mut fc = controller(Some(RcCommand::default()));
+        fc.step(0).unwrap();
+        assert_eq!(fc.turtle(), Err(TransitionError::MotorsNotReversible));
+        assert_eq!(fc.state().state(), FlightState::Disarmed);
+        assert!(fc.log.logged(log::Level::Warn, "can't be reversed"));
+
+        // and the switch has to be cycled before arming again
+        fc.rc.1 = Some(Modes::TURTLE);
+        fc.step(1000).unwrap();
+        fc.rc.1 = Some(Modes::TURTLE | Modes::ARM);
+        fc.step(2000).unwrap();
+        assert_eq!(fc.state().state(), FlightState::Disarmed);
+        assert!(fc.state().can_arm().unwrap_err().contains(ArmingDisabled::ARM_SWITCH));
+    }
+
+    #[test]
+    fn test_mode_hooks() {
+        #[derive(Default)]
+        struct Changes(Vec<(Modes, Modes)>);
+
+        impl StateHooks for Changes {
+            fn on_modes_changed(&mut self, previous: Modes, modes: Modes) {
+                self.0.push((previous, modes));
+            }
+        }
+
+        let mut fc = FlightControllerBuilder::new()
+            .motors(TestMotors::default())
+            .logger(TestLog::default())
+            .imu(MockImu::new(1000f32))
+            .estimator(MadgwickAhrs::default())
+            .rc(TestRc(Some(RcCommand::default()), Some(Modes::BLACKBOX)))
+            .controller(TestController)
+            .mixer(TestMixer)
+            .hooks(Changes::default())
+            .build();
+        fc.handle(Event::BootComplete).unwrap();
+        fc.step(0).unwrap();
+        fc.step(1000).unwrap();
+        assert!(!fc.beeper());
+
+        // Losing the link keeps the modes, so switching while it's down is seen once it returns
+        fc.rc.0 = None;
+        fc.step(2000).unwrap();
+        fc.rc = TestRc(Some(RcCommand::default()), Some(Modes::BEEPER));
+        fc.step(3000).unwrap();
+        assert!(fc.beeper());
+        assert_eq!(fc.state().hooks().0, [
+            (Modes::empty(), Modes::BLACKBOX),
+            (Modes::BLACKBOX, Modes::BEEPER),
+        ]);
+
+        // The beeper sounds in failsafe to find the vehicle
+        fc.rc.1 = Some(Modes::empty());
+        fc.step(4000).unwrap();
+        fc.rc.1 = Some(Modes::ARM);
+        fc.step(5000).unwrap();
+        assert!(fc.is_armed());
+        fc.rc.0 = None;
+        fc.step(6000).unwrap();
+        assert!(fc.beeper());
+    }
 }
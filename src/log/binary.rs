@@ -0,0 +1,638 @@
+//! Compact log encoding that defers formatting to the host.
+//!
+//! Format strings passed to [binlog!](crate::binlog) are collected into [STRINGS] by the build
+//! script, and each message is sent as a frame holding the index of its format string and the
+//! raw bytes of its arguments:
+//!
+//! ```text
+//! 0xB1 | length | level | index (u16) | timestamp (u32, optional) | arguments... | CRC-8
+//! ```
+//!
+//! The length counts the bytes between itself and the CRC, and the CRC is CRC-8 DVB-S2 over the
+//! length and payload. Bit 7 of the level byte is set when a timestamp is present. Each argument
+//! is a type tag followed by its little-endian value, or a length and UTF-8 bytes for strings.
+//!
+//! A stream should begin with a [header] frame, whose level byte is [HEADER] and whose payload
+//! holds [TABLE_HASH], so that decoders built from different sources can refuse it rather than
+//! print the wrong messages
+
+use core::fmt;
+
+use crate::{math::crc::crc8_dvb_s2_slice, Micros};
+
+use super::{Level, Logger};
+
+include!(concat!(env!("OUT_DIR"), "/binlog_strings.rs"));
+
+/// Byte marking the start of a frame
+pub const SYNC: u8 = 0xB1;
+
+/// Largest payload that can be held in a frame
+pub const MAX_PAYLOAD: usize = u8::MAX as usize;
+
+/// Flag in the level byte marking that a timestamp follows the format string index
+const TIMESTAMP_FLAG: u8 = 0x80;
+
+/// Level byte of the frame holding [TABLE_HASH] at the start of a stream
+pub const HEADER: u8 = 0x40;
+
+/// Length of a [header] frame
+pub const HEADER_LEN: usize = 8;
+
+/// FNV-1a hash of [STRINGS], identifying the table a stream's indices refer to
+pub const TABLE_HASH: u32 = table_hash(&STRINGS);
+
+const fn table_hash(strings: &[&str]) -> u32 {
+    let mut hash = 0x811C_9DC5u32;
+    let mut i = 0;
+    while i < strings.len() {
+        let bytes = strings[i].as_bytes();
+        let mut j = 0;
+        // Each string is followed by a zero byte so that moving text between strings changes the hash
+        while j <= bytes.len() {
+            let byte = if j < bytes.len() { bytes[j] } else { 0 };
+            hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+            j += 1;
+        }
+        i += 1;
+    }
+
+    hash
+}
+
+/// Build the frame that starts a stream, identifying its format string table
+pub fn header() -> [u8 ; HEADER_LEN] {
+    let mut frame = [0u8 ; HEADER_LEN];
+    frame[0] = SYNC;
+    frame[1] = 5;
+    frame[2] = HEADER;
+    frame[3..7].copy_from_slice(&TABLE_HASH.to_le_bytes());
+    frame[7] = crc8_dvb_s2_slice(&frame[1..7]);
+    frame
+}
+
+/// Write the [header] frame, which should be done whenever a reader may start reading the stream,
+/// such as when a log file is created or a host opens the serial port
+pub fn write_header<L: Logger + ?Sized>(logger: &mut L) -> fmt::Result {
+    logger.write_bytes(&header())
+}
+
+/// Get the table hash from a frame's payload, or `None` if it isn't a [header]
+pub fn parse_header(payload: &[u8]) -> Option<u32> {
+    match payload {
+        [HEADER, hash @ ..] => Some(u32::from_le_bytes(hash.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// Find the index of a format string in [STRINGS], failing compilation if it was not collected
+pub const fn intern(fmt: &str) -> u16 {
+    let mut i = 0;
+    while i < STRINGS.len() {
+        if str_eq(STRINGS[i], fmt) {
+            return i as u16
+        }
+        i += 1;
+    }
+
+    panic!("Format string was not collected by the build script, add its crate's sources to BINLOG_SOURCES")
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false
+    }
+
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false
+        }
+        i += 1;
+    }
+
+    true
+}
+
+/// Type tags preceding each argument's value
+mod tag {
+    pub const U8: u8 = 0;
+    pub const U16: u8 = 1;
+    pub const U32: u8 = 2;
+    pub const U64: u8 = 3;
+    pub const I8: u8 = 4;
+    pub const I16: u8 = 5;
+    pub const I32: u8 = 6;
+    pub const I64: u8 = 7;
+    pub const F32: u8 = 8;
+    pub const F64: u8 = 9;
+    pub const BOOL: u8 = 10;
+    pub const STR: u8 = 11;
+}
+
+/// Frame being built for a single message
+pub struct Frame {
+    buf: [u8 ; MAX_PAYLOAD + 3],
+    len: usize,
+    truncated: bool,
+}
+
+impl Frame {
+    /// Start a frame for a message at `level` with the format string at `index`
+    pub fn new(level: Level, index: u16, timestamp: Option<Micros>) -> Self {
+        let mut frame = Self {
+            buf: [0u8 ; MAX_PAYLOAD + 3],
+            len: 2,
+            truncated: false,
+        };
+
+        let flags = match timestamp {
+            Some(_) => TIMESTAMP_FLAG,
+            None => 0,
+        };
+        frame.push(&[level as u8 | flags]);
+        frame.push(&index.to_le_bytes());
+        if let Some(t) = timestamp {
+            frame.push(&t.to_le_bytes());
+        }
+
+        frame
+    }
+
+    /// Append an argument's tag and value, dropping it if the frame is full
+    pub fn arg(&mut self, tag: u8, value: &[u8]) {
+        if self.len + 1 + value.len() > MAX_PAYLOAD + 2 {
+            self.truncated = true;
+            return
+        }
+
+        self.push(&[tag]);
+        self.push(value);
+    }
+
+    /// Check if any arguments were dropped because they did not fit
+    pub const fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Add the header and checksum, returning the bytes to send
+    pub fn finish(&mut self) -> &[u8] {
+        self.buf[0] = SYNC;
+        self.buf[1] = (self.len - 2) as u8;
+        self.buf[self.len] = crc8_dvb_s2_slice(&self.buf[1..self.len]);
+        &self.buf[..self.len + 1]
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+/// Value that can be sent as an argument of a binary log message
+pub trait Encode {
+    fn encode(&self, frame: &mut Frame);
+}
+
+macro_rules! impl_encode {
+    ($($ty:ty => $tag:expr),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, frame: &mut Frame) {
+                    frame.arg($tag, &self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_encode!(
+    u8 => tag::U8, u16 => tag::U16, u32 => tag::U32, u64 => tag::U64,
+    i8 => tag::I8, i16 => tag::I16, i32 => tag::I32, i64 => tag::I64,
+    f32 => tag::F32, f64 => tag::F64
+);
+
+impl Encode for bool {
+    fn encode(&self, frame: &mut Frame) {
+        frame.arg(tag::BOOL, &[*self as u8]);
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, frame: &mut Frame) {
+        let len = self.len().min(u8::MAX as usize);
+        let mut buf = [0u8 ; u8::MAX as usize + 1];
+        buf[0] = len as u8;
+        buf[1..=len].copy_from_slice(&self.as_bytes()[..len]);
+        frame.arg(tag::STR, &buf[..=len]);
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, frame: &mut Frame) {
+        (**self).encode(frame)
+    }
+}
+
+/// Write a message as a binary frame through a [Logger](super::Logger), if enabled.
+/// Arguments must implement [Encode].
+///
+/// The format string is found by the build script scanning source text, so it must be written
+/// as a string literal directly inside the invocation, not produced by another macro. Only this
+/// crate's `src` is scanned unless other crates' source directories are listed in the
+/// `BINLOG_SOURCES` environment variable. A format string that was not collected fails to
+/// compile rather than logging the wrong message
+#[macro_export]
+macro_rules! binlog {
+    ($logger:expr, $level:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        const INDEX: u16 = $crate::log::binary::intern($fmt);
+        let level: $crate::log::Level = $level;
+        if $crate::log::STATIC_MAX_LEVEL.allows(level) {
+            let logger = &mut $logger;
+            if $crate::log::Logger::enabled(logger, level, ::core::module_path!()) {
+                let timestamp = $crate::log::Logger::timestamp(logger);
+                let mut frame = $crate::log::binary::Frame::new(level, INDEX, timestamp);
+                $( $crate::log::binary::Encode::encode(&$arg, &mut frame); )*
+                let _ = $crate::log::Logger::write_bytes(logger, frame.finish());
+            }
+        }
+    }};
+}
+
+/// Argument decoded from a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arg<'a> {
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Str(&'a str),
+}
+
+/// Message decoded from a frame's payload
+#[derive(Clone, Copy, Debug)]
+pub struct Message<'a> {
+    pub level: Level,
+    pub index: u16,
+    pub timestamp: Option<Micros>,
+    args: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The payload ended in the middle of a field
+    Truncated,
+    InvalidLevel(u8),
+    InvalidTag(u8),
+    InvalidUtf8,
+    /// The format string index is outside of [STRINGS]
+    UnknownString(u16),
+}
+
+impl<'a> Message<'a> {
+    /// Parse the payload of a frame, not including the sync, length, and CRC bytes
+    pub fn parse(payload: &'a [u8]) -> Result<Self, DecodeError> {
+        let (&flags, rest) = payload.split_first().ok_or(DecodeError::Truncated)?;
+        let level = match flags & !TIMESTAMP_FLAG {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            5 => Level::Trace,
+            other => return Err(DecodeError::InvalidLevel(other)),
+        };
+
+        let (index, rest) = split::<2>(rest)?;
+        let (timestamp, args) = match flags & TIMESTAMP_FLAG {
+            0 => (None, rest),
+            _ => {
+                let (t, rest) = split::<4>(rest)?;
+                (Some(u32::from_le_bytes(t)), rest)
+            },
+        };
+
+        Ok(Self {
+            level,
+            index: u16::from_le_bytes(index),
+            timestamp,
+            args,
+        })
+    }
+
+    /// Get the format string of this message
+    pub fn format_string(&self) -> Result<&'static str, DecodeError> {
+        STRINGS.get(self.index as usize).copied().ok_or(DecodeError::UnknownString(self.index))
+    }
+
+    /// Iterate over the arguments of this message
+    pub fn args(&self) -> Args<'a> {
+        Args { buf: self.args }
+    }
+
+    /// Write the formatted message text
+    pub fn format<W: fmt::Write>(&self, out: &mut W) -> Result<(), FormatError> {
+        format(self.format_string()?, self.args(), out)
+    }
+}
+
+/// Iterator over the arguments in a frame
+pub struct Args<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = Result<Arg<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&tag, rest) = self.buf.split_first()?;
+        self.buf = rest;
+        Some(self.decode(tag))
+    }
+}
+
+impl<'a> Args<'a> {
+    fn decode(&mut self, tag: u8) -> Result<Arg<'a>, DecodeError> {
+        Ok(match tag {
+            tag::U8 => Arg::U64(u8::from_le_bytes(self.take()?) as u64),
+            tag::U16 => Arg::U64(u16::from_le_bytes(self.take()?) as u64),
+            tag::U32 => Arg::U64(u32::from_le_bytes(self.take()?) as u64),
+            tag::U64 => Arg::U64(u64::from_le_bytes(self.take()?)),
+            tag::I8 => Arg::I64(i8::from_le_bytes(self.take()?) as i64),
+            tag::I16 => Arg::I64(i16::from_le_bytes(self.take()?) as i64),
+            tag::I32 => Arg::I64(i32::from_le_bytes(self.take()?) as i64),
+            tag::I64 => Arg::I64(i64::from_le_bytes(self.take()?)),
+            tag::F32 => Arg::F32(f32::from_le_bytes(self.take()?)),
+            tag::F64 => Arg::F64(f64::from_le_bytes(self.take()?)),
+            tag::BOOL => Arg::Bool(self.take::<1>()?[0] != 0),
+            tag::STR => {
+                let [len] = self.take::<1>()?;
+                if self.buf.len() < len as usize {
+                    return Err(DecodeError::Truncated)
+                }
+                let (s, rest) = self.buf.split_at(len as usize);
+                self.buf = rest;
+                Arg::Str(core::str::from_utf8(s).map_err(|_| DecodeError::InvalidUtf8)?)
+            },
+            other => return Err(DecodeError::InvalidTag(other)),
+        })
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8 ; N], DecodeError> {
+        let (value, rest) = split::<N>(self.buf)?;
+        self.buf = rest;
+        Ok(value)
+    }
+}
+
+fn split<const N: usize>(buf: &[u8]) -> Result<([u8 ; N], &[u8]), DecodeError> {
+    match buf.split_first_chunk::<N>() {
+        Some((value, rest)) => Ok((*value, rest)),
+        None => Err(DecodeError::Truncated),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    Decode(DecodeError),
+    /// The format string has more placeholders than the message has arguments
+    MissingArgument,
+    /// A placeholder in the format string was not closed
+    InvalidFormat,
+    Write,
+}
+
+impl From<DecodeError> for FormatError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+impl From<fmt::Error> for FormatError {
+    fn from(_: fmt::Error) -> Self {
+        Self::Write
+    }
+}
+
+/// Substitute arguments into a format string. Placeholders may be empty (`{}`), debug (`{:?}`),
+/// hexadecimal (`{:x}`, `{:X}`), or set a float precision (`{:.2}`)
+pub fn format<'a, W: fmt::Write>(
+    fmt: &str,
+    mut args: impl Iterator<Item = Result<Arg<'a>, DecodeError>>,
+    out: &mut W,
+) -> Result<(), FormatError> {
+    let mut rest = fmt;
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.write_str(&rest[..pos])?;
+        let brace = rest.as_bytes()[pos];
+        rest = &rest[pos + 1..];
+
+        if rest.as_bytes().first() == Some(&brace) {
+            out.write_char(brace as char)?;
+            rest = &rest[1..];
+            continue
+        }
+        if brace == b'}' {
+            return Err(FormatError::InvalidFormat)
+        }
+
+        let end = rest.find('}').ok_or(FormatError::InvalidFormat)?;
+        let spec = rest[..end].split_once(':').map(|(_, spec)| spec).unwrap_or("");
+        rest = &rest[end + 1..];
+
+        let arg = args.next().ok_or(FormatError::MissingArgument)??;
+        match (arg, spec) {
+            (Arg::U64(v), "x") => write!(out, "{v:x}")?,
+            (Arg::U64(v), "X") => write!(out, "{v:X}")?,
+            (Arg::I64(v), "x") => write!(out, "{v:x}")?,
+            (Arg::I64(v), "X") => write!(out, "{v:X}")?,
+            (Arg::F32(v), spec) if spec.starts_with('.') => write!(out, "{v:.*}", precision(spec))?,
+            (Arg::F64(v), spec) if spec.starts_with('.') => write!(out, "{v:.*}", precision(spec))?,
+            (Arg::Str(v), "?") => write!(out, "{v:?}")?,
+            (Arg::U64(v), _) => write!(out, "{v}")?,
+            (Arg::I64(v), _) => write!(out, "{v}")?,
+            (Arg::F32(v), _) => write!(out, "{v}")?,
+            (Arg::F64(v), _) => write!(out, "{v}")?,
+            (Arg::Bool(v), _) => write!(out, "{v}")?,
+            (Arg::Str(v), _) => out.write_str(v)?,
+        }
+    }
+
+    Ok(out.write_str(rest)?)
+}
+
+fn precision(spec: &str) -> usize {
+    spec[1..].parse().unwrap_or(3)
+}
+
+/// Splits a byte stream into frame payloads, skipping bytes until a valid frame is found
+pub struct Decoder {
+    buf: [u8 ; MAX_PAYLOAD + 3],
+    len: usize,
+    /// Length of the frame returned by the last call to `push`, removed on the next call
+    returned: usize,
+    errors: u32,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8 ; MAX_PAYLOAD + 3],
+            len: 0,
+            returned: 0,
+            errors: 0,
+        }
+    }
+
+    /// Get the number of frames discarded because their checksum did not match
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Add a byte from the stream, returning the payload of a frame if this byte completes one
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        self.discard(self.returned);
+        self.returned = 0;
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        loop {
+            match self.buf[..self.len].iter().position(|b| *b == SYNC) {
+                Some(start) => self.discard(start),
+                None => {
+                    self.len = 0;
+                    return None
+                },
+            }
+
+            if self.len < 2 || self.len < self.buf[1] as usize + 3 {
+                return None
+            }
+
+            let payload_len = self.buf[1] as usize;
+            if crc8_dvb_s2_slice(&self.buf[1..payload_len + 2]) == self.buf[payload_len + 2] {
+                self.returned = payload_len + 3;
+                return Some(&self.buf[2..payload_len + 2])
+            }
+
+            // Skip the bad sync byte and look for another frame in the bytes after it
+            self.errors = self.errors.wrapping_add(1);
+            self.discard(1);
+        }
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.buf.copy_within(count..self.len, 0);
+        self.len -= count;
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::{tests::TestLog, Logger, STATIC_MAX_LEVEL};
+
+    fn decode(bytes: &[u8]) -> Vec<String> {
+        let mut decoder = Decoder::new();
+        let mut messages = Vec::new();
+        for b in bytes {
+            if let Some(payload) = decoder.push(*b) {
+                let message = Message::parse(payload).unwrap();
+                let mut text = String::new();
+                message.format(&mut text).unwrap();
+                messages.push(text);
+            }
+        }
+
+        messages
+    }
+
+    #[test]
+    fn test_binlog_roundtrip() {
+        // The max-level features compile these messages out
+        if !STATIC_MAX_LEVEL.allows(Level::Info) {
+            return;
+        }
+        let mut log = TestLog::default();
+        crate::binlog!(log, Level::Info, "gyro {} {:.2} {:x} {}", -5i16, 1.5f32, 255u32, "ok");
+        crate::binlog!(log, Level::Warn, "armed {}", true);
+
+        let bytes = log.1.clone();
+        assert_eq!(bytes[0], SYNC);
+        assert_eq!(decode(&bytes), ["gyro -5 1.50 ff ok", "armed true"]);
+    }
+
+    #[test]
+    fn test_decoder_resync() {
+        if !STATIC_MAX_LEVEL.allows(Level::Error) {
+            return;
+        }
+        let mut log = TestLog::default();
+        crate::binlog!(log, Level::Error, "loop overrun {}", 12u8);
+        crate::binlog!(log, Level::Error, "loop overrun {}", 13u8);
+
+        // A partial frame followed by a frame with a bad checksum
+        let frame_len = log.1.len() / 2;
+        let mut bytes = vec![SYNC, 4, 0x00];
+        bytes.extend_from_slice(&log.1);
+        bytes[3 + frame_len - 1] ^= 0xFF;
+        assert_eq!(decode(&bytes), ["loop overrun 13"]);
+
+        bytes[3 + frame_len - 1] ^= 0xFF;
+        assert_eq!(decode(&bytes), ["loop overrun 12", "loop overrun 13"]);
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let mut frame = Frame::new(Level::Debug, intern("loop overrun {}"), Some(42));
+        12u8.encode(&mut frame);
+        let bytes = frame.finish();
+
+        let message = Message::parse(&bytes[2..bytes.len() - 1]).unwrap();
+        assert_eq!(message.level, Level::Debug);
+        assert_eq!(message.timestamp, Some(42));
+        assert_eq!(message.format_string(), Ok("loop overrun {}"));
+        assert_eq!(message.args().collect::<Vec<_>>(), [Ok(Arg::U64(12))]);
+        assert!(Logger::enabled(&TestLog::default(), Level::Trace, ""));
+    }
+
+    #[test]
+    fn test_format_escapes() {
+        let mut out = String::new();
+        format("{{}} {:?} }}", [Ok(Arg::Str("a"))].into_iter(), &mut out).unwrap();
+        assert_eq!(out, "{} \"a\" }");
+        assert_eq!(format("{}", core::iter::empty(), &mut out), Err(FormatError::MissingArgument));
+    }
+
+    #[test]
+    fn test_header() {
+        let mut log = TestLog::default();
+        write_header(&mut log).unwrap();
+        crate::binlog!(log, Level::Warn, "armed {}", true);
+
+        let mut decoder = Decoder::new();
+        let mut payloads = Vec::new();
+        for b in &log.1 {
+            if let Some(payload) = decoder.push(*b) {
+                payloads.push(payload.to_vec());
+            }
+        }
+        assert_eq!(payloads.len(), 1 + STATIC_MAX_LEVEL.allows(Level::Warn) as usize);
+        assert_eq!(parse_header(&payloads[0]), Some(TABLE_HASH));
+        assert_eq!(Message::parse(&payloads[0]).err(), Some(DecodeError::InvalidLevel(HEADER)));
+        if let Some(message) = payloads.get(1) {
+            assert_eq!(parse_header(message), None);
+        }
+
+        // Reordering or splitting strings changes the hash
+        assert_ne!(table_hash(&["a", "b"]), table_hash(&["b", "a"]));
+        assert_ne!(table_hash(&["ab"]), table_hash(&["a", "b"]));
+    }
+}
@@ -0,0 +1,300 @@
+//! Decoding of blackbox logs, used by host tools to read logs back with the same field
+//! definitions the recorder writes them with
+
+use super::{
+    encoding::Reader,
+    fields::{self, Encoding, EventKind, Predictor, MAX_FIELDS},
+    DisarmReason,
+    Event,
+};
+
+/// Error encountered while decoding a log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data ended in the middle of a frame
+    Truncated,
+    /// A header line described more fields than [MAX_FIELDS]
+    TooManyFields,
+    UnknownPredictor(u8),
+    UnknownEncoding(u8),
+    UnknownEvent(u8),
+    UnknownFrame(u8),
+    /// A header line value could not be parsed
+    InvalidHeader,
+    /// A P-frame was found before any I-frame it could be predicted from
+    MissingIntraFrame,
+}
+
+/// Field layout and frame intervals of a log, read from its header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Number of fields in each main frame
+    pub count: usize,
+    pub intra: [(Predictor, Encoding) ; MAX_FIELDS],
+    pub inter: [(Predictor, Encoding) ; MAX_FIELDS],
+    /// Number of loop iterations between I-frames
+    pub i_interval: u32,
+    /// One of every `p_divisor` loop iterations is recorded
+    pub p_divisor: u32,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            intra: [(Predictor::Zero, Encoding::SignedVb) ; MAX_FIELDS],
+            inter: [(Predictor::Zero, Encoding::SignedVb) ; MAX_FIELDS],
+            i_interval: 32,
+            p_divisor: 1,
+        }
+    }
+}
+
+impl Layout {
+    /// Apply a header line, without its leading `H `. Lines that don't affect decoding are ignored
+    pub fn header(&mut self, line: &str) -> Result<(), DecodeError> {
+        let Some((key, value)) = line.trim_end().split_once(':') else {
+            return Err(DecodeError::InvalidHeader)
+        };
+
+        match key {
+            "Field I name" => self.count = count(value)?,
+            "Field I predictor" => parse_ids(value, &mut self.intra, |e, id| {
+                e.0 = Predictor::from_id(id).ok_or(DecodeError::UnknownPredictor(id))?;
+                Ok(())
+            })?,
+            "Field I encoding" => parse_ids(value, &mut self.intra, |e, id| {
+                e.1 = Encoding::from_id(id).ok_or(DecodeError::UnknownEncoding(id))?;
+                Ok(())
+            })?,
+            "Field P predictor" => parse_ids(value, &mut self.inter, |e, id| {
+                e.0 = Predictor::from_id(id).ok_or(DecodeError::UnknownPredictor(id))?;
+                Ok(())
+            })?,
+            "Field P encoding" => parse_ids(value, &mut self.inter, |e, id| {
+                e.1 = Encoding::from_id(id).ok_or(DecodeError::UnknownEncoding(id))?;
+                Ok(())
+            })?,
+            "I interval" => self.i_interval = value.parse().map_err(|_| DecodeError::InvalidHeader)?,
+            "P interval" => {
+                let (num, denom) = value.split_once('/').ok_or(DecodeError::InvalidHeader)?;
+                if num != "1" {
+                    return Err(DecodeError::InvalidHeader)
+                }
+                self.p_divisor = denom.parse().map_err(|_| DecodeError::InvalidHeader)?;
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+}
+
+fn count(list: &str) -> Result<usize, DecodeError> {
+    match list.split(',').count() {
+        count if count > MAX_FIELDS => Err(DecodeError::TooManyFields),
+        count => Ok(count),
+    }
+}
+
+fn parse_ids<E>(
+    list: &str,
+    entries: &mut [E ; MAX_FIELDS],
+    mut apply: impl FnMut(&mut E, u8) -> Result<(), DecodeError>,
+) -> Result<(), DecodeError> {
+    count(list)?;
+    for (entry, id) in entries.iter_mut().zip(list.split(',')) {
+        apply(entry, id.trim().parse().map_err(|_| DecodeError::InvalidHeader)?)?;
+    }
+
+    Ok(())
+}
+
+/// Frame decoded from the binary part of a log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frame<'a> {
+    /// Main frame with the value of each field, which is an I-frame if `intra` is set
+    Main { intra: bool, values: &'a [i32] },
+    Event(Event),
+    /// The end of the log was reached
+    End,
+}
+
+/// Decoder of the frames following a log's header
+pub struct FrameDecoder {
+    layout: Layout,
+    /// Field values of the last two main frames
+    history: [[i32 ; MAX_FIELDS] ; 2],
+    valid: bool,
+}
+
+impl FrameDecoder {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            history: [[0i32 ; MAX_FIELDS] ; 2],
+            valid: false,
+        }
+    }
+
+    pub const fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Discard the frame history after corrupt data, so P-frames fail until the next I-frame
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    /// Decode the next frame. After an error the reader is left part way through the frame
+    pub fn next<'s>(&'s mut self, reader: &mut Reader<'_>) -> Result<Frame<'s>, DecodeError> {
+        match reader.byte().ok_or(DecodeError::Truncated)? {
+            fields::FRAME_INTRA => self.main(reader, true),
+            fields::FRAME_INTER => self.main(reader, false),
+            fields::FRAME_EVENT => event(reader),
+            kind => Err(DecodeError::UnknownFrame(kind)),
+        }
+    }
+
+    fn main<'s>(&'s mut self, reader: &mut Reader<'_>, intra: bool) -> Result<Frame<'s>, DecodeError> {
+        if !intra && !self.valid {
+            return Err(DecodeError::MissingIntraFrame)
+        }
+
+        let fields = match intra {
+            true => &self.layout.intra,
+            false => &self.layout.inter,
+        };
+        let skipped = self.layout.p_divisor.saturating_sub(1);
+        let mut values = [0i32 ; MAX_FIELDS];
+        for (i, &(predictor, encoding)) in fields[..self.layout.count].iter().enumerate() {
+            let delta = match encoding {
+                Encoding::SignedVb => reader.signed_vb().ok_or(DecodeError::Truncated)?,
+                Encoding::UnsignedVb => reader.unsigned_vb().ok_or(DecodeError::Truncated)? as i32,
+                Encoding::Null => 0,
+            };
+            values[i] = predictor.predict(self.history[0][i], self.history[1][i], skipped).wrapping_add(delta);
+        }
+
+        self.history[1] = match intra {
+            true => values,
+            false => self.history[0],
+        };
+        self.history[0] = values;
+        self.valid = true;
+
+        Ok(Frame::Main { intra, values: &self.history[0][..self.layout.count] })
+    }
+}
+
+fn event<'s>(reader: &mut Reader<'_>) -> Result<Frame<'s>, DecodeError> {
+    let id = reader.byte().ok_or(DecodeError::Truncated)?;
+    let mut value = || reader.unsigned_vb().ok_or(DecodeError::Truncated);
+    let event = match EventKind::from_id(id).ok_or(DecodeError::UnknownEvent(id))? {
+        EventKind::SyncBeep => Event::Armed { time: value()? },
+        EventKind::Disarm => {
+            let reason = value()?;
+            Event::Disarmed {
+                reason: DisarmReason::from_id(reason).ok_or(DecodeError::UnknownEvent(id))?,
+            }
+        },
+        EventKind::FlightMode => Event::FlightMode { flags: value()?, previous: value()? },
+        EventKind::LogEnd => {
+            let message = reader.remaining().get(..fields::LOG_END_MESSAGE.len());
+            if message != Some(fields::LOG_END_MESSAGE) {
+                return Err(DecodeError::Truncated)
+            }
+            for _ in fields::LOG_END_MESSAGE {
+                reader.byte();
+            }
+            return Ok(Frame::End)
+        },
+    };
+
+    Ok(Frame::Event(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{UnitQuaternion, Vector3};
+
+    use super::*;
+    use crate::{
+        control::ControlTelemetry,
+        interface::rc::RcCommand,
+        log::{blackbox::{Config, Recorder, Snapshot}, ring::RingBuffer},
+        state::{self, FlightState, StateHooks},
+        MAX_MOTORS,
+    };
+
+    #[test]
+    fn test_decode_recorded_log() {
+        let config = Config { rate_divisor: 4, iframe_interval: 8, motor_count: 6, looptime: 250 };
+        let mut recorder = Recorder::new(RingBuffer::<16384>::new(), config);
+        recorder.start();
+        recorder.poll();
+
+        let mut expected = Vec::new();
+        for i in 0..200u32 {
+            let snapshot = Snapshot {
+                time: 10_000 + i * 250 + (i % 3),
+                gyro: Vector3::new(i as f32 * 0.01, -0.5, 0.25),
+                accel: Vector3::new(0.0, 0.1, 0.98),
+                attitude: UnitQuaternion::from_euler_angles(0.1, 0.0, i as f32 * 0.01),
+                command: RcCommand { roll: 0.2, pitch: -0.1, yaw: 0.0, throttle: 0.4 },
+                control: ControlTelemetry::default(),
+                motors: [0.4 ; MAX_MOTORS],
+            };
+            if i % 4 == 0 {
+                let mut values = snapshot.fields();
+                values[fields::LOOP_ITERATION] = i as i32;
+                expected.push(values);
+            }
+            recorder.record(&snapshot);
+            if i == 100 {
+                recorder.on_transition(FlightState::Armed, FlightState::Disarmed, state::Event::DisarmRequested);
+            }
+        }
+        recorder.stop();
+
+        let mut log = vec![0u8 ; 16384];
+        let len = recorder.sink_mut().pop(&mut log);
+        let text = core::str::from_utf8(&log[..len]).unwrap_or_else(|e| core::str::from_utf8(&log[..e.valid_up_to()]).unwrap());
+        let mut layout = Layout::default();
+        let mut header_len = 0;
+        for line in text.split_inclusive('\n').take_while(|l| l.starts_with("H ")) {
+            layout.header(&line[2..]).unwrap();
+            header_len += line.len();
+        }
+        assert_eq!((layout.count, layout.p_divisor, layout.i_interval), (fields::BASE_FIELDS + 6, 4, 32));
+
+        let mut decoder = FrameDecoder::new(layout);
+        let mut reader = Reader::new(&log[header_len..len]);
+        let mut decoded = Vec::new();
+        let mut events = Vec::new();
+        loop {
+            match decoder.next(&mut reader).unwrap() {
+                Frame::Main { values, .. } => decoded.push(values.to_vec()),
+                Frame::Event(event) => events.push(event),
+                Frame::End => break,
+            }
+        }
+
+        assert_eq!(decoded.len(), expected.len());
+        for (decoded, expected) in decoded.iter().zip(&expected) {
+            assert_eq!(decoded[..], expected[..layout.count]);
+        }
+        assert_eq!(events, [Event::Disarmed { reason: DisarmReason::Switch }]);
+        assert!(reader.remaining().is_empty());
+    }
+
+    #[test]
+    fn test_p_frame_requires_i_frame() {
+        let mut layout = Layout::default();
+        layout.header("Field I name:a,b").unwrap();
+        let mut decoder = FrameDecoder::new(layout);
+        assert_eq!(decoder.next(&mut Reader::new(b"P\x00\x00")), Err(DecodeError::MissingIntraFrame));
+        assert_eq!(decoder.next(&mut Reader::new(b"X")), Err(DecodeError::UnknownFrame(b'X')));
+        assert_eq!(layout.header("Field I predictor:0,3"), Err(DecodeError::UnknownPredictor(3)));
+    }
+}
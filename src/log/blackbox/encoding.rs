@@ -0,0 +1,124 @@
+//! Variable-byte integer encodings used by blackbox frames
+
+/// Cursor writing bytes into a fixed buffer
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Get the bytes written so far
+    pub fn written(&self) -> &[u8] {
+        &self.buf[..self.pos]
+    }
+
+    pub fn byte(&mut self, byte: u8) {
+        self.buf[self.pos] = byte;
+        self.pos += 1;
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    /// Write an unsigned value 7 bits at a time, least significant first, with the high bit
+    /// of each byte set if more bytes follow
+    pub fn unsigned_vb(&mut self, mut value: u32) {
+        while value > 0x7F {
+            self.byte(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.byte(value as u8);
+    }
+
+    /// Write a signed value as a ZigZag encoded unsigned variable-byte value
+    pub fn signed_vb(&mut self, value: i32) {
+        self.unsigned_vb(zigzag_encode(value));
+    }
+}
+
+/// Largest number of bytes an unsigned variable-byte value can take
+pub const MAX_VB_LEN: usize = 5;
+
+pub const fn zigzag_encode(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+pub const fn zigzag_decode(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+/// Cursor reading values from a buffer
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Get the number of bytes read so far
+    pub const fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Get the bytes that have not been read yet
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+
+    pub fn byte(&mut self) -> Option<u8> {
+        let byte = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    pub fn unsigned_vb(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..MAX_VB_LEN {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u32) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some(value)
+            }
+        }
+
+        None
+    }
+
+    pub fn signed_vb(&mut self) -> Option<i32> {
+        self.unsigned_vb().map(zigzag_decode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variable_byte_roundtrip() {
+        let mut buf = [0u8 ; 32];
+        let mut writer = Writer::new(&mut buf);
+        writer.unsigned_vb(1);
+        writer.unsigned_vb(300);
+        writer.unsigned_vb(u32::MAX);
+        writer.signed_vb(-1);
+        writer.signed_vb(i32::MIN);
+        assert_eq!(&writer.written()[..3], &[0x01, 0xAC, 0x02]);
+
+        let mut reader = Reader::new(writer.written());
+        assert_eq!(reader.unsigned_vb(), Some(1));
+        assert_eq!(reader.unsigned_vb(), Some(300));
+        assert_eq!(reader.unsigned_vb(), Some(u32::MAX));
+        assert_eq!(reader.signed_vb(), Some(-1));
+        assert_eq!(reader.signed_vb(), Some(i32::MIN));
+        assert_eq!(reader.unsigned_vb(), None);
+    }
+}
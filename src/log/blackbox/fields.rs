@@ -0,0 +1,205 @@
+//! Fields recorded in each blackbox frame, with the predictor and encoding used for each
+
+use crate::MAX_MOTORS;
+
+/// Value subtracted from a field before it is encoded, numbered as in Betaflight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Predictor {
+    /// The raw value is encoded
+    Zero = 0,
+    /// The value of the field in the previous frame
+    Previous = 1,
+    /// Extrapolation from the two previous frames, `2 * previous - previous2`
+    StraightLine = 2,
+    /// The previous value plus the number of loop iterations since the previous frame
+    Increment = 6,
+}
+
+impl Predictor {
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Zero),
+            1 => Some(Self::Previous),
+            2 => Some(Self::StraightLine),
+            6 => Some(Self::Increment),
+            _ => None,
+        }
+    }
+
+    /// Predict a field's value from the two frames before it, where `skipped` is the number of
+    /// loop iterations that were not logged since the previous frame
+    pub const fn predict(&self, previous: i32, previous2: i32, skipped: u32) -> i32 {
+        match self {
+            Self::Zero => 0,
+            Self::Previous => previous,
+            Self::StraightLine => previous.wrapping_mul(2).wrapping_sub(previous2),
+            Self::Increment => previous.wrapping_add(skipped as i32).wrapping_add(1),
+        }
+    }
+}
+
+/// Way that the difference from the predicted value is written, numbered as in Betaflight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    SignedVb = 0,
+    UnsignedVb = 1,
+    /// Nothing is written as the prediction is exact
+    Null = 9,
+}
+
+impl Encoding {
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::SignedVb),
+            1 => Some(Self::UnsignedVb),
+            9 => Some(Self::Null),
+            _ => None,
+        }
+    }
+}
+
+/// Definition of one field of the main frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub signed: bool,
+    pub i_predictor: Predictor,
+    pub i_encoding: Encoding,
+    pub p_predictor: Predictor,
+    pub p_encoding: Encoding,
+}
+
+impl Field {
+    /// Field that is written raw in I-frames and as a delta from the previous frame in P-frames
+    const fn delta(name: &'static str, signed: bool) -> Self {
+        Self {
+            name,
+            signed,
+            i_predictor: Predictor::Zero,
+            i_encoding: if signed { Encoding::SignedVb } else { Encoding::UnsignedVb },
+            p_predictor: Predictor::Previous,
+            p_encoding: Encoding::SignedVb,
+        }
+    }
+}
+
+pub const LOOP_ITERATION: usize = 0;
+pub const TIME: usize = 1;
+pub const AXIS_P: usize = 2;
+pub const AXIS_I: usize = 5;
+pub const AXIS_D: usize = 8;
+pub const AXIS_F: usize = 11;
+pub const RC_COMMAND: usize = 14;
+pub const SETPOINT: usize = 18;
+pub const GYRO: usize = 22;
+pub const ACCEL: usize = 25;
+pub const ATTITUDE: usize = 28;
+pub const MOTOR: usize = 31;
+
+/// Number of fields logged for every vehicle, before the motor outputs
+pub const BASE_FIELDS: usize = MOTOR;
+
+/// Largest number of fields in a frame
+pub const MAX_FIELDS: usize = MOTOR + MAX_MOTORS;
+
+/// Every field that may be logged, in frame order. Only the motors the vehicle has are logged,
+/// so the fields of a log are the first `BASE_FIELDS + motor_count` entries.
+///
+/// Names follow Betaflight where Blackbox Explorer knows the field. Units are:
+///  - `axisP`..`axisF`: PID term outputs in thousandths of full scale
+///  - `rcCommand`: roll, pitch and yaw in [-500, 500] and throttle in [1000, 2000]
+///  - `setpoint`: roll, pitch and yaw rates in deg/s and throttle in [0, 1000]
+///  - `gyroADC`: deg/s
+///  - `accSmooth`: thousandths of a g, see the `acc_1G` header
+///  - `attitude`: roll, pitch and yaw in tenths of a degree
+///  - `motor`: thousandths of full throttle
+pub static FIELDS: [Field ; MAX_FIELDS] = [
+    Field {
+        name: "loopIteration",
+        signed: false,
+        i_predictor: Predictor::Zero,
+        i_encoding: Encoding::UnsignedVb,
+        p_predictor: Predictor::Increment,
+        p_encoding: Encoding::Null,
+    },
+    Field {
+        name: "time",
+        signed: false,
+        i_predictor: Predictor::Zero,
+        i_encoding: Encoding::UnsignedVb,
+        p_predictor: Predictor::StraightLine,
+        p_encoding: Encoding::SignedVb,
+    },
+    Field::delta("axisP[0]", true),
+    Field::delta("axisP[1]", true),
+    Field::delta("axisP[2]", true),
+    Field::delta("axisI[0]", true),
+    Field::delta("axisI[1]", true),
+    Field::delta("axisI[2]", true),
+    Field::delta("axisD[0]", true),
+    Field::delta("axisD[1]", true),
+    Field::delta("axisD[2]", true),
+    Field::delta("axisF[0]", true),
+    Field::delta("axisF[1]", true),
+    Field::delta("axisF[2]", true),
+    Field::delta("rcCommand[0]", true),
+    Field::delta("rcCommand[1]", true),
+    Field::delta("rcCommand[2]", true),
+    Field::delta("rcCommand[3]", false),
+    Field::delta("setpoint[0]", true),
+    Field::delta("setpoint[1]", true),
+    Field::delta("setpoint[2]", true),
+    Field::delta("setpoint[3]", false),
+    Field::delta("gyroADC[0]", true),
+    Field::delta("gyroADC[1]", true),
+    Field::delta("gyroADC[2]", true),
+    Field::delta("accSmooth[0]", true),
+    Field::delta("accSmooth[1]", true),
+    Field::delta("accSmooth[2]", true),
+    Field::delta("attitude[0]", true),
+    Field::delta("attitude[1]", true),
+    Field::delta("attitude[2]", true),
+    Field::delta("motor[0]", false),
+    Field::delta("motor[1]", false),
+    Field::delta("motor[2]", false),
+    Field::delta("motor[3]", false),
+    Field::delta("motor[4]", false),
+    Field::delta("motor[5]", false),
+    Field::delta("motor[6]", false),
+    Field::delta("motor[7]", false),
+];
+
+/// Type of an event frame, numbered as in Betaflight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// Written when the vehicle arms, carrying the time in microseconds
+    SyncBeep = 0,
+    /// Written when the vehicle disarms, carrying the reason
+    Disarm = 15,
+    /// Written when the flight mode flags change, carrying the new and old flags
+    FlightMode = 30,
+    /// Final frame of a log, followed by [LOG_END_MESSAGE]
+    LogEnd = 255,
+}
+
+impl EventKind {
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::SyncBeep),
+            15 => Some(Self::Disarm),
+            30 => Some(Self::FlightMode),
+            255 => Some(Self::LogEnd),
+            _ => None,
+        }
+    }
+}
+
+/// Text following a [EventKind::LogEnd] event
+pub const LOG_END_MESSAGE: &[u8] = b"End of log\0";
+
+/// Flight mode flag set while failsafe is active, matching Betaflight's `FAILSAFE_MODE`
+pub const FLIGHT_MODE_FAILSAFE: u32 = 1 << 10;
+
+pub const FRAME_INTRA: u8 = b'I';
+pub const FRAME_INTER: u8 = b'P';
+pub const FRAME_EVENT: u8 = b'E';
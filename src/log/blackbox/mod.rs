@@ -0,0 +1,645 @@
+//! Flight data recorder producing logs in the Betaflight blackbox format, so that they can be
+//! viewed in Blackbox Explorer.
+//!
+//! A log starts with text header lines describing the fields, followed by binary frames. Intra
+//! (I) frames hold every field, while inter (P) frames hold only the difference of each field
+//! from a prediction based on the previous frames, which is usually small. Values are written
+//! as variable-byte integers so small differences take a single byte.
+
+use core::fmt::Write;
+
+use heapless::String;
+use nalgebra::{UnitQuaternion, Vector3};
+
+use crate::{
+    control::ControlTelemetry,
+    interface::rc::{Modes, RcCommand},
+    log::ring::RingBuffer,
+    state::{self, FlightState, StateHooks},
+    Micros,
+    MAX_MOTORS,
+};
+use encoding::{Writer, MAX_VB_LEN};
+use fields::{EventKind, Encoding, FIELDS, BASE_FIELDS, MAX_FIELDS};
+
+pub mod decode;
+pub mod encoding;
+pub mod fields;
+
+/// Largest size of an encoded main frame
+pub const MAX_FRAME_LEN: usize = 1 + MAX_FIELDS * MAX_VB_LEN;
+
+/// Largest size of a header line, which must hold the longest field list
+const HEADER_LINE_MAX: usize = 512;
+
+/// Reason for disarming recorded in a disarm event, numbered as in Betaflight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisarmReason {
+    Failsafe = 1,
+    Switch = 4,
+    Crash = 5,
+}
+
+impl DisarmReason {
+    pub const fn from_id(id: u32) -> Option<Self> {
+        match id {
+            1 => Some(Self::Failsafe),
+            4 => Some(Self::Switch),
+            5 => Some(Self::Crash),
+            _ => None,
+        }
+    }
+}
+
+/// Event recorded between frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The vehicle armed at the given time
+    Armed { time: Micros },
+    Disarmed { reason: DisarmReason },
+    /// The flight mode flags changed, see [fields::FLIGHT_MODE_FAILSAFE]
+    FlightMode { flags: u32, previous: u32 },
+}
+
+/// Destination for recorded bytes, such as a queue drained to flash
+pub trait Sink {
+    /// Get the number of bytes that can be written without any being dropped
+    fn free(&self) -> usize;
+
+    /// Write as many bytes as will fit, returning the number written. Must not block
+    fn write(&mut self, bytes: &[u8]) -> usize;
+}
+
+impl<const N: usize> Sink for RingBuffer<N> {
+    fn free(&self) -> usize {
+        RingBuffer::free(self)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> usize {
+        self.push(bytes)
+    }
+}
+
+/// Recording options
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Record one of every `rate_divisor` control cycles
+    pub rate_divisor: u16,
+    /// Number of recorded frames between I-frames
+    pub iframe_interval: u16,
+    /// Number of motor outputs to record, at most [MAX_MOTORS]
+    pub motor_count: usize,
+    /// Period of the control loop in microseconds
+    pub looptime: Micros,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rate_divisor: 1,
+            iframe_interval: 32,
+            motor_count: 4,
+            looptime: 125,
+        }
+    }
+}
+
+/// Values from one control cycle, in the units used by the flight stack
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapshot {
+    pub time: Micros,
+    /// Angular velocity in rad/s
+    pub gyro: Vector3<f32>,
+    /// Linear acceleration in g
+    pub accel: Vector3<f32>,
+    pub attitude: UnitQuaternion<f32>,
+    pub command: RcCommand<f32>,
+    pub control: ControlTelemetry<f32>,
+    /// Motor outputs in [0, 1]
+    pub motors: [f32 ; MAX_MOTORS],
+}
+
+impl Snapshot {
+    /// Convert to the integer field values described by [FIELDS]
+    pub fn fields(&self) -> [i32 ; MAX_FIELDS] {
+        use fields::*;
+
+        let mut values = [0i32 ; MAX_FIELDS];
+        values[TIME] = self.time as i32;
+        for axis in 0..3 {
+            values[AXIS_P + axis] = scale(self.control.p[axis], 1000.0);
+            values[AXIS_I + axis] = scale(self.control.i[axis], 1000.0);
+            values[AXIS_D + axis] = scale(self.control.d[axis], 1000.0);
+            values[AXIS_F + axis] = scale(self.control.f[axis], 1000.0);
+            values[SETPOINT + axis] = scale(self.control.setpoint[axis].to_degrees(), 1.0);
+            values[GYRO + axis] = scale(self.gyro[axis].to_degrees(), 1.0);
+            values[ACCEL + axis] = scale(self.accel[axis], ACC_1G as f32);
+        }
+
+        values[RC_COMMAND] = scale(self.command.roll, 500.0);
+        values[RC_COMMAND + 1] = scale(self.command.pitch, 500.0);
+        values[RC_COMMAND + 2] = scale(self.command.yaw, 500.0);
+        values[RC_COMMAND + 3] = 1000 + scale(self.command.throttle, 1000.0);
+        values[SETPOINT + 3] = scale(self.command.throttle, 1000.0);
+
+        let (roll, pitch, yaw) = self.attitude.euler_angles();
+        values[ATTITUDE] = scale(roll.to_degrees(), 10.0);
+        values[ATTITUDE + 1] = scale(pitch.to_degrees(), 10.0);
+        values[ATTITUDE + 2] = scale(yaw.to_degrees(), 10.0);
+
+        for (value, motor) in values[MOTOR..].iter_mut().zip(self.motors) {
+            *value = scale(motor, 1000.0);
+        }
+
+        values
+    }
+}
+
+/// Accelerometer reading of 1 g in the units of the `accSmooth` fields
+pub const ACC_1G: u32 = 1000;
+
+/// Multiply and round to the nearest integer
+fn scale(value: f32, factor: f32) -> i32 {
+    let value = value * factor;
+    match value < 0.0 {
+        true => (value - 0.5) as i32,
+        false => (value + 0.5) as i32,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Stopped,
+    /// Writing the header line with the given index
+    Header(usize),
+    Recording,
+    /// Waiting to write the end of log event
+    Ending,
+}
+
+/// Blackbox recorder writing frames to a [Sink].
+///
+/// The recorder never waits for the sink. The header is written over as many cycles as needed,
+/// and frames that do not fit are dropped, with the next frame written as an I-frame so the log
+/// can still be decoded.
+///
+/// Also implements [StateHooks] to record arm, disarm and failsafe events, and to start and stop
+/// the log with the [BLACKBOX](Modes::BLACKBOX) mode switch.
+pub struct Recorder<S: Sink> {
+    sink: S,
+    config: Config,
+    state: State,
+    /// Header line being written and the number of its bytes already written
+    line: String<HEADER_LINE_MAX>,
+    line_written: usize,
+    iteration: u32,
+    /// Field values of the last two recorded frames
+    history: [[i32 ; MAX_FIELDS] ; 2],
+    need_iframe: bool,
+    time: Micros,
+    dropped: u32,
+}
+
+impl<S: Sink> Recorder<S> {
+    pub fn new(sink: S, config: Config) -> Self {
+        Self {
+            sink,
+            config: Config {
+                rate_divisor: config.rate_divisor.max(1),
+                iframe_interval: config.iframe_interval.max(1),
+                motor_count: config.motor_count.min(MAX_MOTORS),
+                ..config
+            },
+            state: State::Stopped,
+            line: String::new(),
+            line_written: 0,
+            iteration: 0,
+            history: [[0i32 ; MAX_FIELDS] ; 2],
+            need_iframe: true,
+            time: 0,
+            dropped: 0,
+        }
+    }
+
+    pub const fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub const fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Check if a log is in progress, including while its header or end are being written
+    pub const fn is_recording(&self) -> bool {
+        !matches!(self.state, State::Stopped)
+    }
+
+    /// Get the number of frames and events dropped because the sink was full
+    pub const fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Start a new log, beginning with its header. Does nothing if a log is in progress
+    pub fn start(&mut self) {
+        if self.is_recording() {
+            return
+        }
+
+        self.state = State::Header(0);
+        self.line.clear();
+        self.line_written = 0;
+        self.iteration = 0;
+        self.need_iframe = true;
+    }
+
+    /// Finish the current log with an end of log event
+    pub fn stop(&mut self) {
+        self.state = match self.state {
+            State::Stopped | State::Header(_) => State::Stopped,
+            State::Recording | State::Ending => State::Ending,
+        };
+        self.poll();
+    }
+
+    /// Continue writing the header or end of log, if either is in progress
+    pub fn poll(&mut self) {
+        while let State::Header(index) = self.state {
+            if self.line_written == self.line.len() {
+                self.line.clear();
+                self.line_written = 0;
+                if !header_line(index, &self.config, &mut self.line) {
+                    self.state = State::Recording;
+                    break
+                }
+                self.state = State::Header(index + 1);
+            }
+
+            self.line_written += self.sink.write(&self.line.as_bytes()[self.line_written..]);
+            if self.line_written < self.line.len() {
+                break
+            }
+        }
+
+        if self.state == State::Ending {
+            let mut buf = [0u8 ; 2 + fields::LOG_END_MESSAGE.len()];
+            let mut writer = Writer::new(&mut buf);
+            writer.byte(fields::FRAME_EVENT);
+            writer.byte(EventKind::LogEnd as u8);
+            writer.bytes(fields::LOG_END_MESSAGE);
+            if self.sink.free() >= buf.len() {
+                self.sink.write(&buf);
+                self.state = State::Stopped;
+            }
+        }
+    }
+
+    /// Record one control cycle. Must be called every cycle, as only one of every
+    /// `rate_divisor` calls records a frame
+    pub fn record(&mut self, snapshot: &Snapshot) {
+        self.poll();
+        if self.state != State::Recording {
+            return
+        }
+
+        let iteration = self.iteration;
+        self.iteration = self.iteration.wrapping_add(1);
+        self.time = snapshot.time;
+        let divisor = self.config.rate_divisor as u32;
+        if !iteration.is_multiple_of(divisor) {
+            return
+        }
+
+        let mut values = snapshot.fields();
+        values[fields::LOOP_ITERATION] = iteration as i32;
+        let intra = self.need_iframe || iteration.is_multiple_of(divisor * self.config.iframe_interval as u32);
+
+        let mut buf = [0u8 ; MAX_FRAME_LEN];
+        let mut writer = Writer::new(&mut buf);
+        let count = BASE_FIELDS + self.config.motor_count;
+        match intra {
+            true => {
+                writer.byte(fields::FRAME_INTRA);
+                for (field, &value) in FIELDS[..count].iter().zip(&values) {
+                    let predicted = field.i_predictor.predict(0, 0, 0);
+                    encode(&mut writer, field.i_encoding, value.wrapping_sub(predicted));
+                }
+            },
+            false => {
+                writer.byte(fields::FRAME_INTER);
+                for (i, field) in FIELDS[..count].iter().enumerate() {
+                    let predicted = field.p_predictor.predict(self.history[0][i], self.history[1][i], divisor - 1);
+                    encode(&mut writer, field.p_encoding, values[i].wrapping_sub(predicted));
+                }
+            },
+        }
+
+        let frame = writer.written();
+        if self.sink.free() < frame.len() {
+            self.dropped += 1;
+            self.need_iframe = true;
+            return
+        }
+        self.sink.write(frame);
+
+        self.history[1] = match intra {
+            true => values,
+            false => self.history[0],
+        };
+        self.history[0] = values;
+        self.need_iframe = false;
+    }
+
+    /// Record an event between frames. Events are only recorded once the header is complete
+    pub fn event(&mut self, event: Event) {
+        if self.state != State::Recording {
+            return
+        }
+
+        let mut buf = [0u8 ; 2 + 2 * MAX_VB_LEN];
+        let mut writer = Writer::new(&mut buf);
+        writer.byte(fields::FRAME_EVENT);
+        match event {
+            Event::Armed { time } => {
+                writer.byte(EventKind::SyncBeep as u8);
+                writer.unsigned_vb(time);
+            },
+            Event::Disarmed { reason } => {
+                writer.byte(EventKind::Disarm as u8);
+                writer.unsigned_vb(reason as u32);
+            },
+            Event::FlightMode { flags, previous } => {
+                writer.byte(EventKind::FlightMode as u8);
+                writer.unsigned_vb(flags);
+                writer.unsigned_vb(previous);
+            },
+        }
+
+        let event = writer.written();
+        match self.sink.free() < event.len() {
+            true => self.dropped += 1,
+            false => { self.sink.write(event); },
+        }
+    }
+}
+
+impl<S: Sink> StateHooks for Recorder<S> {
+    fn on_transition(&mut self, from: FlightState, to: FlightState, event: state::Event) {
+        if to == FlightState::Failsafe {
+            self.event(Event::FlightMode { flags: fields::FLIGHT_MODE_FAILSAFE, previous: 0 });
+        } else if from == FlightState::Failsafe {
+            self.event(Event::FlightMode { flags: 0, previous: fields::FLIGHT_MODE_FAILSAFE });
+        }
+
+        if !from.is_armed() && to.is_armed() {
+            self.event(Event::Armed { time: self.time });
+        } else if from.is_armed() && !to.is_armed() {
+            let reason = match event {
+                state::Event::LinkLost => DisarmReason::Failsafe,
+                state::Event::CrashDetected => DisarmReason::Crash,
+                _ => DisarmReason::Switch,
+            };
+            self.event(Event::Disarmed { reason });
+        }
+    }
+
+    fn on_modes_changed(&mut self, previous: Modes, modes: Modes) {
+        match (previous.contains(Modes::BLACKBOX), modes.contains(Modes::BLACKBOX)) {
+            (false, true) => self.start(),
+            (true, false) => self.stop(),
+            _ => {},
+        }
+    }
+}
+
+fn encode(writer: &mut Writer<'_>, encoding: Encoding, value: i32) {
+    match encoding {
+        Encoding::SignedVb => writer.signed_vb(value),
+        Encoding::UnsignedVb => writer.unsigned_vb(value as u32),
+        Encoding::Null => {},
+    }
+}
+
+/// Write the header line with the given index, returning false once past the last line
+fn header_line<W: Write>(index: usize, config: &Config, out: &mut W) -> bool {
+    let fields = &FIELDS[..BASE_FIELDS + config.motor_count];
+    let result = match index {
+        0 => out.write_str("H Product:Blackbox flight data recorder by Nicholas Sherlock\n"),
+        1 => out.write_str("H Data version:2\n"),
+        2 => writeln!(out, "H I interval:{}", config.iframe_interval as u32 * config.rate_divisor as u32),
+        3 => writeln!(out, "H P interval:1/{}", config.rate_divisor),
+        4 => field_line(out, "I name", fields.iter().map(|f| FieldValue::Name(f.name))),
+        5 => field_line(out, "I signed", fields.iter().map(|f| FieldValue::Id(f.signed as u8))),
+        6 => field_line(out, "I predictor", fields.iter().map(|f| FieldValue::Id(f.i_predictor as u8))),
+        7 => field_line(out, "I encoding", fields.iter().map(|f| FieldValue::Id(f.i_encoding as u8))),
+        8 => field_line(out, "P predictor", fields.iter().map(|f| FieldValue::Id(f.p_predictor as u8))),
+        9 => field_line(out, "P encoding", fields.iter().map(|f| FieldValue::Id(f.p_encoding as u8))),
+        10 => out.write_str("H Firmware type:Cleanflight\n"),
+        11 => writeln!(out, "H Firmware revision:Bingo FC {}", env!("CARGO_PKG_VERSION")),
+        12 => out.write_str("H Log start datetime:0000-01-01T00:00:00.000+00:00\n"),
+        13 => writeln!(out, "H looptime:{}", config.looptime),
+        // Gyro values are in deg/s, which Blackbox Explorer already converts to rad/s per
+        // microsecond for Cleanflight logs, so the scale is 1.0 as Betaflight writes it
+        14 => writeln!(out, "H gyro_scale:0x{:08x}", 1f32.to_bits()),
+        15 => writeln!(out, "H acc_1G:{ACC_1G}"),
+        16 => out.write_str("H motorOutput:0,1000\n"),
+        17 => out.write_str("H minthrottle:1000\n"),
+        18 => out.write_str("H maxthrottle:2000\n"),
+        _ => return false,
+    };
+
+    debug_assert!(result.is_ok(), "Header line {index} does not fit");
+    true
+}
+
+enum FieldValue {
+    Name(&'static str),
+    Id(u8),
+}
+
+fn field_line<W: Write>(out: &mut W, kind: &str, values: impl Iterator<Item = FieldValue>) -> core::fmt::Result {
+    write!(out, "H Field {kind}:")?;
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        match value {
+            FieldValue::Name(name) => out.write_str(name)?,
+            FieldValue::Id(id) => write!(out, "{id}")?,
+        }
+    }
+    out.write_char('\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::Reader;
+    use fields::Predictor;
+
+    fn snapshot(time: Micros, roll_rate: f32) -> Snapshot {
+        Snapshot {
+            time,
+            gyro: Vector3::new(roll_rate, 0.0, 0.0),
+            accel: Vector3::new(0.0, 0.0, 1.0),
+            attitude: UnitQuaternion::identity(),
+            command: RcCommand { throttle: 0.5, ..Default::default() },
+            control: ControlTelemetry::default(),
+            motors: [0.5 ; MAX_MOTORS],
+        }
+    }
+
+    /// Split a log into its header text and the binary frames after it
+    fn split(log: &[u8]) -> (&str, &[u8]) {
+        let mut end = 0;
+        while log[end..].starts_with(b"H ") {
+            end += log[end..].iter().position(|&b| b == b'\n').unwrap() + 1;
+        }
+        (core::str::from_utf8(&log[..end]).unwrap(), &log[end..])
+    }
+
+    fn drain<const N: usize>(recorder: &mut Recorder<RingBuffer<N>>) -> Vec<u8> {
+        let mut log = vec![0u8 ; N];
+        let len = recorder.sink_mut().pop(&mut log);
+        log.truncate(len);
+        log
+    }
+
+    #[test]
+    fn test_header_fields() {
+        let mut recorder = Recorder::new(RingBuffer::<4096>::new(), Config { rate_divisor: 2, ..Default::default() });
+        recorder.start();
+        recorder.poll();
+        let log = drain(&mut recorder);
+        let (header, frames) = split(&log);
+        assert!(frames.is_empty());
+        assert!(header.contains("H I interval:64\n"));
+        assert!(header.contains("H P interval:1/2\n"));
+
+        let names = header.lines().find_map(|l| l.strip_prefix("H Field I name:")).unwrap();
+        let names: Vec<_> = names.split(',').collect();
+        assert_eq!(names.len(), BASE_FIELDS + 4);
+        assert_eq!(names[fields::GYRO], "gyroADC[0]");
+        assert_eq!(names.last(), Some(&"motor[3]"));
+
+        let gyro_scale = header.lines().find_map(|l| l.strip_prefix("H gyro_scale:0x")).unwrap();
+        assert_eq!(f32::from_bits(u32::from_str_radix(gyro_scale, 16).unwrap()), 1.0);
+        assert!(header.contains("H gyro_scale:0x3f800000\n"));
+    }
+
+    #[test]
+    fn test_header_written_across_polls() {
+        let mut recorder = Recorder::new(RingBuffer::<64>::new(), Config::default());
+        recorder.start();
+        let mut log = Vec::new();
+        for i in 0..100 {
+            recorder.record(&snapshot(i * 125, 0.0));
+            log.extend(drain(&mut recorder));
+        }
+
+        let (header, frames) = split(&log);
+        assert!(header.ends_with("H maxthrottle:2000\n"));
+        assert_eq!(frames.first(), Some(&fields::FRAME_INTRA));
+    }
+
+    #[test]
+    fn test_frames_decode() {
+        let config = Config { rate_divisor: 2, iframe_interval: 4, ..Default::default() };
+        let mut recorder = Recorder::new(RingBuffer::<8192>::new(), config);
+        recorder.start();
+        recorder.poll();
+        drain(&mut recorder);
+
+        let snapshots: Vec<_> = (0..16u32).map(|i| snapshot(1000 + i * 125, i as f32 * 0.1)).collect();
+        for snapshot in &snapshots {
+            recorder.record(snapshot);
+        }
+        let log = drain(&mut recorder);
+
+        // Decode by applying the predictors, as Blackbox Explorer does
+        let count = BASE_FIELDS + config.motor_count;
+        let mut reader = Reader::new(&log);
+        let mut history = [[0i32 ; MAX_FIELDS] ; 2];
+        let mut kinds = Vec::new();
+        for snapshot in snapshots.iter().step_by(2) {
+            let kind = reader.byte().unwrap();
+            kinds.push(kind);
+            let mut values = [0i32 ; MAX_FIELDS];
+            for (i, field) in FIELDS[..count].iter().enumerate() {
+                let (predictor, encoding) = match kind {
+                    fields::FRAME_INTRA => (field.i_predictor, field.i_encoding),
+                    _ => (field.p_predictor, field.p_encoding),
+                };
+                let delta = match encoding {
+                    Encoding::SignedVb => reader.signed_vb().unwrap(),
+                    Encoding::UnsignedVb => reader.unsigned_vb().unwrap() as i32,
+                    Encoding::Null => 0,
+                };
+                values[i] = predictor.predict(history[0][i], history[1][i], 1).wrapping_add(delta);
+            }
+
+            let mut expected = snapshot.fields();
+            expected[fields::LOOP_ITERATION] = values[fields::LOOP_ITERATION];
+            assert_eq!(values[..count], expected[..count]);
+            history[1] = if kind == fields::FRAME_INTRA { values } else { history[0] };
+            history[0] = values;
+        }
+
+        assert!(reader.remaining().is_empty());
+        assert_eq!(kinds, b"IPPPIPPP");
+        assert_eq!(Predictor::Increment.predict(0, 0, 1), 2);
+    }
+
+    #[test]
+    fn test_dropped_frame_forces_iframe() {
+        let mut recorder = Recorder::new(RingBuffer::<2048>::new(), Config::default());
+        recorder.start();
+        recorder.poll();
+        drain(&mut recorder);
+
+        recorder.record(&snapshot(0, 0.0));
+        recorder.sink_mut().push(&[0u8 ; 2048]);
+        recorder.record(&snapshot(125, 0.0));
+        assert_eq!(recorder.dropped(), 1);
+
+        recorder.sink_mut().clear();
+        recorder.record(&snapshot(250, 0.0));
+        assert_eq!(drain(&mut recorder).first(), Some(&fields::FRAME_INTRA));
+    }
+
+    #[test]
+    fn test_state_events() {
+        let mut recorder = Recorder::new(RingBuffer::<4096>::new(), Config::default());
+        recorder.start();
+        recorder.record(&snapshot(5000, 0.0));
+        drain(&mut recorder);
+
+        recorder.on_transition(FlightState::Disarmed, FlightState::Armed, state::Event::ArmRequested);
+        recorder.on_transition(FlightState::Armed, FlightState::Failsafe, state::Event::LinkLost);
+        recorder.stop();
+        assert!(!recorder.is_recording());
+
+        let log = drain(&mut recorder);
+        let mut expected = vec![b'E', 0, 0x88, 0x27, b'E', 30, 0x80, 0x08, 0, b'E', 15, 1, b'E', 255];
+        expected.extend_from_slice(fields::LOG_END_MESSAGE);
+        assert_eq!(log, expected);
+    }
+
+    #[test]
+    fn test_blackbox_switch() {
+        let mut recorder = Recorder::new(RingBuffer::<4096>::new(), Config::default());
+        recorder.on_modes_changed(Modes::empty(), Modes::ARM);
+        assert!(!recorder.is_recording());
+
+        recorder.on_modes_changed(Modes::ARM, Modes::ARM | Modes::BLACKBOX);
+        assert!(recorder.is_recording());
+        recorder.on_modes_changed(Modes::ARM | Modes::BLACKBOX, Modes::BLACKBOX);
+        assert!(recorder.is_recording());
+
+        recorder.on_modes_changed(Modes::BLACKBOX, Modes::empty());
+        assert!(!recorder.is_recording());
+    }
+}
@@ -0,0 +1,156 @@
+use heapless::String;
+
+use crate::Micros;
+
+use super::{Level, LevelFilter, Logger, Record};
+
+/// Longest module path that can be given its own level
+pub const MAX_MODULE_LEN: usize = 48;
+
+/// Logger wrapper that filters messages by level per module and stamps them with the time
+pub struct Filtered<L: Logger, const N: usize = 8> {
+    inner: L,
+    default: LevelFilter,
+    modules: heapless::Vec<(String<MAX_MODULE_LEN>, LevelFilter), N>,
+    clock: Option<fn() -> Micros>,
+}
+
+/// Error returned when a module level can't be stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    /// The module path is longer than [MAX_MODULE_LEN]
+    PathTooLong,
+    /// Levels are already set for the maximum number of modules
+    Full,
+}
+
+impl<L: Logger, const N: usize> Filtered<L, N> {
+    /// Wrap a logger, allowing messages up to `default` from all modules
+    pub const fn new(inner: L, default: LevelFilter) -> Self {
+        Self {
+            inner,
+            default,
+            modules: heapless::Vec::new(),
+            clock: None,
+        }
+    }
+
+    /// Stamp messages with the time returned by `clock` in microseconds
+    pub fn with_clock(mut self, clock: fn() -> Micros) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Set the level used for modules without their own level
+    pub fn set_default(&mut self, level: LevelFilter) {
+        self.default = level;
+    }
+
+    /// Set the level for a module and all of its submodules
+    pub fn set_level(&mut self, module: &str, level: LevelFilter) -> Result<(), FilterError> {
+        if let Some((_, existing)) = self.modules.iter_mut().find(|(m, _)| m == module) {
+            *existing = level;
+            return Ok(())
+        }
+
+        let module = String::try_from(module).map_err(|_| FilterError::PathTooLong)?;
+        self.modules.push((module, level)).map_err(|_| FilterError::Full)
+    }
+
+    /// Remove a module's level, so that it uses the level of its parent or the default
+    pub fn clear_level(&mut self, module: &str) {
+        self.modules.retain(|(m, _)| m != module);
+    }
+
+    /// Get the level applied to messages from the given module
+    pub fn level(&self, module: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(m, _)| Self::contains(m, module))
+            .max_by_key(|(m, _)| m.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    pub const fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut L {
+        &mut self.inner
+    }
+
+    /// Check if `module` is `parent` or one of its submodules
+    fn contains(parent: &str, module: &str) -> bool {
+        match module.strip_prefix(parent) {
+            Some(rest) => rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        }
+    }
+}
+
+impl<L: Logger, const N: usize> core::fmt::Write for Filtered<L, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.inner.write_str(s)
+    }
+}
+
+impl<L: Logger, const N: usize> Logger for Filtered<L, N> {
+    fn enabled(&self, level: Level, module: &str) -> bool {
+        self.level(module).allows(level) && self.inner.enabled(level, module)
+    }
+
+    fn timestamp(&self) -> Option<Micros> {
+        self.clock.map(|clock| clock())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> core::fmt::Result {
+        self.inner.write_bytes(bytes)
+    }
+
+    fn log(&mut self, record: &Record<'_>) {
+        self.inner.log(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::{tests::TestLog, STATIC_MAX_LEVEL};
+
+    #[test]
+    fn test_module_levels() {
+        let mut log = Filtered::<_, 4>::new(TestLog::default(), LevelFilter::Info);
+        log.set_level("bingo_fc::ahrs", LevelFilter::Off).unwrap();
+        log.set_level("bingo_fc::ahrs::madgwick", LevelFilter::Trace).unwrap();
+
+        assert_eq!(log.level("bingo_fc::state"), LevelFilter::Info);
+        assert_eq!(log.level("bingo_fc::ahrs"), LevelFilter::Off);
+        assert_eq!(log.level("bingo_fc::ahrs::mahony"), LevelFilter::Off);
+        assert_eq!(log.level("bingo_fc::ahrs::madgwick"), LevelFilter::Trace);
+        assert_eq!(log.level("bingo_fc::ahrsx"), LevelFilter::Info);
+
+        log.clear_level("bingo_fc::ahrs");
+        assert_eq!(log.level("bingo_fc::ahrs::mahony"), LevelFilter::Info);
+    }
+
+    #[test]
+    fn test_filtered_macros() {
+        // The max-level features compile these messages out
+        if !STATIC_MAX_LEVEL.allows(Level::Debug) {
+            return;
+        }
+        let mut log = Filtered::<_, 4>::new(TestLog::default(), LevelFilter::Warn)
+            .with_clock(|| 1_500_000);
+
+        crate::info!(log, "hidden");
+        crate::error!(log, "shown {}", 1);
+        assert_eq!(log.inner().0, "    1.500000 [ERROR] bingo_fc::log::filter::tests: shown 1\r\n");
+
+        log.set_level("bingo_fc::log", LevelFilter::Debug).unwrap();
+        crate::debug!(log, "now shown");
+        crate::trace!(log, "still hidden");
+        assert!(log.inner().0.ends_with("now shown\r\n"));
+        assert!(!log.inner().0.contains("hidden"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Log storage appending to NOR flash without blocking the caller
+
+use crate::interface::flash::{NorFlash, ERASED};
+
+use super::{blackbox::Sink, ring::RingBuffer};
+
+/// Append-only log on a [NorFlash] memory.
+///
+/// Written bytes are queued in RAM and moved to flash by [FlashLog::poll], which starts at most
+/// one flash operation per call and never waits for one to finish. Erase regions are erased
+/// ahead of the write position, so that space is ready before it is needed.
+pub struct FlashLog<F: NorFlash, const N: usize = 1024> {
+    flash: F,
+    queue: RingBuffer<N>,
+    /// Address that the next byte will be programmed at
+    write_address: u32,
+    /// End of the erased region following the write address
+    erased_to: u32,
+    flush: bool,
+}
+
+impl<F: NorFlash, const N: usize> FlashLog<F, N> {
+    /// Open the log on a flash memory, finding the end of the data already written. The memory
+    /// must be erased or hold a log written from address 0, as the end is found by bisection.
+    /// Reads are blocking, so this should be done during startup
+    pub fn mount(mut flash: F) -> Result<Self, F::Error> {
+        while flash.is_busy()? {}
+
+        let page_size = flash.page_size();
+        let pages = flash.capacity() / page_size;
+
+        // Data is written in order, so every page after the first erased page is also erased
+        let (mut low, mut high) = (0, pages);
+        while low < high {
+            let page = low + (high - low) / 2;
+            match is_erased(&mut flash, page * page_size, page_size)? {
+                true => high = page,
+                false => low = page + 1,
+            }
+        }
+
+        let write_address = low * page_size;
+        let erase_size = flash.erase_size();
+        Ok(Self {
+            erased_to: write_address.div_ceil(erase_size) * erase_size,
+            flash,
+            queue: RingBuffer::new(),
+            write_address,
+            flush: false,
+        })
+    }
+
+    /// Get the number of bytes written to flash
+    pub const fn len(&self) -> u32 {
+        self.write_address
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.write_address == 0
+    }
+
+    /// Get the number of bytes waiting to be written to flash
+    pub const fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Check if the flash has no space left for more data
+    pub fn is_full(&self) -> bool {
+        self.write_address >= self.flash.capacity()
+    }
+
+    pub const fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    /// Get the flash memory, to read back the log. The memory may be busy
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Release the flash memory, discarding any queued bytes
+    pub fn into_flash(self) -> F {
+        self.flash
+    }
+
+    /// Write queued bytes that don't fill a page on the following polls
+    pub fn flush(&mut self) {
+        self.flush = true;
+    }
+
+    /// Start erasing the entire log, discarding any queued bytes
+    pub fn erase_all(&mut self) -> Result<(), F::Error> {
+        while self.flash.is_busy()? {}
+
+        self.flash.erase_all()?;
+        self.queue.clear();
+        self.write_address = 0;
+        self.erased_to = self.flash.capacity();
+        self.flush = false;
+        Ok(())
+    }
+
+    /// Start the next flash operation if the memory is idle
+    pub fn poll(&mut self) -> Result<(), F::Error> {
+        if self.flash.is_busy()? {
+            return Ok(())
+        }
+
+        let capacity = self.flash.capacity();
+        let erase_size = self.flash.erase_size();
+        if self.erased_to - self.write_address < erase_size && self.erased_to < capacity {
+            self.flash.erase(self.erased_to)?;
+            self.erased_to += erase_size;
+            return Ok(())
+        }
+
+        let page_size = self.flash.page_size();
+        let page_left = page_size - self.write_address % page_size;
+        let data = self.queue.peek();
+        let len = data.len().min(page_left as usize).min((self.erased_to - self.write_address) as usize);
+        // Wait for a full page unless flushing, as programming takes as long for any length
+        if len == 0 || !(self.queue.len() >= page_left as usize || self.flush) {
+            return Ok(())
+        }
+
+        self.flash.program(self.write_address, &data[..len])?;
+        self.queue.consume(len);
+        self.write_address += len as u32;
+        if self.queue.is_empty() {
+            self.flush = false;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_erased<F: NorFlash>(flash: &mut F, address: u32, len: u32) -> Result<bool, F::Error> {
+    let mut buf = [0u8 ; 64];
+    for offset in (0..len).step_by(buf.len()) {
+        let chunk = &mut buf[..(len - offset).min(64) as usize];
+        flash.read(address + offset, chunk)?;
+        if chunk.iter().any(|&b| b != ERASED) {
+            return Ok(false)
+        }
+    }
+
+    Ok(true)
+}
+
+impl<F: NorFlash, const N: usize> Sink for FlashLog<F, N> {
+    fn free(&self) -> usize {
+        let unqueued = self.flash.capacity() - self.write_address - self.queue.len() as u32;
+        self.queue.free().min(unqueued as usize)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> usize {
+        let len = bytes.len().min(self.free());
+        self.queue.push(&bytes[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::MockFlash;
+
+    fn poll_until_idle<F: NorFlash, const N: usize>(log: &mut FlashLog<F, N>) {
+        for _ in 0..10_000 {
+            log.poll().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_append_and_remount() {
+        let mut flash = MockFlash::<{ 64 * 1024 }>::new();
+        flash.latency = 3;
+        let mut log = FlashLog::<_, 512>::mount(flash).unwrap();
+        assert!(log.is_empty());
+
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let mut written = 0;
+        while written < data.len() {
+            written += log.write(&data[written..(written + 100).min(data.len())]);
+            log.poll().unwrap();
+        }
+        log.flush();
+        poll_until_idle(&mut log);
+        assert_eq!(log.len(), 5000);
+        assert_eq!(log.pending(), 0);
+
+        let flash = log.flash;
+        assert_eq!(&flash.memory[..5000], &data[..]);
+        // The next erase region was erased ahead of the data
+        assert!(flash.memory[5000..3 * 4096].iter().all(|&b| b == ERASED));
+
+        let log = FlashLog::<_, 512>::mount(flash).unwrap();
+        assert_eq!(log.len(), 5120);
+    }
+
+    #[test]
+    fn test_erase_all() {
+        let mut flash = MockFlash::<{ 16 * 1024 }>::new();
+        flash.memory[..9000].fill(0x5A);
+        flash.latency = 5;
+        let mut log = FlashLog::<_, 512>::mount(flash).unwrap();
+        assert_eq!(log.len(), 9216);
+
+        log.erase_all().unwrap();
+        assert!(log.is_empty());
+        log.write(&[1u8 ; 300]);
+        log.flush();
+        poll_until_idle(&mut log);
+
+        let memory = &log.flash().memory;
+        assert!(memory[..300].iter().all(|&b| b == 1));
+        assert!(memory[300..].iter().all(|&b| b == ERASED));
+    }
+
+    #[test]
+    fn test_full() {
+        let flash = MockFlash::<4096>::new();
+        let mut log = FlashLog::<_, 256>::mount(flash).unwrap();
+        let mut written = 0;
+        for _ in 0..100 {
+            written += log.write(&[0u8 ; 64]);
+            log.poll().unwrap();
+        }
+
+        assert!(log.is_full());
+        assert_eq!(written, 4096);
+        assert_eq!(log.free(), 0);
+    }
+}
@@ -1,4 +1,198 @@
+use core::fmt;
+
+use crate::Micros;
 
 pub mod usb_serial;
+pub mod ring;
+pub mod filter;
+pub mod binary;
+pub mod blackbox;
+pub mod flash;
+#[cfg(feature = "usb-msc")]
+pub mod msc;
+
+pub use filter::Filtered;
+
+/// Severity of a log message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Most verbose level of messages that will be written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LevelFilter {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelFilter {
+    /// Check if messages at the given level pass the filter
+    pub const fn allows(&self, level: Level) -> bool {
+        level as u8 <= *self as u8
+    }
+}
+
+/// Most verbose level compiled into the firmware, set by the `max-level-*` features.
+/// Log macros below this level are removed entirely
+pub const STATIC_MAX_LEVEL: LevelFilter = if cfg!(feature = "max-level-off") {
+    LevelFilter::Off
+} else if cfg!(feature = "max-level-error") {
+    LevelFilter::Error
+} else if cfg!(feature = "max-level-warn") {
+    LevelFilter::Warn
+} else if cfg!(feature = "max-level-info") {
+    LevelFilter::Info
+} else if cfg!(feature = "max-level-debug") {
+    LevelFilter::Debug
+} else {
+    LevelFilter::Trace
+};
+
+/// A single log message with the context it was written in
+pub struct Record<'a> {
+    pub level: Level,
+    /// Path of the module that wrote the message
+    pub module: &'static str,
+    /// Time the message was written in microseconds, if the logger has a clock
+    pub timestamp: Option<Micros>,
+    pub args: fmt::Arguments<'a>,
+}
+
+pub trait Logger : core::fmt::Write {
+    /// Check if messages at `level` from `module` should be written
+    fn enabled(&self, _level: Level, _module: &str) -> bool {
+        true
+    }
+
+    /// Get the current time in microseconds to stamp messages with
+    fn timestamp(&self) -> Option<Micros> {
+        None
+    }
+
+    /// Write raw bytes, used for binary log frames. Loggers that can only carry text
+    /// fail if the bytes are not valid UTF-8
+    fn write_bytes(&mut self, bytes: &[u8]) -> fmt::Result {
+        self.write_str(core::str::from_utf8(bytes).map_err(|_| fmt::Error)?)
+    }
+
+    /// Write a message as a line of text
+    fn log(&mut self, record: &Record<'_>) {
+        if let Some(t) = record.timestamp {
+            let _ = write!(self, "{:>5}.{:06} ", t / 1_000_000, t % 1_000_000);
+        }
+        let _ = write!(self, "[{:<5}] {}: {}\r\n", record.level, record.module, record.args);
+    }
+}
+
+/// Write a message at the given level through a [Logger], if enabled
+#[macro_export]
+macro_rules! log {
+    ($logger:expr, $level:expr, $($arg:tt)+) => {{
+        let level: $crate::log::Level = $level;
+        if $crate::log::STATIC_MAX_LEVEL.allows(level) {
+            let logger = &mut $logger;
+            if $crate::log::Logger::enabled(logger, level, ::core::module_path!()) {
+                let timestamp = $crate::log::Logger::timestamp(logger);
+                $crate::log::Logger::log(logger, &$crate::log::Record {
+                    level,
+                    module: ::core::module_path!(),
+                    timestamp,
+                    args: ::core::format_args!($($arg)+),
+                });
+            }
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! error {
+    ($logger:expr, $($arg:tt)+) => { $crate::log!($logger, $crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($logger:expr, $($arg:tt)+) => { $crate::log!($logger, $crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($logger:expr, $($arg:tt)+) => { $crate::log!($logger, $crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($logger:expr, $($arg:tt)+) => { $crate::log!($logger, $crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($logger:expr, $($arg:tt)+) => { $crate::log!($logger, $crate::log::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Logger collecting text and binary output separately
+    #[derive(Default)]
+    pub struct TestLog(pub String, pub Vec<u8>);
+
+    impl fmt::Write for TestLog {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.write_str(s)
+        }
+    }
+
+    impl Logger for TestLog {
+        fn write_bytes(&mut self, bytes: &[u8]) -> fmt::Result {
+            self.1.extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_log_format() {
+        let mut log = TestLog::default();
+        crate::warn!(log, "gyro {} overflow", 2);
+        let expected = match STATIC_MAX_LEVEL.allows(Level::Warn) {
+            true => "[WARN ] bingo_fc::log::tests: gyro 2 overflow\r\n",
+            false => "",
+        };
+        assert_eq!(log.0, expected);
+    }
 
-pub trait Logger : core::fmt::Write {}
+    #[test]
+    fn test_level_filter() {
+        assert!(LevelFilter::Info.allows(Level::Error));
+        assert!(LevelFilter::Info.allows(Level::Info));
+        assert!(!LevelFilter::Info.allows(Level::Debug));
+        assert!(!LevelFilter::Off.allows(Level::Error));
+    }
+}
@@ -0,0 +1,199 @@
+//! Read-only FAT16 disk generated on the fly, holding the flash log as a single file
+
+use crate::{interface::flash::NorFlash, log::flash::FlashLog};
+
+use super::{BlockDevice, BLOCK_SIZE};
+
+/// Name of the log file, in the 8.3 directory entry format
+const FILE_NAME: &[u8 ; 11] = b"BLACKBOXBBL";
+const VOLUME_LABEL: &[u8 ; 11] = b"BINGO FC   ";
+const VOLUME_ID: u32 = 0xB1B0_F405;
+
+/// FAT16 volumes must have at least this many clusters, or they are taken to be FAT12
+const MIN_CLUSTERS: u32 = 4096;
+const ROOT_ENTRIES: u32 = 512;
+const ROOT_SECTORS: u32 = ROOT_ENTRIES * 32 / BLOCK_SIZE as u32;
+const RESERVED_SECTORS: u32 = 1;
+const FAT_COUNT: u32 = 2;
+
+/// Date of 2025-01-01 and time of midnight in the directory entry format
+const FAT_DATE: u16 = (2025 - 1980) << 9 | 1 << 5 | 1;
+const FAT_TIME: u16 = 0;
+
+/// Disk presenting the contents of a flash log as `BLACKBOX.BBL`, with one sector per cluster
+/// so each cluster maps to 512 bytes of flash
+pub struct FatDisk<F: NorFlash> {
+    flash: F,
+    /// Length of the log in bytes
+    len: u32,
+    clusters: u32,
+    fat_sectors: u32,
+}
+
+impl<F: NorFlash> FatDisk<F> {
+    /// Create a disk holding the first `len` bytes of the flash
+    pub fn new(flash: F, len: u32) -> Self {
+        let clusters = flash.capacity().div_ceil(BLOCK_SIZE as u32).max(MIN_CLUSTERS);
+        Self {
+            fat_sectors: ((clusters + 2) * 2).div_ceil(BLOCK_SIZE as u32),
+            flash,
+            len,
+            clusters,
+        }
+    }
+
+    /// Create a disk holding the data written to a flash log
+    pub fn from_log<const N: usize>(log: FlashLog<F, N>) -> Self {
+        let len = log.len();
+        Self::new(log.into_flash(), len)
+    }
+
+    /// Release the flash memory
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    const fn root_start(&self) -> u32 {
+        RESERVED_SECTORS + FAT_COUNT * self.fat_sectors
+    }
+
+    const fn data_start(&self) -> u32 {
+        self.root_start() + ROOT_SECTORS
+    }
+
+    /// Get the number of clusters used by the log file
+    const fn file_clusters(&self) -> u32 {
+        self.len.div_ceil(BLOCK_SIZE as u32)
+    }
+
+    fn boot_sector(&self, buf: &mut [u8 ; BLOCK_SIZE]) {
+        let total = self.block_count();
+        buf[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        buf[3..11].copy_from_slice(b"BINGO FC");
+        buf[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+        buf[13] = 1;
+        buf[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+        buf[16] = FAT_COUNT as u8;
+        buf[17..19].copy_from_slice(&(ROOT_ENTRIES as u16).to_le_bytes());
+        if total < 0x10000 {
+            buf[19..21].copy_from_slice(&(total as u16).to_le_bytes());
+        } else {
+            buf[32..36].copy_from_slice(&total.to_le_bytes());
+        }
+        // Fixed disk media descriptor
+        buf[21] = 0xF8;
+        buf[22..24].copy_from_slice(&(self.fat_sectors as u16).to_le_bytes());
+        buf[24..26].copy_from_slice(&32u16.to_le_bytes());
+        buf[26..28].copy_from_slice(&64u16.to_le_bytes());
+        buf[36] = 0x80;
+        // Extended boot signature, followed by the volume ID, label and file system type
+        buf[38] = 0x29;
+        buf[39..43].copy_from_slice(&VOLUME_ID.to_le_bytes());
+        buf[43..54].copy_from_slice(VOLUME_LABEL);
+        buf[54..62].copy_from_slice(b"FAT16   ");
+        buf[510..512].copy_from_slice(&[0x55, 0xAA]);
+    }
+
+    /// Fill a sector of the FAT, where the log file is a single chain starting at cluster 2
+    fn fat_sector(&self, sector: u32, buf: &mut [u8 ; BLOCK_SIZE]) {
+        let last = 2 + self.file_clusters();
+        for (i, entry) in buf.chunks_exact_mut(2).enumerate() {
+            let cluster = sector * (BLOCK_SIZE as u32 / 2) + i as u32;
+            let value: u16 = match cluster {
+                0 => 0xFFF8,
+                1 => 0xFFFF,
+                c if c + 1 == last => 0xFFFF,
+                c if c < last => c as u16 + 1,
+                _ => 0,
+            };
+            entry.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn root_directory(&self, buf: &mut [u8 ; BLOCK_SIZE]) {
+        let (label, file) = buf[..64].split_at_mut(32);
+        label[..11].copy_from_slice(VOLUME_LABEL);
+        label[11] = 0x08;
+
+        file[..11].copy_from_slice(FILE_NAME);
+        // Read-only and archive attributes
+        file[11] = 0x21;
+        for offset in [14, 22] {
+            file[offset..offset + 2].copy_from_slice(&FAT_TIME.to_le_bytes());
+        }
+        for offset in [16, 18, 24] {
+            file[offset..offset + 2].copy_from_slice(&FAT_DATE.to_le_bytes());
+        }
+        let first_cluster: u16 = if self.len > 0 { 2 } else { 0 };
+        file[26..28].copy_from_slice(&first_cluster.to_le_bytes());
+        file[28..32].copy_from_slice(&self.len.to_le_bytes());
+    }
+}
+
+impl<F: NorFlash> BlockDevice for FatDisk<F> {
+    type Error = F::Error;
+
+    fn block_count(&self) -> u32 {
+        self.data_start() + self.clusters
+    }
+
+    fn read_block(&mut self, lba: u32, buf: &mut [u8 ; BLOCK_SIZE]) -> Result<(), Self::Error> {
+        buf.fill(0);
+        let fat_start = RESERVED_SECTORS;
+        match lba {
+            0 => self.boot_sector(buf),
+            lba if lba < self.root_start() => self.fat_sector((lba - fat_start) % self.fat_sectors, buf),
+            lba if lba == self.root_start() => self.root_directory(buf),
+            lba if lba < self.data_start() => {},
+            lba => {
+                let address = (lba - self.data_start()) * BLOCK_SIZE as u32;
+                if address < self.len {
+                    let len = (self.len - address).min(BLOCK_SIZE as u32) as usize;
+                    while self.flash.is_busy()? {}
+                    self.flash.read(address, &mut buf[..len])?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::MockFlash;
+
+    #[test]
+    fn test_disk_layout() {
+        let mut flash = MockFlash::<{ 64 * 1024 }>::new();
+        for (i, byte) in flash.memory[..1300].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut disk = FatDisk::new(flash, 1300);
+        let mut buf = [0u8 ; BLOCK_SIZE];
+
+        disk.read_block(0, &mut buf).unwrap();
+        assert_eq!(&buf[54..62], b"FAT16   ");
+        assert_eq!(u16::from_le_bytes([buf[19], buf[20]]) as u32, disk.block_count());
+        assert_eq!(&buf[510..], &[0x55, 0xAA]);
+
+        // Both copies of the FAT chain clusters 2, 3 and 4
+        let fat_sectors = u16::from_le_bytes([buf[22], buf[23]]) as u32;
+        for fat in [1, 1 + fat_sectors] {
+            disk.read_block(fat, &mut buf).unwrap();
+            let entries: Vec<_> = buf[..12].chunks(2).map(|e| u16::from_le_bytes([e[0], e[1]])).collect();
+            assert_eq!(entries, [0xFFF8, 0xFFFF, 3, 4, 0xFFFF, 0]);
+        }
+
+        disk.read_block(1 + 2 * fat_sectors, &mut buf).unwrap();
+        assert_eq!(&buf[32..43], FILE_NAME);
+        assert_eq!(u32::from_le_bytes(buf[60..64].try_into().unwrap()), 1300);
+
+        let data_start = 1 + 2 * fat_sectors + ROOT_SECTORS;
+        disk.read_block(data_start + 2, &mut buf).unwrap();
+        assert_eq!(buf[0], (1024 % 256) as u8);
+        assert_eq!(buf[1300 - 1024 - 1], ((1300 - 1) % 256) as u8);
+        assert!(buf[1300 - 1024..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,284 @@
+//! USB mass storage class exposing recorded logs as a read-only disk
+
+use usb_device::{
+    bus::{InterfaceNumber, UsbBus, UsbBusAllocator},
+    class::{ControlIn, ControlOut, UsbClass},
+    control::{Recipient, Request, RequestType},
+    descriptor::DescriptorWriter,
+    endpoint::{EndpointAddress, EndpointIn, EndpointOut},
+};
+
+use scsi::{Cbw, Command, Sense, Status};
+
+pub mod fat;
+pub mod scsi;
+
+pub use fat::FatDisk;
+
+/// Size of a disk block in bytes
+pub const BLOCK_SIZE: usize = 512;
+
+const USB_CLASS_MSC: u8 = 0x08;
+const MSC_SUBCLASS_SCSI: u8 = 0x06;
+const MSC_PROTOCOL_BULK_ONLY: u8 = 0x50;
+const REQ_GET_MAX_LUN: u8 = 0xFE;
+const REQ_BULK_ONLY_RESET: u8 = 0xFF;
+
+const PACKET_SIZE: u16 = 64;
+
+/// Storage read in fixed size blocks
+pub trait BlockDevice {
+    type Error: core::fmt::Debug;
+
+    fn block_count(&self) -> u32;
+
+    fn read_block(&mut self, lba: u32, buf: &mut [u8 ; BLOCK_SIZE]) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    /// Waiting for a command block wrapper
+    Command,
+    /// Sending `buf[sent..len]`, followed by `blocks` more blocks starting at `lba`
+    DataIn { len: usize, sent: usize, lba: u32, blocks: u32 },
+    /// Discarding data sent by the host for a command that is refused
+    DataOut { remaining: u32 },
+    /// Waiting for the host to clear the halted bulk-in endpoint after a failed data stage
+    Stalled,
+    /// Waiting to send the command status wrapper
+    Status,
+}
+
+/// Bulk-only transport mass storage class serving a read-only [BlockDevice].
+///
+/// Writes are refused with a write protected error, and the disk reports itself as write
+/// protected so hosts mount it read-only
+pub struct UsbMsc<'a, B: UsbBus, D: BlockDevice> {
+    interface: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    disk: D,
+    stage: Stage,
+    buf: [u8 ; BLOCK_SIZE],
+    tag: u32,
+    /// Number of bytes the host expects in the data stage that have not been transferred
+    residue: u32,
+    status: Status,
+    sense: Sense,
+}
+
+impl<'a, B: UsbBus, D: BlockDevice> UsbMsc<'a, B, D> {
+    pub fn new(allocator: &'a UsbBusAllocator<B>, disk: D) -> Self {
+        Self {
+            interface: allocator.interface(),
+            read_ep: allocator.bulk(PACKET_SIZE),
+            write_ep: allocator.bulk(PACKET_SIZE),
+            disk,
+            stage: Stage::Command,
+            buf: [0u8 ; BLOCK_SIZE],
+            tag: 0,
+            residue: 0,
+            status: Status::Passed,
+            sense: Sense::NONE,
+        }
+    }
+
+    pub const fn disk(&self) -> &D {
+        &self.disk
+    }
+
+    /// Release the disk
+    pub fn free(self) -> D {
+        self.disk
+    }
+
+    fn fail(&mut self, sense: Sense) {
+        self.status = Status::Failed;
+        self.sense = sense;
+    }
+
+    /// Start handling a command, setting up its data stage
+    fn command(&mut self, cbw: Cbw) {
+        self.tag = cbw.tag;
+        self.residue = cbw.transfer_len;
+        self.status = Status::Passed;
+        let command = cbw.command;
+        if !matches!(command, Command::RequestSense { .. }) {
+            self.sense = Sense::NONE;
+        }
+
+        let block_count = self.disk.block_count();
+        let len = match command {
+            // Only one logical unit is reported by GET MAX LUN
+            _ if cbw.lun != 0 => {
+                self.fail(Sense::LUN_NOT_SUPPORTED);
+                0
+            },
+            Command::Read10 { lba, blocks } if lba.checked_add(blocks).is_some_and(|end| end <= block_count) => {
+                self.stage = Stage::DataIn { len: 0, sent: 0, lba, blocks };
+                return
+            },
+            Command::Read10 { .. } => {
+                self.fail(Sense::LBA_OUT_OF_RANGE);
+                0
+            },
+            Command::Write10 { .. } => {
+                self.fail(Sense::WRITE_PROTECTED);
+                0
+            },
+            Command::TestUnitReady | Command::Accepted => 0,
+            Command::Unsupported(_) => {
+                self.fail(Sense::INVALID_COMMAND);
+                0
+            },
+            command => scsi::respond(command, block_count, BLOCK_SIZE as u32, self.sense, &mut self.buf).unwrap_or(0),
+        };
+
+        self.stage = match (cbw.transfer_len, cbw.data_in) {
+            (0, _) => Stage::Status,
+            (_, true) => Stage::DataIn { len: len.min(cbw.transfer_len as usize), sent: 0, lba: 0, blocks: 0 },
+            (remaining, false) => Stage::DataOut { remaining },
+        };
+    }
+
+    /// Send as many packets of the current stage as the endpoint will take
+    fn send(&mut self) {
+        loop {
+            match self.stage {
+                Stage::DataIn { len, sent, lba, blocks } if sent < len => {
+                    let end = len.min(sent + PACKET_SIZE as usize);
+                    match self.write_ep.write(&self.buf[sent..end]) {
+                        Ok(count) => {
+                            self.residue -= count as u32;
+                            self.stage = Stage::DataIn { len, sent: sent + count, lba, blocks };
+                        },
+                        Err(_) => return,
+                    }
+                },
+                Stage::DataIn { lba, blocks, .. } if blocks > 0 && self.residue > 0 => {
+                    if self.disk.read_block(lba, &mut self.buf).is_err() {
+                        self.fail(Sense::MEDIUM_ERROR);
+                        self.stage = Stage::DataIn { len: 0, sent: 0, lba, blocks: 0 };
+                        continue
+                    }
+                    let len = BLOCK_SIZE.min(self.residue as usize);
+                    self.stage = Stage::DataIn { len, sent: 0, lba: lba + 1, blocks: blocks - 1 };
+                },
+                // A failed command halts the endpoint instead of ending the data stage, and the
+                // status is sent once the host has cleared it
+                Stage::DataIn { .. } if self.residue > 0 && self.status == Status::Failed => {
+                    self.write_ep.stall();
+                    self.stage = Stage::Stalled;
+                },
+                Stage::DataIn { sent, .. } => {
+                    // End a data stage shorter than the host expected with a short packet
+                    if self.residue > 0 && sent % PACKET_SIZE as usize == 0 {
+                        match self.write_ep.write(&[]) {
+                            Ok(_) => {},
+                            Err(_) => return,
+                        }
+                    }
+                    self.stage = Stage::Status;
+                },
+                Stage::Status => {
+                    match self.write_ep.write(&scsi::csw(self.tag, self.residue, self.status)) {
+                        Ok(_) => self.stage = Stage::Command,
+                        Err(_) => return,
+                    }
+                },
+                Stage::Command | Stage::DataOut { .. } | Stage::Stalled => return,
+            }
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut packet = [0u8 ; PACKET_SIZE as usize];
+        let count = match self.read_ep.read(&mut packet) {
+            Ok(count) => count,
+            Err(_) => return,
+        };
+
+        match self.stage {
+            Stage::Command => {
+                if let Some(cbw) = Cbw::parse(&packet[..count]) {
+                    self.command(cbw);
+                }
+            },
+            Stage::DataOut { remaining } => {
+                let remaining = remaining.saturating_sub(count as u32);
+                self.stage = match remaining {
+                    0 => Stage::Status,
+                    remaining => Stage::DataOut { remaining },
+                };
+            },
+            // The host should not send anything else before the status is sent
+            Stage::DataIn { .. } | Stage::Stalled | Stage::Status => {},
+        }
+    }
+}
+
+impl<B: UsbBus, D: BlockDevice> UsbClass<B> for UsbMsc<'_, B, D> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> usb_device::Result<()> {
+        writer.iad(self.interface, 1, USB_CLASS_MSC, MSC_SUBCLASS_SCSI, MSC_PROTOCOL_BULK_ONLY, None)?;
+        writer.interface(self.interface, USB_CLASS_MSC, MSC_SUBCLASS_SCSI, MSC_PROTOCOL_BULK_ONLY)?;
+        writer.endpoint(&self.read_ep)?;
+        writer.endpoint(&self.write_ep)
+    }
+
+    fn reset(&mut self) {
+        self.stage = Stage::Command;
+        self.sense = Sense::NONE;
+    }
+
+    fn poll(&mut self) {
+        self.send();
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let request = *xfer.request();
+        if request.request_type == RequestType::Class
+            && request.recipient == Recipient::Interface
+            && request.index == u8::from(self.interface) as u16
+            && request.request == REQ_GET_MAX_LUN
+        {
+            let _ = xfer.accept_with(&[0]);
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let request = *xfer.request();
+        // Left to the device to accept, which clears the halt before the status is sent
+        if self.stage == Stage::Stalled
+            && request.request_type == RequestType::Standard
+            && request.recipient == Recipient::Endpoint
+            && request.request == Request::CLEAR_FEATURE
+            && request.value == Request::FEATURE_ENDPOINT_HALT
+            && request.index == u8::from(self.write_ep.address()) as u16
+        {
+            self.stage = Stage::Status;
+            return
+        }
+
+        if request.request_type == RequestType::Class
+            && request.recipient == Recipient::Interface
+            && request.index == u8::from(self.interface) as u16
+            && request.request == REQ_BULK_ONLY_RESET
+        {
+            self.reset();
+            let _ = xfer.accept();
+        }
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        if addr == self.read_ep.address() {
+            self.receive();
+            self.send();
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_ep.address() {
+            self.send();
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Bulk-only transport wrappers and the SCSI commands needed by a read-only disk
+
+/// Size of a command block wrapper
+pub const CBW_LEN: usize = 31;
+/// Size of a command status wrapper
+pub const CSW_LEN: usize = 13;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+
+/// Command block wrapper sent by the host to start a command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cbw {
+    pub tag: u32,
+    /// Number of bytes the host expects to transfer in the data stage
+    pub transfer_len: u32,
+    /// The data stage is from the device to the host
+    pub data_in: bool,
+    pub lun: u8,
+    pub command: Command,
+}
+
+impl Cbw {
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() != CBW_LEN || u32::from_le_bytes(packet[0..4].try_into().ok()?) != CBW_SIGNATURE {
+            return None
+        }
+
+        let cb_len = (packet[14] & 0x1F) as usize;
+        Some(Self {
+            tag: u32::from_le_bytes(packet[4..8].try_into().ok()?),
+            transfer_len: u32::from_le_bytes(packet[8..12].try_into().ok()?),
+            data_in: packet[12] & 0x80 != 0,
+            lun: packet[13] & 0x0F,
+            command: Command::parse(&packet[15..15 + cb_len.min(16)]),
+        })
+    }
+}
+
+/// Result of a command reported in the command status wrapper
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Passed = 0,
+    Failed = 1,
+}
+
+/// Build a command status wrapper, where `residue` is the number of expected bytes that
+/// were not transferred
+pub fn csw(tag: u32, residue: u32, status: Status) -> [u8 ; CSW_LEN] {
+    let mut csw = [0u8 ; CSW_LEN];
+    csw[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+    csw[4..8].copy_from_slice(&tag.to_le_bytes());
+    csw[8..12].copy_from_slice(&residue.to_le_bytes());
+    csw[12] = status as u8;
+    csw
+}
+
+/// SCSI command, with the allocation length of commands returning fixed data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    TestUnitReady,
+    RequestSense { len: usize },
+    Inquiry { len: usize },
+    ModeSense6 { len: usize },
+    ModeSense10 { len: usize },
+    ReadFormatCapacities { len: usize },
+    ReadCapacity10,
+    Read10 { lba: u32, blocks: u32 },
+    Write10 { lba: u32, blocks: u32 },
+    /// Commands that have no data and always succeed on a read-only disk
+    Accepted,
+    Unsupported(u8),
+}
+
+impl Command {
+    pub fn parse(cb: &[u8]) -> Self {
+        let byte = |i: usize| cb.get(i).copied().unwrap_or(0) as u32;
+        let be16 = |i: usize| byte(i) << 8 | byte(i + 1);
+        let be32 = |i: usize| be16(i) << 16 | be16(i + 2);
+
+        match cb.first().copied().unwrap_or(0) {
+            0x00 => Self::TestUnitReady,
+            0x03 => Self::RequestSense { len: byte(4) as usize },
+            0x12 => Self::Inquiry { len: be16(3) as usize },
+            0x1A => Self::ModeSense6 { len: byte(4) as usize },
+            0x5A => Self::ModeSense10 { len: be16(7) as usize },
+            0x23 => Self::ReadFormatCapacities { len: be16(7) as usize },
+            0x25 => Self::ReadCapacity10,
+            0x28 => Self::Read10 { lba: be32(2), blocks: be16(7) },
+            0x2A => Self::Write10 { lba: be32(2), blocks: be16(7) },
+            // Prevent/allow medium removal, start stop unit, verify and synchronize cache
+            0x1E | 0x1B | 0x2F | 0x35 => Self::Accepted,
+            opcode => Self::Unsupported(opcode),
+        }
+    }
+}
+
+/// Sense data describing why the last command failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sense {
+    pub key: u8,
+    pub asc: u8,
+}
+
+impl Sense {
+    pub const NONE: Self = Self { key: 0x00, asc: 0x00 };
+    pub const MEDIUM_ERROR: Self = Self { key: 0x03, asc: 0x11 };
+    pub const INVALID_COMMAND: Self = Self { key: 0x05, asc: 0x20 };
+    pub const LBA_OUT_OF_RANGE: Self = Self { key: 0x05, asc: 0x21 };
+    pub const LUN_NOT_SUPPORTED: Self = Self { key: 0x05, asc: 0x25 };
+    pub const WRITE_PROTECTED: Self = Self { key: 0x07, asc: 0x27 };
+}
+
+/// Write the fixed response to a command that returns data other than blocks into `buf`,
+/// returning its length, or `None` if the command has no such response
+pub fn respond(command: Command, block_count: u32, block_size: u32, sense: Sense, buf: &mut [u8]) -> Option<usize> {
+    let (response, len): (&[u8], usize) = match command {
+        Command::RequestSense { len } => {
+            let mut data = [0u8 ; 18];
+            data[0] = 0x70;
+            data[2] = sense.key;
+            data[7] = 10;
+            data[12] = sense.asc;
+            buf[..18].copy_from_slice(&data);
+            return Some(len.min(18))
+        },
+        Command::Inquiry { len } => (&INQUIRY, len),
+        // Mode parameter headers with the write protect bit set
+        Command::ModeSense6 { len } => (&[3, 0, 0x80, 0], len),
+        Command::ModeSense10 { len } => (&[0, 6, 0, 0x80, 0, 0, 0, 0], len),
+        Command::ReadCapacity10 => {
+            buf[0..4].copy_from_slice(&block_count.saturating_sub(1).to_be_bytes());
+            buf[4..8].copy_from_slice(&block_size.to_be_bytes());
+            return Some(8)
+        },
+        Command::ReadFormatCapacities { len } => {
+            buf[0..4].copy_from_slice(&[0, 0, 0, 8]);
+            buf[4..8].copy_from_slice(&block_count.to_be_bytes());
+            // Formatted media, followed by the 3-byte block length
+            buf[8..12].copy_from_slice(&(0x0200_0000 | block_size).to_be_bytes());
+            return Some(len.min(12))
+        },
+        _ => return None,
+    };
+
+    let len = len.min(response.len());
+    buf[..len].copy_from_slice(&response[..len]);
+    Some(len)
+}
+
+/// Standard inquiry data for a removable direct access device
+const INQUIRY: [u8 ; 36] = *b"\x00\x80\x04\x02\x1f\x00\x00\x00Bingo FCBlackbox Logs   1.00";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_read() {
+        let mut packet = [0u8 ; CBW_LEN];
+        packet[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        packet[4..8].copy_from_slice(&7u32.to_le_bytes());
+        packet[8..12].copy_from_slice(&1024u32.to_le_bytes());
+        packet[12] = 0x80;
+        packet[14] = 10;
+        packet[15..25].copy_from_slice(&[0x28, 0, 0, 0, 0x12, 0x34, 0, 0, 2, 0]);
+
+        let cbw = Cbw::parse(&packet).unwrap();
+        assert_eq!(cbw.tag, 7);
+        assert!(cbw.data_in);
+        assert_eq!(cbw.command, Command::Read10 { lba: 0x1234, blocks: 2 });
+
+        packet[0] = 0;
+        assert_eq!(Cbw::parse(&packet), None);
+    }
+
+    #[test]
+    fn test_responses() {
+        let mut buf = [0u8 ; 64];
+        assert_eq!(respond(Command::Inquiry { len: 255 }, 100, 512, Sense::NONE, &mut buf), Some(36));
+        assert_eq!(&buf[8..16], b"Bingo FC");
+
+        assert_eq!(respond(Command::ReadCapacity10, 100, 512, Sense::NONE, &mut buf), Some(8));
+        assert_eq!(&buf[..8], &[0, 0, 0, 99, 0, 0, 2, 0]);
+
+        assert_eq!(respond(Command::RequestSense { len: 18 }, 100, 512, Sense::WRITE_PROTECTED, &mut buf), Some(18));
+        assert_eq!((buf[2], buf[12]), (0x07, 0x27));
+        assert_eq!(respond(Command::TestUnitReady, 100, 512, Sense::NONE, &mut buf), None);
+    }
+}
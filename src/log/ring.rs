@@ -0,0 +1,102 @@
+/// Fixed capacity FIFO of bytes
+pub struct RingBuffer<const N: usize> {
+    buf: [u8 ; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8 ; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Get the number of bytes waiting to be read
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of bytes that can be written before the buffer is full
+    pub const fn free(&self) -> usize {
+        N - self.len
+    }
+
+    /// Write as many bytes from `data` as will fit, returning the number written
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let count = data.len().min(self.free());
+        let tail = (self.head + self.len) % N;
+        let first = count.min(N - tail);
+        self.buf[tail..tail + first].copy_from_slice(&data[..first]);
+        self.buf[..count - first].copy_from_slice(&data[first..count]);
+        self.len += count;
+        count
+    }
+
+    /// Get the longest contiguous run of bytes at the front of the buffer
+    pub fn peek(&self) -> &[u8] {
+        let end = (self.head + self.len).min(N);
+        &self.buf[self.head..end]
+    }
+
+    /// Remove up to `count` bytes from the front of the buffer
+    pub fn consume(&mut self, count: usize) {
+        let count = count.min(self.len);
+        self.head = (self.head + count) % N;
+        self.len -= count;
+        if self.len == 0 {
+            self.head = 0;
+        }
+    }
+
+    /// Remove bytes from the front of the buffer into `out`, returning the number read
+    pub fn pop(&mut self, out: &mut [u8]) -> usize {
+        let mut read = 0;
+        while read < out.len() && !self.is_empty() {
+            let chunk = self.peek();
+            let count = chunk.len().min(out.len() - read);
+            out[read..read + count].copy_from_slice(&chunk[..count]);
+            self.consume(count);
+            read += count;
+        }
+
+        read
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_wraps() {
+        let mut ring = RingBuffer::<8>::new();
+        assert_eq!(ring.push(b"abcdef"), 6);
+        ring.consume(4);
+        assert_eq!(ring.push(b"ghijklmn"), 6);
+        assert_eq!(ring.free(), 0);
+
+        assert_eq!(ring.peek(), b"efgh");
+        let mut out = [0u8 ; 8];
+        assert_eq!(ring.pop(&mut out), 8);
+        assert_eq!(&out, b"efghijkl");
+        assert!(ring.is_empty());
+    }
+}
@@ -1,16 +1,112 @@
-use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usb_device::{bus::{InterfaceNumber, StringIndex, UsbBus, UsbBusAllocator}, class::{ControlIn, ControlOut, UsbClass}, descriptor::DescriptorWriter, endpoint::EndpointAddress, LangID, UsbError};
 
+use super::{ring::RingBuffer, Logger};
 
 
-pub struct UsbSerialLogger<'buf, B: UsbBus> {
+/// Logger that queues formatted text and sends it over a USB CDC serial port as the host reads it.
+/// Text written while the buffer is full is discarded and counted instead of blocking
+pub struct UsbSerialLogger<'buf, B: UsbBus, const N: usize = 1024> {
     usb: usbd_serial::SerialPort<'buf, B>,
+    queue: RingBuffer<N>,
+    dropped: u32,
 }
 
-impl<'buf, B: UsbBus> UsbSerialLogger<'buf, B> {
+impl<'buf, B: UsbBus, const N: usize> UsbSerialLogger<'buf, B, N> {
     /// Create a new USB serial logger from the given bus allocator
     pub fn new(allocator: &'buf UsbBusAllocator<B>) -> Self {
         Self {
-            usb: usbd_serial::SerialPort::new(allocator)
+            usb: usbd_serial::SerialPort::new(allocator),
+            queue: RingBuffer::new(),
+            dropped: 0,
         }
     }
+
+    /// Check if a host has opened the serial port
+    pub fn connected(&self) -> bool {
+        self.usb.dtr()
+    }
+
+    /// Get the number of bytes discarded because the queue was full
+    pub const fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Get the number of bytes waiting to be sent to the host
+    pub const fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Read bytes sent by the host
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, UsbError> {
+        self.usb.read(buf)
+    }
+
+    /// Move as much queued text as possible into the serial port without blocking.
+    /// Text is kept queued while no host has the port open
+    pub fn drain(&mut self) {
+        while self.connected() && !self.queue.is_empty() {
+            match self.usb.write(self.queue.peek()) {
+                Ok(count) => self.queue.consume(count),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<B: UsbBus, const N: usize> core::fmt::Write for UsbSerialLogger<'_, B, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes())
+    }
+}
+
+impl<B: UsbBus, const N: usize> Logger for UsbSerialLogger<'_, B, N> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> core::fmt::Result {
+        let written = self.queue.push(bytes);
+        self.dropped = self.dropped.wrapping_add((bytes.len() - written) as u32);
+        Ok(())
+    }
+}
+
+/// Forwards to the underlying serial port, draining queued text each time the device is polled
+impl<B: UsbBus, const N: usize> UsbClass<B> for UsbSerialLogger<'_, B, N> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> usb_device::Result<()> {
+        self.usb.get_configuration_descriptors(writer)
+    }
+
+    fn get_string(&self, index: StringIndex, lang_id: LangID) -> Option<&str> {
+        self.usb.get_string(index, lang_id)
+    }
+
+    fn reset(&mut self) {
+        self.usb.reset();
+    }
+
+    fn poll(&mut self) {
+        self.drain();
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        self.usb.control_out(xfer);
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        self.usb.control_in(xfer);
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        self.usb.endpoint_out(addr);
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        self.usb.endpoint_in_complete(addr);
+        self.drain();
+    }
+
+    fn get_alt_setting(&mut self, interface: InterfaceNumber) -> Option<u8> {
+        self.usb.get_alt_setting(interface)
+    }
+
+    fn set_alt_setting(&mut self, interface: InterfaceNumber, alternative: u8) -> bool {
+        self.usb.set_alt_setting(interface, alternative)
+    }
 }
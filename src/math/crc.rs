@@ -0,0 +1,54 @@
+/// Update a CRC-8 with polynomial 0xD5 (DVB-S2) by one byte
+pub const fn crc8_dvb_s2(crc: u8, byte: u8) -> u8 {
+    let mut crc = crc ^ byte;
+    let mut i = 0;
+    while i < 8 {
+        crc = match crc & 0x80 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0xD5,
+        };
+        i += 1;
+    }
+
+    crc
+}
+
+/// Calculate the CRC-8 DVB-S2 of a buffer, starting from zero
+pub fn crc8_dvb_s2_slice(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, b| crc8_dvb_s2(crc, *b))
+}
+
+/// Update a CRC-8 with polynomial 0x07 (SMBus), as used by KISS ESC telemetry, by one byte
+pub const fn crc8_smbus(crc: u8, byte: u8) -> u8 {
+    let mut crc = crc ^ byte;
+    let mut i = 0;
+    while i < 8 {
+        crc = match crc & 0x80 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x07,
+        };
+        i += 1;
+    }
+
+    crc
+}
+
+/// Calculate the CRC-8 SMBus of a buffer, starting from zero
+pub fn crc8_smbus_slice(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, b| crc8_smbus(crc, *b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc8_dvb_s2() {
+        assert_eq!(crc8_dvb_s2_slice(b"123456789"), 0xBC);
+    }
+
+    #[test]
+    fn test_crc8_smbus() {
+        assert_eq!(crc8_smbus_slice(b"123456789"), 0xF4);
+    }
+}
@@ -0,0 +1,150 @@
+use nalgebra as na;
+
+/// First order lowpass filter, with the cutoff frequency given on each update so it can follow
+/// a varying loop time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pt1<T> {
+    state: Option<T>,
+}
+
+impl<T> Pt1<T>
+    where T: na::RealField + Copy
+{
+    pub const fn new() -> Self {
+        Self { state: None }
+    }
+
+    /// Filter a sample taken `dt` seconds after the last with a cutoff of `cutoff` Hz.
+    /// The first sample passes through unchanged, and a cutoff of zero disables the filter
+    pub fn update(&mut self, input: T, cutoff: T, dt: T) -> T {
+        let output = match self.state {
+            Some(state) if cutoff > T::zero() => {
+                let rc = T::one() / (T::two_pi() * cutoff);
+                state + (input - state) * dt / (rc + dt)
+            },
+            _ => input,
+        };
+
+        self.state = Some(output);
+        output
+    }
+
+    /// Get the last output, if any sample has been filtered
+    pub const fn output(&self) -> Option<T> {
+        self.state
+    }
+
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+impl<T: na::RealField + Copy> Default for Pt1<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Second order Butterworth lowpass filter, rolling off twice as steeply as [Pt1] for the same
+/// delay. Like [Pt1], the cutoff is given on each update
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Biquad<T> {
+    /// Last two inputs and outputs, newest first
+    state: Option<([T ; 2], [T ; 2])>,
+}
+
+impl<T> Biquad<T>
+    where T: na::RealField + Copy
+{
+    pub const fn new() -> Self {
+        Self { state: None }
+    }
+
+    /// Filter a sample taken `dt` seconds after the last with a cutoff of `cutoff` Hz, limited to
+    /// just below the Nyquist frequency. The first sample passes through unchanged, and a cutoff
+    /// of zero disables the filter
+    pub fn update(&mut self, input: T, cutoff: T, dt: T) -> T {
+        let output = match self.state {
+            Some((x, y)) if cutoff > T::zero() => {
+                let cutoff = cutoff.min(na::convert::<f64, T>(0.45) / dt);
+                let (sin, cos) = (T::two_pi() * cutoff * dt).sin_cos();
+                let two = T::one() + T::one();
+                let alpha = sin / two.sqrt();
+                let b1 = T::one() - cos;
+                let b0 = b1 / two;
+                let (a1, a2) = (-two * cos, T::one() - alpha);
+                (b0 * input + b1 * x[0] + b0 * x[1] - a1 * y[0] - a2 * y[1]) / (T::one() + alpha)
+            },
+            _ => input,
+        };
+
+        self.state = Some(match self.state {
+            Some((x, y)) => ([input, x[0]], [output, y[0]]),
+            None => ([input ; 2], [output ; 2]),
+        });
+        output
+    }
+
+    /// Get the last output, if any sample has been filtered
+    pub fn output(&self) -> Option<T> {
+        self.state.map(|(_, y)| y[0])
+    }
+
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+impl<T: na::RealField + Copy> Default for Biquad<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pt1_step_response() {
+        let mut filter = Pt1::new();
+        let dt = 0.001f32;
+        filter.update(0f32, 10f32, dt);
+
+        // After one time constant a step reaches 1 - 1/e of its final value
+        let tau = 1f32 / (core::f32::consts::TAU * 10f32);
+        let mut output = 0f32;
+        for _ in 0..(tau / dt).round() as usize {
+            output = filter.update(1f32, 10f32, dt);
+        }
+        assert!((output - 0.632).abs() < 0.02, "{output}");
+
+        assert_eq!(filter.update(5f32, 0f32, dt), 5f32);
+    }
+
+    #[test]
+    fn test_biquad_response() {
+        let mut filter = Biquad::new();
+        let dt = 0.001f32;
+        assert_eq!(filter.update(0f32, 10f32, dt), 0f32);
+
+        // A step settles at its final value, overshooting by about 4%
+        let outputs: Vec<f32> = (0..1000).map(|_| filter.update(1f32, 10f32, dt)).collect();
+        let peak = outputs.iter().copied().fold(0f32, f32::max);
+        assert!((peak - 1.043).abs() < 0.01, "{peak}");
+        assert!((outputs[999] - 1f32).abs() < 1e-3);
+
+        // A sine at the cutoff is attenuated by 3 dB, and one a decade above by 40 dB
+        for (frequency, gain) in [(10f32, 0.707f32), (100f32, 0.01f32)] {
+            filter.reset();
+            let amplitude = (0..2000)
+                .map(|i| filter.update((core::f32::consts::TAU * frequency * i as f32 * dt).sin(), 10f32, dt))
+                .skip(1000)
+                .fold(0f32, |max, y| max.max(y.abs()));
+            assert!((amplitude - gain).abs() < gain * 0.1, "{frequency} Hz: {amplitude}");
+        }
+
+        assert_eq!(filter.update(5f32, 0f32, dt), 5f32);
+        assert_eq!(filter.output(), Some(5f32));
+    }
+}
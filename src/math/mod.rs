@@ -1,5 +1,7 @@
 pub mod vec;
 pub mod quat;
+pub mod crc;
+pub mod filter;
 
 pub use vec::Vector3;
 pub use quat::Quaternion;
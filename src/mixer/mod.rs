@@ -0,0 +1,20 @@
+use nalgebra::Vector3;
+
+pub mod table;
+
+pub use table::{Desaturation, Geometry, MotorMix, TableMixer};
+
+/// Converts roll, pitch, yaw and throttle demands into motor outputs
+pub trait Mixer<T> {
+    /// Get the number of motor outputs produced
+    fn motor_count(&self) -> usize;
+
+    /// Mix roll, pitch and yaw demands in [-1, 1] and throttle in [0, 1] into
+    /// one output in [0, 1] per motor
+    fn mix(&mut self, demand: Vector3<T>, throttle: T, outputs: &mut [T]);
+
+    /// Mix roll, pitch and yaw stick positions in [-1, 1] for turtle mode, where the motors spin
+    /// in reverse to flip the vehicle back over. Only the motors whose reversed thrust turns the
+    /// vehicle the commanded way spin, each in proportion to how much it helps
+    fn mix_turtle(&mut self, sticks: Vector3<T>, outputs: &mut [T]);
+}
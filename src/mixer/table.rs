@@ -0,0 +1,454 @@
+//! Mixer driven by a table of each motor's contribution to roll, pitch and yaw
+
+use heapless::Vec;
+use nalgebra::Vector3;
+use nalgebra as na;
+
+use crate::MAX_MOTORS;
+
+use super::Mixer;
+
+/// Contribution of one motor to roll, pitch and yaw torque. Every motor takes the full throttle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotorMix<T> {
+    pub roll: T,
+    pub pitch: T,
+    pub yaw: T,
+}
+
+/// Built-in frame layouts, numbering motors and spinning propellers inwards as Betaflight does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Geometry {
+    /// Rear right, front right, rear left, front left
+    QuadX,
+    /// Rear, right, left, front
+    QuadPlus,
+    /// Rear right, front right, rear left, front left, right, left
+    HexX,
+    /// Flat octocopter, starting at the left motor just ahead of the middle and going round
+    /// the frame as Betaflight's OctoFlatX does
+    OctoX,
+    /// Rear, right, left, with yaw from tilting the rear motor on a servo
+    Tricopter,
+}
+
+const QUAD_X: [[f32 ; 3] ; 4] = [
+    [-1.0, 1.0, -1.0],
+    [-1.0, -1.0, 1.0],
+    [1.0, 1.0, 1.0],
+    [1.0, -1.0, -1.0],
+];
+
+const QUAD_PLUS: [[f32 ; 3] ; 4] = [
+    [0.0, 1.0, -1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, 1.0],
+    [0.0, -1.0, -1.0],
+];
+
+const HEX_X: [[f32 ; 3] ; 6] = [
+    [-0.5, 0.866_025, 1.0],
+    [-0.5, -0.866_025, 1.0],
+    [0.5, 0.866_025, -1.0],
+    [0.5, -0.866_025, -1.0],
+    [-1.0, 0.0, -1.0],
+    [1.0, 0.0, 1.0],
+];
+
+const OCTO_X: [[f32 ; 3] ; 8] = [
+    [1.0, -0.414_178, 1.0],
+    [-0.414_178, -1.0, -1.0],
+    [-1.0, 0.414_178, 1.0],
+    [0.414_178, 1.0, -1.0],
+    [0.414_178, -1.0, 1.0],
+    [-1.0, -0.414_178, -1.0],
+    [-0.414_178, 1.0, 1.0],
+    [1.0, 0.414_178, -1.0],
+];
+
+const TRICOPTER: [[f32 ; 3] ; 3] = [
+    [0.0, 1.333_333, 0.0],
+    [-1.0, -0.666_667, 0.0],
+    [1.0, -0.666_667, 0.0],
+];
+
+impl Geometry {
+    fn table(&self) -> &'static [[f32 ; 3]] {
+        match self {
+            Self::QuadX => &QUAD_X,
+            Self::QuadPlus => &QUAD_PLUS,
+            Self::HexX => &HEX_X,
+            Self::OctoX => &OCTO_X,
+            Self::Tricopter => &TRICOPTER,
+        }
+    }
+
+    /// Whether yaw comes from a servo rather than from the motors' torque
+    pub const fn has_yaw_servo(&self) -> bool {
+        matches!(self, Self::Tricopter)
+    }
+}
+
+/// How to handle demands that would drive some motors outside [0, 1]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Desaturation {
+    /// Clamp each motor on its own, which distorts the torque produced
+    Clip,
+    /// Scale torque down to fit the output range, and move the throttle to fit the torque,
+    /// raising it by at most the mixer's `max_boost`
+    #[default]
+    ThrottleBoost,
+    /// Scale torque down to fit the output range, and move the throttle as far as needed, so
+    /// there's full authority even at zero throttle
+    Airmode,
+}
+
+/// [Mixer] for a frame described by a [Geometry] or a custom table
+pub struct TableMixer<T> {
+    motors: Vec<MotorMix<T>, MAX_MOTORS>,
+    servo: Option<T>,
+    pub desaturation: Desaturation,
+    /// Largest amount [Desaturation::ThrottleBoost] raises the throttle by
+    pub max_boost: T,
+    /// Give roll and pitch priority over yaw when the demand saturates, reducing yaw first
+    pub limit_yaw: bool,
+    /// Reverse the yaw direction, for propellers spinning outwards
+    pub reversed: bool,
+}
+
+impl<T> TableMixer<T>
+    where T: na::RealField + Copy
+{
+    pub fn new(geometry: Geometry) -> Self {
+        let motors = geometry.table()
+            .iter()
+            .map(|&[roll, pitch, yaw]| MotorMix {
+                roll: na::convert(roll as f64),
+                pitch: na::convert(pitch as f64),
+                yaw: na::convert(yaw as f64),
+            })
+            .collect();
+
+        Self {
+            motors,
+            servo: geometry.has_yaw_servo().then(T::zero),
+            desaturation: Desaturation::default(),
+            max_boost: na::convert(0.2),
+            limit_yaw: true,
+            reversed: false,
+        }
+    }
+
+    /// Create a mixer from a custom table, or `None` if it has no motors or more than [MAX_MOTORS]
+    pub fn custom(motors: &[MotorMix<T>]) -> Option<Self> {
+        if motors.is_empty() {
+            return None
+        }
+
+        Some(Self {
+            motors: Vec::from_slice(motors).ok()?,
+            servo: None,
+            desaturation: Desaturation::default(),
+            max_boost: na::convert(0.2),
+            limit_yaw: true,
+            reversed: false,
+        })
+    }
+
+    pub fn motors(&self) -> &[MotorMix<T>] {
+        &self.motors
+    }
+
+    /// Get the yaw servo position in [-1, 1] from the last mix, if the frame has a yaw servo
+    pub const fn servo(&self) -> Option<T> {
+        self.servo
+    }
+
+    fn range(&self, torque: impl Fn(&MotorMix<T>) -> T) -> (T, T) {
+        let first = torque(&self.motors[0]);
+        self.motors.iter().map(torque).fold((first, first), |(min, max), t| (min.min(t), max.max(t)))
+    }
+
+    /// Largest fraction of the yaw demand that keeps the range of outputs within 1, or zero if
+    /// roll and pitch don't fit on their own
+    fn yaw_room(&self, roll_pitch: &impl Fn(&MotorMix<T>) -> T, yaw: T) -> T {
+        // Each pair of motors limits the yaw scale s by a_i - a_j + s (b_i - b_j) <= 1
+        let mut scale = T::one();
+        for i in &self.motors {
+            for j in &self.motors {
+                let spread = roll_pitch(i) - roll_pitch(j);
+                let yaw_spread = (i.yaw - j.yaw) * yaw;
+                if spread > T::one() {
+                    return T::zero()
+                }
+                if yaw_spread > T::zero() {
+                    scale = scale.min((T::one() - spread) / yaw_spread);
+                }
+            }
+        }
+
+        scale
+    }
+}
+
+impl<T> Mixer<T> for TableMixer<T>
+    where T: na::RealField + Copy
+{
+    fn motor_count(&self) -> usize {
+        self.motors.len()
+    }
+
+    fn mix(&mut self, demand: Vector3<T>, throttle: T, outputs: &mut [T]) {
+        let yaw = match self.reversed {
+            true => -demand.z,
+            false => demand.z,
+        };
+        if let Some(servo) = &mut self.servo {
+            *servo = yaw.clamp(-T::one(), T::one());
+        }
+
+        let roll_pitch = |m: &MotorMix<T>| m.roll * demand.x + m.pitch * demand.y;
+        let mut yaw_scale = T::one();
+        let mut scale = T::one();
+        let (mut min, mut max) = self.range(|m| roll_pitch(m) + m.yaw * yaw);
+
+        if self.desaturation != Desaturation::Clip && max - min > T::one() {
+            if self.limit_yaw {
+                yaw_scale = self.yaw_room(&roll_pitch, yaw);
+                (min, max) = self.range(|m| roll_pitch(m) + m.yaw * yaw * yaw_scale);
+            }
+
+            if max - min > T::one() {
+                scale = T::one() / (max - min);
+                min *= scale;
+                max *= scale;
+            }
+        }
+
+        // Not a clamp, as rounding can leave the bounds crossed by a tiny amount
+        let fitted = throttle.max(-min).min(T::one() - max);
+        let throttle = match self.desaturation {
+            Desaturation::Clip => throttle,
+            Desaturation::ThrottleBoost => fitted.min(throttle + self.max_boost),
+            Desaturation::Airmode => fitted,
+        };
+
+        for (output, m) in outputs.iter_mut().zip(&self.motors) {
+            let torque = (roll_pitch(m) + m.yaw * yaw * yaw_scale) * scale;
+            *output = (throttle + torque).clamp(T::zero(), T::one());
+        }
+    }
+
+    fn mix_turtle(&mut self, sticks: Vector3<T>, outputs: &mut [T]) {
+        let yaw = match self.reversed {
+            true => -sticks.z,
+            false => sticks.z,
+        };
+        if let Some(servo) = &mut self.servo {
+            *servo = T::zero();
+        }
+
+        // Reversed thrust turns the vehicle the opposite way to the motor's mix
+        for (output, m) in outputs.iter_mut().zip(&self.motors) {
+            let torque = m.roll * sticks.x + m.pitch * sticks.y + m.yaw * yaw;
+            *output = (-torque).clamp(T::zero(), T::one());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEOMETRIES: [Geometry ; 5] = [
+        Geometry::QuadX,
+        Geometry::QuadPlus,
+        Geometry::HexX,
+        Geometry::OctoX,
+        Geometry::Tricopter,
+    ];
+
+    fn mix(mixer: &mut TableMixer<f32>, demand: Vector3<f32>, throttle: f32) -> std::vec::Vec<f32> {
+        let mut outputs = vec![0f32 ; mixer.motor_count()];
+        mixer.mix(demand, throttle, &mut outputs);
+        outputs
+    }
+
+    /// Get the roll, pitch and yaw torque and the mean throttle produced by a set of outputs
+    fn produced(mixer: &TableMixer<f32>, outputs: &[f32]) -> (Vector3<f32>, f32) {
+        let torque = mixer.motors().iter().zip(outputs).fold(Vector3::zeros(), |torque, (m, &o)| {
+            torque + Vector3::new(m.roll, m.pitch, m.yaw) * o
+        });
+        (torque, outputs.iter().sum::<f32>() / outputs.len() as f32)
+    }
+
+    fn apply(mixer: &mut TableMixer<f32>, demand: Vector3<f32>, throttle: f32) -> (Vector3<f32>, f32) {
+        let outputs = mix(mixer, demand, throttle);
+        produced(mixer, &outputs)
+    }
+
+    /// Torque produced by a demand with no saturation
+    fn unsaturated(mixer: &TableMixer<f32>, demand: Vector3<f32>) -> Vector3<f32> {
+        mixer.motors().iter().fold(Vector3::zeros(), |torque, m| {
+            let t = m.roll * demand.x + m.pitch * demand.y + m.yaw * demand.z;
+            torque + Vector3::new(m.roll, m.pitch, m.yaw) * t
+        })
+    }
+
+    #[test]
+    fn test_tables_balanced() {
+        for geometry in GEOMETRIES {
+            let mixer = TableMixer::<f32>::new(geometry);
+            let (torque, throttle) = produced(&mixer, &mix(&mut TableMixer::new(geometry), Vector3::zeros(), 0.4));
+            assert!(torque.norm() < 1e-4, "{geometry:?}: {torque}");
+            assert!((throttle - 0.4).abs() < 1e-6);
+
+            // Each axis produces torque on that axis alone
+            for axis in 0..3 {
+                let demand = Vector3::ith(axis, 0.1f32);
+                let torque = produced(&mixer, &mix(&mut TableMixer::new(geometry), demand, 0.5)).0;
+                let expected = unsaturated(&mixer, demand);
+                assert!((torque - expected).norm() < 1e-4, "{geometry:?} axis {axis}: {torque}");
+                assert!(torque[axis] >= 0.0 && (torque - Vector3::ith(axis, torque[axis])).norm() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_outputs_in_range() {
+        let demands = [-1f32, -0.6, -0.1, 0.0, 0.3, 1.0];
+        for geometry in GEOMETRIES {
+            for desaturation in [Desaturation::Clip, Desaturation::ThrottleBoost, Desaturation::Airmode] {
+                let mut mixer = TableMixer::new(geometry);
+                mixer.desaturation = desaturation;
+                for roll in demands {
+                    for pitch in demands {
+                        for yaw in demands {
+                            for throttle in [0f32, 0.1, 0.5, 0.95, 1.0] {
+                                let outputs = mix(&mut mixer, Vector3::new(roll, pitch, yaw), throttle);
+                                assert!(outputs.iter().all(|o| (0.0..=1.0).contains(o)), "{outputs:?}");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_airmode_preserves_torque() {
+        let mut mixer = TableMixer::new(Geometry::QuadX);
+        mixer.desaturation = Desaturation::Airmode;
+
+        // At zero and full throttle the throttle moves to make room for the torque
+        let demand = Vector3::new(0.3f32, -0.2, 0.1);
+        for throttle in [0f32, 1.0] {
+            let (torque, _) = apply(&mut mixer, demand, throttle);
+            assert!((torque - unsaturated(&mixer, demand)).norm() < 1e-4, "{torque}");
+        }
+
+        // Demands beyond the output range are scaled down, keeping their direction
+        let demand = Vector3::new(1f32, 0.5, 0.0);
+        let (torque, throttle) = apply(&mut mixer, demand, 0.2);
+        let expected = unsaturated(&mixer, demand);
+        assert!((torque.normalize() - expected.normalize()).norm() < 1e-4, "{torque}");
+        assert!((throttle - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_throttle_boost_limit() {
+        let mut mixer = TableMixer::new(Geometry::QuadX);
+        mixer.limit_yaw = false;
+        let demand = Vector3::new(0.3f32, 0.0, 0.0);
+
+        // Raised enough to keep the torque
+        let (torque, throttle) = apply(&mut mixer, demand, 0.1);
+        assert!((torque - unsaturated(&mixer, demand)).norm() < 1e-4);
+        assert!((throttle - 0.3).abs() < 1e-4);
+
+        // But no further than the limit, clipping what remains
+        let outputs = mix(&mut mixer, demand, 0.0);
+        assert_eq!(outputs, [0.0, 0.0, 0.5, 0.5]);
+
+        // Lowered freely at the top
+        let (torque, _) = apply(&mut mixer, demand, 1.0);
+        assert!((torque - unsaturated(&mixer, demand)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_yaw_limit() {
+        let mut mixer = TableMixer::new(Geometry::QuadX);
+        mixer.desaturation = Desaturation::Airmode;
+        let demand = Vector3::new(0.3f32, 0.1, 0.8);
+
+        // Roll and pitch are kept in full while yaw is reduced
+        let (torque, _) = apply(&mut mixer, demand, 0.5);
+        let expected = unsaturated(&mixer, demand);
+        assert!((torque.xy() - expected.xy()).norm() < 1e-4, "{torque}");
+        assert!(torque.z > 0.0 && torque.z < expected.z);
+        let outputs = mix(&mut mixer, demand, 0.5);
+        let range = outputs.iter().copied().fold(0f32, f32::max) - outputs.iter().copied().fold(1f32, f32::min);
+        assert!((range - 1.0).abs() < 1e-4);
+
+        // Without the limit every axis is reduced together
+        mixer.limit_yaw = false;
+        let (torque, _) = apply(&mut mixer, demand, 0.5);
+        assert!(torque.x < expected.x);
+        assert!((torque.normalize() - expected.normalize()).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_reversed_and_servo() {
+        let mut mixer = TableMixer::new(Geometry::QuadX);
+        let normal = mix(&mut mixer, Vector3::new(0.0, 0.0, 0.2), 0.5);
+        mixer.reversed = true;
+        let reversed = mix(&mut mixer, Vector3::new(0.0, 0.0, -0.2), 0.5);
+        assert_eq!(normal, reversed);
+        assert_eq!(mixer.servo(), None);
+
+        let mut tri = TableMixer::new(Geometry::Tricopter);
+        let outputs = mix(&mut tri, Vector3::new(0.0, 0.0, 0.5), 0.5);
+        assert_eq!(outputs, [0.5 ; 3]);
+        assert_eq!(tri.servo(), Some(0.5));
+        mix(&mut tri, Vector3::new(0.0, 0.0, -2.0), 0.5);
+        assert_eq!(tri.servo(), Some(-1.0));
+    }
+
+    #[test]
+    fn test_turtle() {
+        for geometry in GEOMETRIES {
+            let mut mixer = TableMixer::new(geometry);
+            for sticks in [Vector3::new(0.5f32, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.7, 0.7, 0.0)] {
+                let mut outputs = vec![0f32 ; mixer.motor_count()];
+                mixer.mix_turtle(sticks, &mut outputs);
+                assert!(outputs.iter().all(|o| (0.0..=1.0).contains(o)), "{geometry:?} {outputs:?}");
+
+                // Reversed thrust produces the opposite of the torque the outputs would normally
+                let (torque, _) = produced(&mixer, &outputs);
+                assert!((-torque).xy().dot(&sticks.xy()) > 0.0, "{geometry:?} {sticks} {torque}");
+            }
+        }
+
+        // Only the motors on one side spin to roll
+        let mut mixer = TableMixer::new(Geometry::QuadX);
+        let mut outputs = [0f32 ; 4];
+        mixer.mix_turtle(Vector3::new(0.5, 0.0, 0.0), &mut outputs);
+        assert_eq!(outputs, [0.5, 0.5, 0.0, 0.0]);
+
+        let mut tri = TableMixer::new(Geometry::Tricopter);
+        mix(&mut tri, Vector3::new(0.0, 0.0, 0.5), 0.5);
+        tri.mix_turtle(Vector3::new(0.0, 0.0, 0.5), &mut [0f32 ; 3]);
+        assert_eq!(tri.servo(), Some(0.0));
+    }
+
+    #[test]
+    fn test_custom_table() {
+        let motor = MotorMix { roll: 1f32, pitch: 0.0, yaw: 0.0 };
+        assert!(TableMixer::<f32>::custom(&[]).is_none());
+        assert!(TableMixer::custom(&[motor ; MAX_MOTORS + 1]).is_none());
+
+        let mut mixer = TableMixer::custom(&[motor, MotorMix { roll: -1.0, ..motor }]).unwrap();
+        assert_eq!(mixer.motor_count(), 2);
+        assert_eq!(mix(&mut mixer, Vector3::new(0.25, 0.0, 0.0), 0.5), [0.75, 0.25]);
+    }
+}
@@ -1,12 +1,24 @@
 use arbitrary_int::{u12, u24, u4, Number};
 use embedded_hal::{delay::DelayNs, spi::{Operation, SpiDevice}};
+use nalgebra::Vector3;
+
+use crate::interface::{imu::{Accelerometer, Gyroscope, Imu, ImuSample, Sample, Timestamp}, Sensor};
 
 pub mod regs;
 
+/// Frequency of the sensor time counter in Hz
+pub const SENSOR_TIME_HZ: u32 = 25_600;
+
+/// Raw X, Y, and Z axis readings
+pub type RawAxes = (i16, i16, i16);
+
 /// Driver for the BMI270 IMU on an SPI bus
 pub struct Bmi270<S: SpiDevice, D: DelayNs> {
     spi: S,
     delay: D,
+    acc_range: regs::AccRangeMode,
+    gyr_range: regs::GyrRangeMode,
+    odr: regs::OutputDataRate,
 }
 
 impl<S: SpiDevice, D: DelayNs> Bmi270<S, D> {
@@ -17,6 +29,9 @@ impl<S: SpiDevice, D: DelayNs> Bmi270<S, D> {
         Self {
             spi,
             delay,
+            acc_range: regs::AccRangeMode::Range16G,
+            gyr_range: regs::GyrRangeMode::Range2000,
+            odr: regs::OutputDataRate::Odr800,
         }
     }
     
@@ -31,7 +46,7 @@ impl<S: SpiDevice, D: DelayNs> Bmi270<S, D> {
         Ok(u24::from_le_bytes([buf[1], buf[2], buf[3]]))
     }
 
-    pub fn data(&mut self) -> Result<((i16, i16, i16), (i16, i16, i16)), S::Error> {
+    pub fn data(&mut self) -> Result<(RawAxes, RawAxes), S::Error> {
         let mut buf = [0u8 ; 13];
         self.spi.transaction(&mut [
             Operation::Write(&[0x0C | 0b10000000]),
@@ -46,6 +61,17 @@ impl<S: SpiDevice, D: DelayNs> Bmi270<S, D> {
         ))
     } 
 
+    /// Read accelerometer and gyroscope data along with the sensor time in one burst
+    pub fn sample(&mut self) -> Result<ImuSample<f32>, S::Error> {
+        let mut buf = [0u8 ; 16];
+        self.spi.transaction(&mut [
+            Operation::Write(&[0x0C | 0b10000000]),
+            Operation::Read(&mut buf)
+        ])?;
+
+        Ok(decode_sample(&buf, self.acc_scale(), self.gyr_scale()))
+    }
+
     pub fn status(&mut self) -> Result<regs::InternalStatus, S::Error> {
         self.read::<regs::InternalStatus>()
     }
@@ -74,23 +100,23 @@ impl<S: SpiDevice, D: DelayNs> Bmi270<S, D> {
     
     pub fn enable(&mut self) -> Result<(), S::Error> {
         self.write(regs::AccConf::DEFAULT
-            .with_acc_odr(regs::OutputDataRate::Odr800)
+            .with_acc_odr(self.odr)
             .with_acc_filter_perf(true)
             .with_acc_bwp(regs::AccBwp::NormAvg4)
         , 1)?;
 
 
-        self.write(regs::AccRange::DEFAULT.with_acc_range(regs::AccRangeMode::Range16G), 1)?;
+        self.write(regs::AccRange::DEFAULT.with_acc_range(self.acc_range), 1)?;
 
 
         self.write(regs::GyrConf::DEFAULT
-            .with_gyr_odr(regs::OutputDataRate::Odr800)
+            .with_gyr_odr(self.odr)
             .with_gyr_filter_perf(true)
             .with_gyr_noise_perf(false)
             .with_gyro_bwp(regs::GyrBwp::Norm)
         , 1)?;
 
-        self.write(regs::GyrRange::DEFAULT.with_gyr_range(regs::GyrRangeMode::Range2000), 1)?;
+        self.write(regs::GyrRange::DEFAULT.with_gyr_range(self.gyr_range), 1)?;
 
         self.write(regs::PwrCtrl::DEFAULT.with_acc_en(true).with_gyr_en(true).with_temp_en(true).with_aux_en(false), 1)?;
 
@@ -126,6 +152,30 @@ impl<S: SpiDevice, D: DelayNs> Bmi270<S, D> {
     }
 
 
+    /// Get the acceleration in g represented by one LSB of the configured range
+    fn acc_scale(&self) -> f32 {
+        let range = match self.acc_range {
+            regs::AccRangeMode::Range2G => 2f32,
+            regs::AccRangeMode::Range4G => 4f32,
+            regs::AccRangeMode::Range8G => 8f32,
+            regs::AccRangeMode::Range16G => 16f32,
+        };
+
+        range / 32768f32
+    }
+
+    /// Get the angular velocity in rad/s represented by one LSB of the configured range
+    fn gyr_scale(&self) -> f32 {
+        let range = match self.gyr_range {
+            regs::GyrRangeMode::Range1000 => 1000f32,
+            regs::GyrRangeMode::Range500 => 500f32,
+            regs::GyrRangeMode::Range250 => 250f32,
+            regs::GyrRangeMode::Range125 => 125f32,
+            _ => 2000f32,
+        };
+
+        range / 32768f32 * core::f32::consts::PI / 180f32
+    }
     
     /// Set the address used for initializing config file
     fn set_init_addr(&mut self, addr: u12) -> Result<(), S::Error> {
@@ -138,6 +188,82 @@ impl<S: SpiDevice, D: DelayNs> Bmi270<S, D> {
     }
 }
 
+impl<S: SpiDevice, D: DelayNs> Sensor for Bmi270<S, D> {
+    type Error = S::Error;
+}
+
+impl<S: SpiDevice, D: DelayNs> Accelerometer<f32> for Bmi270<S, D> {
+    fn linear_acceleration(&mut self) -> Result<Sample<f32>, Self::Error> {
+        let sample = self.sample()?;
+        Ok(Sample { value: sample.accel, timestamp: sample.timestamp })
+    }
+
+    fn accel_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read::<regs::Status>()?.drdy_acc())
+    }
+
+    fn accel_sample_rate(&self) -> f32 {
+        odr_hz(self.odr)
+    }
+}
+
+impl<S: SpiDevice, D: DelayNs> Gyroscope<f32> for Bmi270<S, D> {
+    fn angular_velocity(&mut self) -> Result<Sample<f32>, Self::Error> {
+        let sample = self.sample()?;
+        Ok(Sample { value: sample.gyro, timestamp: sample.timestamp })
+    }
+
+    fn gyro_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read::<regs::Status>()?.drdy_gyr())
+    }
+
+    fn gyro_sample_rate(&self) -> f32 {
+        odr_hz(self.odr)
+    }
+}
+
+impl<S: SpiDevice, D: DelayNs> Imu<f32> for Bmi270<S, D> {
+    fn read(&mut self) -> Result<ImuSample<f32>, Self::Error> {
+        self.sample()
+    }
+}
+
+/// Decode a burst read of the data registers starting at `ACC_X_LSB`, including the dummy byte
+fn decode_sample(buf: &[u8 ; 16], acc_scale: f32, gyr_scale: f32) -> ImuSample<f32> {
+    let decode = |idx: usize| i16::from_le_bytes([buf[idx], buf[idx + 1]]) as f32;
+
+    ImuSample {
+        accel: Vector3::new(decode(1), decode(3), decode(5)) * acc_scale,
+        gyro: Vector3::new(decode(7), decode(9), decode(11)) * gyr_scale,
+        timestamp: Timestamp::Sensor {
+            ticks: u32::from_le_bytes([buf[13], buf[14], buf[15], 0]),
+            hz: SENSOR_TIME_HZ,
+        },
+    }
+}
+
+/// Get the sample rate in Hz of an output data rate setting
+fn odr_hz(odr: regs::OutputDataRate) -> f32 {
+    match odr {
+        regs::OutputDataRate::Reserved => 0f32,
+        regs::OutputDataRate::Odr0p78 => 25f32 / 32f32,
+        regs::OutputDataRate::Odr1p5 => 25f32 / 16f32,
+        regs::OutputDataRate::Odr3p1 => 25f32 / 8f32,
+        regs::OutputDataRate::Odr6p25 => 6.25f32,
+        regs::OutputDataRate::Odr12p5 => 12.5f32,
+        regs::OutputDataRate::Odr25 => 25f32,
+        regs::OutputDataRate::Odr50 => 50f32,
+        regs::OutputDataRate::Odr100 => 100f32,
+        regs::OutputDataRate::Odr200 => 200f32,
+        regs::OutputDataRate::Odr400 => 400f32,
+        regs::OutputDataRate::Odr800 => 800f32,
+        regs::OutputDataRate::Odr1k6 => 1600f32,
+        regs::OutputDataRate::Odr3k2 => 3200f32,
+        regs::OutputDataRate::Odr6k4 => 6400f32,
+        regs::OutputDataRate::Odr12k8 => 12800f32,
+    }
+}
+
 #[derive(Debug)]
 pub enum Bmi270InitError<E: core::fmt::Debug> {
     SpiDevice(E),
@@ -149,3 +275,22 @@ impl<E: core::fmt::Debug> From<E> for Bmi270InitError<E> {
         Self::SpiDevice(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_sample() {
+        let mut buf = [0u8 ; 16];
+        buf[1..3].copy_from_slice(&2048i16.to_le_bytes());
+        buf[5..7].copy_from_slice(&(-4096i16).to_le_bytes());
+        buf[9..11].copy_from_slice(&16384i16.to_le_bytes());
+        buf[13..16].copy_from_slice(&[0x00, 0x64, 0x00]);
+
+        let sample = decode_sample(&buf, 16f32 / 32768f32, 1f32);
+        assert_eq!(sample.accel, Vector3::new(1f32, 0f32, -2f32));
+        assert_eq!(sample.gyro, Vector3::new(0f32, 16384f32, 0f32));
+        assert_eq!(sample.timestamp.sensor_micros(), Some(1_000_000));
+    }
+}
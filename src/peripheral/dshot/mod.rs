@@ -0,0 +1,323 @@
+//! DShot digital ESC protocol, encoding frames into the timer compare values that a timer and
+//! DMA stream shift out as pulses, independent of the MCU doing the shifting
+
+use crate::Micros;
+
+pub mod telemetry;
+
+/// Bits in a frame: 11 bits of throttle or command, the telemetry request bit and a 4-bit CRC
+pub const FRAME_BITS: usize = 16;
+
+/// Bit periods with the line held low after each frame, separating it from the next
+pub const RESET_BITS: usize = 2;
+
+/// Length of the compare value buffer for one motor
+pub const BUFFER_LEN: usize = FRAME_BITS + RESET_BITS;
+
+/// Timer ticks in each bit period
+pub const BIT_TICKS: u16 = 20;
+
+/// Ticks the line is high for a zero bit, 35% of the period
+pub const ZERO_TICKS: u16 = 7;
+
+/// Ticks the line is high for a one bit, 70% of the period
+pub const ONE_TICKS: u16 = 14;
+
+/// Lowest frame value that sets a throttle, with the values below it used for commands
+pub const THROTTLE_MIN: u16 = 48;
+
+/// Highest frame value, at full throttle
+pub const THROTTLE_MAX: u16 = 2047;
+
+/// Time to wait between repeats of a command, and after a command before the next
+pub const COMMAND_INTERVAL: Micros = 1000;
+
+/// Bit rate of the protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DshotSpeed {
+    Dshot150,
+    Dshot300,
+    Dshot600,
+    Dshot1200,
+}
+
+impl DshotSpeed {
+    /// Get the number of bits sent per second
+    pub const fn bit_rate(&self) -> u32 {
+        match self {
+            Self::Dshot150 => 150_000,
+            Self::Dshot300 => 300_000,
+            Self::Dshot600 => 600_000,
+            Self::Dshot1200 => 1_200_000,
+        }
+    }
+
+    /// Get the frequency a timer must count at for each bit to last [BIT_TICKS]
+    pub const fn timer_frequency(&self) -> u32 {
+        self.bit_rate() * BIT_TICKS as u32
+    }
+
+    /// Get the bit rate of bidirectional telemetry responses, 5/4 of the frame bit rate
+    pub const fn telemetry_bit_rate(&self) -> u32 {
+        self.bit_rate() * 5 / 4
+    }
+
+    /// Get the time taken to send a frame and its reset period in microseconds
+    pub const fn frame_time(&self) -> Micros {
+        (BUFFER_LEN as u32 * 1_000_000).div_ceil(self.bit_rate())
+    }
+}
+
+/// Command sent in place of a throttle value while the motors are stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Command {
+    MotorStop = 0,
+    Beep1 = 1,
+    Beep2 = 2,
+    Beep3 = 3,
+    Beep4 = 4,
+    Beep5 = 5,
+    /// Reply with ESC information over the telemetry wire
+    EscInfo = 6,
+    SpinDirection1 = 7,
+    SpinDirection2 = 8,
+    ThreeDModeOff = 9,
+    ThreeDModeOn = 10,
+    SettingsRequest = 11,
+    SaveSettings = 12,
+    /// Send temperature, voltage and current in bidirectional telemetry frames alongside eRPM
+    ExtendedTelemetryEnable = 13,
+    ExtendedTelemetryDisable = 14,
+    SpinDirectionNormal = 20,
+    SpinDirectionReversed = 21,
+}
+
+impl Command {
+    /// Get the number of consecutive times the command must be sent for the ESC to act on it.
+    /// Commands changing settings are repeated so that a corrupted frame can't change them
+    pub const fn repeats(&self) -> u8 {
+        match self {
+            Self::SpinDirection1
+            | Self::SpinDirection2
+            | Self::ThreeDModeOff
+            | Self::ThreeDModeOn
+            | Self::SaveSettings
+            | Self::ExtendedTelemetryEnable
+            | Self::ExtendedTelemetryDisable
+            | Self::SpinDirectionNormal
+            | Self::SpinDirectionReversed => 10,
+            _ => 1,
+        }
+    }
+
+    /// Get the time the ESC needs after the command before it accepts another
+    pub const fn delay(&self) -> Micros {
+        match self {
+            Self::Beep1 | Self::Beep2 | Self::Beep3 | Self::Beep4 | Self::Beep5 => 100_000,
+            Self::EscInfo => 12_000,
+            _ => COMMAND_INTERVAL,
+        }
+    }
+}
+
+/// 16-bit DShot frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame(u16);
+
+impl Frame {
+    /// Build a frame from an 11-bit value, requesting telemetry if `telemetry` is set
+    pub const fn new(value: u16, telemetry: bool) -> Self {
+        let data = (value & 0x7FF) << 1 | telemetry as u16;
+        Self(data << 4 | checksum(data))
+    }
+
+    /// Build a frame for a throttle in [0, 1], where zero stops the motor
+    pub fn throttle(throttle: f32, telemetry: bool) -> Self {
+        let value = match throttle > 0f32 {
+            true => THROTTLE_MIN + (throttle.min(1f32) * (THROTTLE_MAX - THROTTLE_MIN) as f32 + 0.5) as u16,
+            false => 0,
+        };
+
+        Self::new(value, telemetry)
+    }
+
+    /// Build a frame for a command, which always has the telemetry bit set as ESCs require
+    pub const fn command(command: Command) -> Self {
+        Self::new(command as u16, true)
+    }
+
+    /// Invert the checksum, which makes the ESC send telemetry after every frame. The output
+    /// must also be inverted to idle high, leaving the line free for the ESC to respond on
+    pub const fn inverted(self) -> Self {
+        Self(self.0 ^ 0xF)
+    }
+
+    /// Get the bits in the order they are sent, most significant first
+    pub const fn bits(&self) -> u16 {
+        self.0
+    }
+
+    /// Get the 11-bit throttle or command value
+    pub const fn value(&self) -> u16 {
+        self.0 >> 5
+    }
+
+    pub const fn telemetry(&self) -> bool {
+        self.0 & 1 << 4 != 0
+    }
+
+    /// Write the compare value of each bit period for this frame, followed by the reset period
+    pub fn encode(&self, buffer: &mut [u16 ; BUFFER_LEN]) {
+        encode_burst(&[*self], buffer);
+    }
+}
+
+/// Checksum of the 12 data bits, the XOR of their three nibbles
+const fn checksum(data: u16) -> u16 {
+    (data ^ data >> 4 ^ data >> 8) & 0xF
+}
+
+/// Write the compare values for several motors on channels of one timer, interleaved so that
+/// each update event's DMA burst writes one bit period to every channel in turn.
+///
+/// `buffer` must hold `BUFFER_LEN * frames.len()` values
+pub fn encode_burst(frames: &[Frame], buffer: &mut [u16]) {
+    let channels = frames.len();
+    assert_eq!(buffer.len(), BUFFER_LEN * channels);
+
+    for (bit, period) in buffer.chunks_exact_mut(channels).enumerate() {
+        for (value, frame) in period.iter_mut().zip(frames) {
+            *value = match bit < FRAME_BITS {
+                true if frame.0 & 0x8000 >> bit != 0 => ONE_TICKS,
+                true => ZERO_TICKS,
+                false => 0,
+            };
+        }
+    }
+}
+
+/// Sends a command to an ESC the number of times it needs, waiting between repeats and after
+/// the last for the ESC to act on it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CommandSequence {
+    command: Option<Command>,
+    sent: u8,
+    last: Micros,
+}
+
+impl CommandSequence {
+    pub const fn new() -> Self {
+        Self { command: None, sent: 0, last: 0 }
+    }
+
+    /// Begin sending a command, replacing any command in progress
+    pub fn start(&mut self, command: Command) {
+        self.command = Some(command);
+        self.sent = 0;
+    }
+
+    /// Whether a command is still being sent or waited on
+    pub const fn is_busy(&self) -> bool {
+        self.command.is_some()
+    }
+
+    /// Get the frame to send at `now`, which is the command when it's due and a motor stop
+    /// otherwise, or `None` once the sequence has finished
+    pub fn next(&mut self, now: Micros) -> Option<Frame> {
+        let command = self.command?;
+        let elapsed = now.wrapping_sub(self.last);
+        if self.sent >= command.repeats() {
+            if elapsed < command.delay() {
+                return Some(Frame::command(Command::MotorStop))
+            }
+            self.command = None;
+            return None
+        }
+
+        if self.sent > 0 && elapsed < COMMAND_INTERVAL {
+            return Some(Frame::command(Command::MotorStop))
+        }
+
+        self.sent += 1;
+        self.last = now;
+        Some(Frame::command(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_encoding() {
+        // Reference frames from the DShot protocol description
+        let frame = Frame::new(1046, false);
+        assert_eq!(frame.bits(), 0b1000_0010_1100_0110);
+        assert_eq!((frame.value(), frame.telemetry()), (1046, false));
+
+        assert_eq!(Frame::throttle(0.0, false).bits(), 0);
+        assert_eq!(Frame::throttle(1.0, false).value(), THROTTLE_MAX);
+        assert_eq!(Frame::throttle(1e-6, false).value(), THROTTLE_MIN);
+        assert_eq!(Frame::throttle(0.5, true).value(), 1048);
+        assert!(Frame::throttle(0.5, true).telemetry());
+
+        assert_eq!(Frame::new(1046, false).inverted().bits(), 0b1000_0010_1100_1001);
+
+        let command = Frame::command(Command::SaveSettings);
+        assert_eq!(command.bits(), 0b0000_0001_1001_1000);
+        assert_eq!(command.value(), 12);
+    }
+
+    #[test]
+    fn test_bit_buffer() {
+        let mut buffer = [0u16 ; BUFFER_LEN];
+        Frame::new(1046, false).encode(&mut buffer);
+        let expected = [1, 0, 0, 0, 0, 0, 1, 0, 1, 1, 0, 0, 0, 1, 1, 0]
+            .map(|bit| if bit == 1 { ONE_TICKS } else { ZERO_TICKS });
+        assert_eq!(buffer[..FRAME_BITS], expected);
+        assert_eq!(buffer[FRAME_BITS..], [0 ; RESET_BITS]);
+
+        let frames = [Frame::new(0xFFFF, true), Frame::new(0, false)];
+        let mut burst = [0u16 ; BUFFER_LEN * 2];
+        encode_burst(&frames, &mut burst);
+        assert_eq!(burst[..4], [ONE_TICKS, ZERO_TICKS, ONE_TICKS, ZERO_TICKS]);
+        assert_eq!(burst[BUFFER_LEN * 2 - 4..], [0 ; 4]);
+    }
+
+    #[test]
+    fn test_speeds() {
+        assert_eq!(DshotSpeed::Dshot600.timer_frequency(), 12_000_000);
+        assert_eq!(DshotSpeed::Dshot150.frame_time(), 120);
+        assert_eq!(DshotSpeed::Dshot1200.frame_time(), 15);
+    }
+
+    #[test]
+    fn test_command_sequence() {
+        let stop = Frame::command(Command::MotorStop);
+        let save = Frame::command(Command::SaveSettings);
+        let mut sequence = CommandSequence::new();
+        assert_eq!(sequence.next(0), None);
+
+        sequence.start(Command::SaveSettings);
+        let mut sent = 0;
+        let mut now = 10_000;
+        while let Some(frame) = sequence.next(now) {
+            if frame == save {
+                sent += 1;
+            } else {
+                assert_eq!(frame, stop);
+            }
+            now += 125;
+        }
+        assert_eq!(sent, 10);
+        // Nine intervals between repeats and the delay after the last
+        assert!(now - 10_000 >= 10 * COMMAND_INTERVAL);
+        assert!(!sequence.is_busy());
+
+        sequence.start(Command::Beep2);
+        assert_eq!(sequence.next(0), Some(Frame::command(Command::Beep2)));
+        assert_eq!(sequence.next(99_000), Some(stop));
+        assert_eq!(sequence.next(100_000), None);
+    }
+}
@@ -0,0 +1,259 @@
+//! Bidirectional DShot telemetry, sent by the ESC on the signal wire after each frame.
+//!
+//! The response is 21 bits with the line idling high: a start bit followed by 20 bits that
+//! carry a 16-bit value as four 5-bit GCR groups, where each 1 is sent as a transition. The
+//! value is either the electrical rotation period or, with extended telemetry enabled, one of
+//! the ESC's other measurements.
+
+/// Bits in a response, including the start bit
+pub const RESPONSE_BITS: u32 = 21;
+
+/// Value sent while the motor is stopped, the longest period that can be represented
+const STOPPED: u16 = 0xFFF;
+
+/// Error decoding a telemetry response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TelemetryError {
+    /// The edges didn't add up to a whole response
+    Length,
+    /// The bit time was zero ticks
+    BitTime,
+    /// A 5-bit group was not a valid GCR code
+    Gcr,
+    Checksum,
+}
+
+/// Status reported by the ESC in extended telemetry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EscStatus {
+    pub alert: bool,
+    pub warning: bool,
+    pub error: bool,
+    /// Highest stress level since the last status, from 0 to 15
+    pub max_stress: u8,
+}
+
+/// Measurement decoded from a telemetry response
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Telemetry {
+    /// Electrical revolutions per minute, the mechanical speed times half the motor's poles
+    Erpm(u32),
+    /// Temperature in °C
+    Temperature(u8),
+    /// Supply voltage in volts
+    Voltage(f32),
+    /// Current in amps
+    Current(u8),
+    Debug1(u8),
+    Debug2(u8),
+    /// Stress level from 0 to 255
+    Stress(u8),
+    Status(EscStatus),
+}
+
+impl Telemetry {
+    /// Interpret a 12-bit value, which is either an eRPM period or, if the ESC has extended
+    /// telemetry enabled, one of its other measurements. eRPM periods always have the top bit
+    /// of their mantissa set unless their exponent is zero, which leaves the other prefixes
+    /// free for extended telemetry
+    pub fn from_value(value: u16, extended: bool) -> Self {
+        if !extended {
+            return Self::Erpm(erpm(value))
+        }
+        let data = value as u8;
+        match value >> 8 {
+            0x2 => Self::Temperature(data),
+            0x4 => Self::Voltage(data as f32 * 0.25),
+            0x6 => Self::Current(data),
+            0x8 => Self::Debug1(data),
+            0xA => Self::Debug2(data),
+            0xC => Self::Stress(data),
+            0xE => Self::Status(EscStatus {
+                alert: data & 1 << 7 != 0,
+                warning: data & 1 << 6 != 0,
+                error: data & 1 << 5 != 0,
+                max_stress: data & 0xF,
+            }),
+            _ => Self::Erpm(erpm(value)),
+        }
+    }
+}
+
+/// Convert a 12-bit period value, a 9-bit mantissa shifted left by a 3-bit exponent in µs,
+/// into electrical revolutions per minute
+pub const fn erpm(value: u16) -> u32 {
+    let period = ((value & 0x1FF) as u32) << (value >> 9);
+    match (value, period) {
+        (STOPPED, _) | (_, 0) => 0,
+        _ => 60_000_000 / period,
+    }
+}
+
+/// Convert electrical revolutions per minute into mechanical revolutions per minute for a
+/// motor with `poles` magnet poles
+pub const fn rpm(erpm: u32, poles: u8) -> u32 {
+    match poles {
+        0 | 1 => erpm,
+        _ => erpm * 2 / poles as u32,
+    }
+}
+
+/// Convert the times of each edge in a captured response, starting at the falling edge of the
+/// start bit, into the 21 received bits with the start bit as the most significant.
+///
+/// Each interval between edges is rounded to the nearest whole number of bits, and the bits
+/// after the last edge are the remainder of the response, as the line returns to idle after it
+pub fn edges_to_bits(edges: &[u32], ticks_per_bit: u32) -> Result<u32, TelemetryError> {
+    if ticks_per_bit == 0 {
+        return Err(TelemetryError::BitTime)
+    }
+
+    let mut value = 0u32;
+    let mut bits = 0;
+    for pair in edges.windows(2) {
+        let interval = pair[1].wrapping_sub(pair[0]);
+        let len = interval.saturating_add(ticks_per_bit / 2) / ticks_per_bit;
+        if len == 0 || len >= RESPONSE_BITS - bits {
+            return Err(TelemetryError::Length)
+        }
+        // Each transition is a 1, followed by a 0 for every bit without one
+        value = value << len | 1 << (len - 1);
+        bits += len;
+    }
+
+    if edges.is_empty() {
+        return Err(TelemetryError::Length)
+    }
+    let len = RESPONSE_BITS - bits;
+    Ok(value << len | 1 << (len - 1))
+}
+
+/// Decode the 20 GCR bits of a response into a 12-bit value, checking its checksum
+pub fn decode_gcr(bits: u32) -> Result<u16, TelemetryError> {
+    let mut value = 0u16;
+    for group in (0..4).rev() {
+        let nibble = gcr_decode((bits >> (group * 5)) as u8 & 0x1F).ok_or(TelemetryError::Gcr)?;
+        value = value << 4 | nibble as u16;
+    }
+
+    // The checksum is inverted, so the XOR of all four nibbles is 0xF
+    let checksum = value ^ value >> 4 ^ value >> 8 ^ value >> 12;
+    match checksum & 0xF {
+        0xF => Ok(value >> 4),
+        _ => Err(TelemetryError::Checksum),
+    }
+}
+
+/// Decode a captured response from the times of its edges, with `extended` set if the ESC
+/// has extended telemetry enabled
+pub fn decode(edges: &[u32], ticks_per_bit: u32, extended: bool) -> Result<Telemetry, TelemetryError> {
+    let bits = edges_to_bits(edges, ticks_per_bit)?;
+    decode_gcr(bits).map(|value| Telemetry::from_value(value, extended))
+}
+
+const fn gcr_decode(code: u8) -> Option<u8> {
+    Some(match code {
+        0x19 => 0x0,
+        0x1B => 0x1,
+        0x12 => 0x2,
+        0x13 => 0x3,
+        0x1D => 0x4,
+        0x15 => 0x5,
+        0x16 => 0x6,
+        0x17 => 0x7,
+        0x1A => 0x8,
+        0x09 => 0x9,
+        0x0A => 0xA,
+        0x0B => 0xB,
+        0x1E => 0xC,
+        0x0D => 0xD,
+        0x0E => 0xE,
+        0x0F => 0xF,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKS: u32 = 16;
+
+    /// Build the edge times an ESC sends a 12-bit value with, as a capture would record them
+    fn capture(value: u16, start: u32) -> Vec<u32> {
+        const GCR: [u32 ; 16] = [
+            0x19, 0x1B, 0x12, 0x13, 0x1D, 0x15, 0x16, 0x17, 0x1A, 0x09, 0x0A, 0x0B, 0x1E, 0x0D, 0x0E, 0x0F,
+        ];
+        let checksum = !(value ^ value >> 4 ^ value >> 8) & 0xF;
+        let data = value << 4 | checksum;
+        let gcr = (0..4).fold(0u32, |gcr, i| gcr << 5 | GCR[(data >> (12 - i * 4) & 0xF) as usize]);
+
+        // Start bit, then a transition for each 1
+        let bits = 1 << 20 | gcr;
+        (0..RESPONSE_BITS)
+            .filter(|i| bits & 1 << (RESPONSE_BITS - 1 - i) != 0)
+            .map(|i| start.wrapping_add(i * TICKS))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_erpm() {
+        // 20 000 eRPM is a 3000 µs period, 375 << 3
+        let value = 3 << 9 | 375;
+        let edges = capture(value, 1000);
+        assert_eq!(decode(&edges, TICKS, false), Ok(Telemetry::Erpm(20_000)));
+        assert_eq!(rpm(20_000, 14), 2857);
+
+        assert_eq!(decode(&capture(STOPPED, 0), TICKS, false), Ok(Telemetry::Erpm(0)));
+        assert_eq!(decode(&edges, TICKS, true), Ok(Telemetry::Erpm(20_000)));
+        assert_eq!(erpm(0), 0);
+    }
+
+    #[test]
+    fn test_capture_jitter() {
+        // Edges a few ticks early or late, and a timer wrapping during the response
+        let mut edges = capture(1 << 9 | 0x1AB, u32::MAX - 100);
+        for (i, edge) in edges.iter_mut().enumerate() {
+            *edge = edge.wrapping_add([0, 3, u32::MAX - 2, 2][i % 4]);
+        }
+        assert_eq!(decode(&edges, TICKS, false), Ok(Telemetry::Erpm(60_000_000 / (0x1AB << 1))));
+    }
+
+    #[test]
+    fn test_decode_extended() {
+        let cases = [
+            (0x24B, Telemetry::Temperature(0x4B)),
+            (0x430, Telemetry::Voltage(12.0)),
+            (0x612, Telemetry::Current(18)),
+            (0x801, Telemetry::Debug1(1)),
+            (0xA02, Telemetry::Debug2(2)),
+            (0xCFF, Telemetry::Stress(255)),
+            (0xEA7, Telemetry::Status(EscStatus { alert: true, warning: false, error: true, max_stress: 7 })),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(decode(&capture(value, 0), TICKS, true), Ok(expected), "{value:#x}");
+            assert_eq!(decode(&capture(value, 0), TICKS, false), Ok(Telemetry::Erpm(erpm(value))));
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut edges = capture(0x24B, 0);
+        assert_eq!(decode(&edges[..1], TICKS, false), Err(TelemetryError::Gcr));
+        assert_eq!(decode(&[], TICKS, false), Err(TelemetryError::Length));
+
+        // A stray edge lengthens the response beyond 21 bits
+        edges.push(edges.last().unwrap() + 4 * TICKS);
+        edges.push(edges.last().unwrap() + 4 * TICKS);
+        assert_eq!(decode(&edges, TICKS, false), Err(TelemetryError::Length));
+
+        // A zero bit time, and an interval that would overflow when rounded
+        assert_eq!(decode(&capture(0x24B, 0), 0, false), Err(TelemetryError::BitTime));
+        assert_eq!(edges_to_bits(&[0, u32::MAX], TICKS), Err(TelemetryError::Length));
+        assert_eq!(edges_to_bits(&[0, u32::MAX - 1], 1), Err(TelemetryError::Length));
+
+        // Swapping the first group from 2 to 3, another valid code, breaks the checksum
+        let bits = edges_to_bits(&capture(0x24B, 0), TICKS).unwrap();
+        assert_eq!(decode_gcr(bits ^ (0x12 ^ 0x13) << 15), Err(TelemetryError::Checksum));
+    }
+}
@@ -0,0 +1,220 @@
+//! Analog ESC protocols, where the throttle is the width of a pulse repeated at a fixed rate
+
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::interface::motor::MotorOutput;
+
+/// Pulse protocol understood by the ESCs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// Standard servo PWM, 1000 to 2000 µs pulses at a rate from 50 to 490 Hz
+    Pwm { rate: u32 },
+    /// 125 to 250 µs pulses
+    OneShot125,
+    /// 42 to 84 µs pulses
+    OneShot42,
+    /// 5 to 25 µs pulses
+    MultiShot,
+}
+
+impl Protocol {
+    /// Lowest and highest rates standard PWM ESCs accept in Hz
+    pub const PWM_RATES: (u32, u32) = (50, 490);
+
+    /// Get the shortest and longest pulse in nanoseconds
+    pub const fn pulse_range(&self) -> (u32, u32) {
+        match self {
+            Self::Pwm { .. } => (1_000_000, 2_000_000),
+            Self::OneShot125 => (125_000, 250_000),
+            Self::OneShot42 => (42_000, 84_000),
+            Self::MultiShot => (5_000, 25_000),
+        }
+    }
+
+    /// Get the rate pulses are sent at in Hz, which the PWM channels must be configured with.
+    /// Apart from PWM, each protocol runs as fast as its longest pulse allows with some margin
+    pub const fn frequency(&self) -> u32 {
+        match *self {
+            Self::Pwm { rate } if rate < Self::PWM_RATES.0 => Self::PWM_RATES.0,
+            Self::Pwm { rate } if rate > Self::PWM_RATES.1 => Self::PWM_RATES.1,
+            Self::Pwm { rate } => rate,
+            Self::OneShot125 => 2000,
+            Self::OneShot42 => 8000,
+            Self::MultiShot => 32_000,
+        }
+    }
+
+    /// Get the time between the start of each pulse in nanoseconds
+    pub const fn period(&self) -> u32 {
+        1_000_000_000 / self.frequency()
+    }
+}
+
+/// Throttle endpoints as fractions of the protocol's pulse range, so that 0 is the shortest
+/// pulse and 1 the longest
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Endpoints {
+    /// Pulse that stops the motor, sent while disarmed
+    pub stop: f32,
+    /// Pulse at the lowest throttle, where the motor spins reliably
+    pub min: f32,
+    /// Pulse at full throttle
+    pub max: f32,
+    /// Stop the motors at zero throttle while armed rather than letting them idle at `min`
+    pub motor_stop: bool,
+}
+
+impl Default for Endpoints {
+    /// Equivalent to Betaflight's defaults of 1000 µs to stop, 1070 µs idle and 2000 µs at full
+    /// throttle on PWM
+    fn default() -> Self {
+        Self {
+            stop: 0.0,
+            min: 0.07,
+            max: 1.0,
+            motor_stop: false,
+        }
+    }
+}
+
+/// Step of ESC throttle range calibration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Calibration {
+    /// Send the full throttle pulse, which ESCs look for when powered up to start calibrating
+    High,
+    /// Send the stop pulse, after the ESC acknowledges the full throttle pulse
+    Low,
+}
+
+/// [MotorOutput] driving an ESC from each channel of a PWM timer, which must already be
+/// running at the protocol's [frequency](Protocol::frequency)
+pub struct AnalogEsc<P: SetDutyCycle, const N: usize> {
+    channels: [P ; N],
+    max_duty: [u16 ; N],
+    protocol: Protocol,
+    pub endpoints: Endpoints,
+}
+
+impl<P: SetDutyCycle, const N: usize> AnalogEsc<P, N> {
+    /// Create the output and send the stop pulse on every channel
+    pub fn new(mut channels: [P ; N], protocol: Protocol, endpoints: Endpoints) -> Result<Self, P::Error> {
+        let max_duty = channels.each_mut().map(|channel| channel.max_duty_cycle());
+        let mut esc = Self { channels, max_duty, protocol, endpoints };
+        esc.stop()?;
+        Ok(esc)
+    }
+
+    pub const fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Release the PWM channels
+    pub fn free(self) -> [P ; N] {
+        self.channels
+    }
+
+    /// Send a calibration pulse to all ESCs. Power the ESCs up while sending [Calibration::High],
+    /// then send [Calibration::Low] once they signal that they have seen it
+    pub fn calibrate(&mut self, step: Calibration) -> Result<(), P::Error> {
+        let fraction = match step {
+            Calibration::High => self.endpoints.max,
+            Calibration::Low => self.endpoints.stop,
+        };
+
+        self.set_all(fraction)
+    }
+
+    fn set_all(&mut self, fraction: f32) -> Result<(), P::Error> {
+        for i in 0..N {
+            self.set(i, fraction)?;
+        }
+
+        Ok(())
+    }
+
+    /// Set a channel's pulse to a fraction of the protocol's pulse range
+    fn set(&mut self, channel: usize, fraction: f32) -> Result<(), P::Error> {
+        let (shortest, longest) = self.protocol.pulse_range();
+        let pulse = shortest + ((longest - shortest) as f32 * fraction.clamp(0f32, 1f32)) as u32;
+        let duty = pulse as u64 * self.max_duty[channel] as u64 / self.protocol.period() as u64;
+        self.channels[channel].set_duty_cycle(duty as u16)
+    }
+}
+
+impl<P: SetDutyCycle, const N: usize> MotorOutput for AnalogEsc<P, N> {
+    type Error = P::Error;
+
+    fn set_throttle(&mut self, throttle: &[f32]) -> Result<(), Self::Error> {
+        let Endpoints { stop, min, max, motor_stop } = self.endpoints;
+        for (i, &throttle) in throttle.iter().enumerate().take(N) {
+            let fraction = match throttle > 0f32 {
+                true => min + throttle.min(1f32) * (max - min),
+                false if motor_stop => stop,
+                false => min,
+            };
+            self.set(i, fraction)?;
+        }
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Self::Error> {
+        self.set_all(self.endpoints.stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal_mock::eh1::pwm::{Mock, Transaction};
+
+    use super::*;
+
+    fn channel(max_duty: u16, duties: &[u16]) -> Mock {
+        let mut transactions = vec![Transaction::max_duty_cycle(max_duty)];
+        transactions.extend(duties.iter().map(|&duty| Transaction::set_duty_cycle(duty)));
+        Mock::new(&transactions)
+    }
+
+    #[test]
+    fn test_protocol_timing() {
+        assert_eq!(Protocol::Pwm { rate: 20 }.frequency(), 50);
+        assert_eq!(Protocol::Pwm { rate: 1000 }.frequency(), 490);
+        assert_eq!(Protocol::Pwm { rate: 400 }.period(), 2_500_000);
+        for protocol in [Protocol::OneShot125, Protocol::OneShot42, Protocol::MultiShot] {
+            assert!(protocol.period() > protocol.pulse_range().1, "{protocol:?}");
+        }
+    }
+
+    #[test]
+    fn test_pwm_pulses() {
+        // At 400 Hz, a duty of 10000 is the full 2500 µs period, so 1 µs is 4 counts
+        let endpoints = Endpoints { motor_stop: true, ..Default::default() };
+        let channels = [
+            channel(10_000, &[4000, 4000, 8000]),
+            channel(10_000, &[4000, 4000, 6140]),
+            channel(10_000, &[4000, 4000, 4280]),
+        ];
+        let mut esc = AnalogEsc::new(channels, Protocol::Pwm { rate: 400 }, endpoints).unwrap();
+        esc.set_throttle(&[0.0, 0.0, 0.0]).unwrap();
+        esc.endpoints.motor_stop = false;
+        esc.set_throttle(&[1.0, 0.5, 0.0]).unwrap();
+
+        for channel in &mut esc.free() {
+            channel.done();
+        }
+    }
+
+    #[test]
+    fn test_oneshot_and_calibration() {
+        // At 2 kHz, a duty of 1000 is the 500 µs period, so OneShot125 spans 250 to 500
+        let channels = [channel(1000, &[250, 500, 250, 279])];
+        let mut esc = AnalogEsc::new(channels, Protocol::OneShot125, Endpoints::default()).unwrap();
+        esc.calibrate(Calibration::High).unwrap();
+        esc.calibrate(Calibration::Low).unwrap();
+        esc.set_throttle(&[0.05]).unwrap();
+
+        for channel in &mut esc.free() {
+            channel.done();
+        }
+    }
+}
@@ -0,0 +1,4 @@
+//! Drivers for ESCs using protocols other than DShot, and for their serial telemetry
+
+pub mod analog;
+pub mod telemetry;
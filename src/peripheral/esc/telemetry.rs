@@ -0,0 +1,273 @@
+//! KISS serial ESC telemetry, also sent by BLHeli_32 ESCs, on a UART line shared by all ESCs.
+//!
+//! An ESC replies with a 10-byte frame after receiving a DShot frame with the telemetry bit
+//! set, so only one motor is asked at a time and the reply is attributed to it. All values are
+//! big-endian: temperature in °C, voltage in 10 mV, current in 10 mA, consumption in mAh and
+//! speed in 100 eRPM, followed by a CRC-8 of the other nine bytes.
+
+use embedded_io::{Read, ReadReady};
+
+use crate::{math::crc::crc8_smbus_slice, peripheral::dshot::telemetry::rpm, Micros};
+
+/// Length of a telemetry frame, including the CRC
+pub const FRAME_LEN: usize = 10;
+
+/// Time to wait for a reply before asking the next motor. A frame takes under 1 ms at the
+/// 115200 baud ESCs use
+pub const RESPONSE_TIMEOUT: Micros = 5000;
+
+/// Error parsing a telemetry frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TelemetryError {
+    Checksum,
+}
+
+/// Measurements from one ESC
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EscTelemetry {
+    /// Temperature in °C
+    pub temperature: u8,
+    /// Supply voltage in volts
+    pub voltage: f32,
+    /// Current in amps
+    pub current: f32,
+    /// Charge used since the ESC powered up in mAh
+    pub consumption: u16,
+    /// Electrical revolutions per minute
+    pub erpm: u32,
+}
+
+impl EscTelemetry {
+    pub fn parse(frame: &[u8 ; FRAME_LEN]) -> Result<Self, TelemetryError> {
+        if crc8_smbus_slice(&frame[..FRAME_LEN - 1]) != frame[FRAME_LEN - 1] {
+            return Err(TelemetryError::Checksum)
+        }
+
+        let word = |i: usize| u16::from_be_bytes([frame[i], frame[i + 1]]);
+        Ok(Self {
+            temperature: frame[0],
+            voltage: word(1) as f32 * 0.01,
+            current: word(3) as f32 * 0.01,
+            consumption: word(5),
+            erpm: word(7) as u32 * 100,
+        })
+    }
+
+    /// Get the mechanical revolutions per minute of a motor with `poles` magnet poles
+    pub const fn rpm(&self, poles: u8) -> u32 {
+        rpm(self.erpm, poles)
+    }
+}
+
+/// Latest telemetry from an ESC and counts of how its replies have fared
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EscHealth {
+    /// Last valid frame, or `None` if the ESC hasn't sent one
+    pub telemetry: Option<EscTelemetry>,
+    /// Time the last valid frame was received
+    pub updated: Micros,
+    pub frames: u32,
+    /// Replies with an incorrect CRC
+    pub crc_errors: u32,
+    /// Requests the ESC didn't complete a reply to within [RESPONSE_TIMEOUT]
+    pub timeouts: u32,
+}
+
+impl EscHealth {
+    /// Whether a valid frame was received within `max_age` of `now`
+    pub fn is_fresh(&self, now: Micros, max_age: Micros) -> bool {
+        self.telemetry.is_some() && now.wrapping_sub(self.updated) <= max_age
+    }
+}
+
+/// Reads the telemetry of `N` ESCs from a UART, asking each motor for a reply in turn
+pub struct EscTelemetryReader<R, const N: usize> {
+    uart: R,
+    frame: [u8 ; FRAME_LEN],
+    len: usize,
+    /// Motor a reply is expected from and the time it was asked
+    pending: Option<(usize, Micros)>,
+    next: usize,
+    health: [EscHealth ; N],
+}
+
+impl<R, const N: usize> EscTelemetryReader<R, N>
+where R: Read + ReadReady
+{
+    pub fn new(uart: R) -> Self {
+        Self {
+            uart,
+            frame: [0u8 ; FRAME_LEN],
+            len: 0,
+            pending: None,
+            next: 0,
+            health: [EscHealth::default() ; N],
+        }
+    }
+
+    /// Get the motor whose next DShot frame must request telemetry, or `None` while the reply
+    /// to the previous request is still due
+    pub fn request(&mut self, now: Micros) -> Option<usize> {
+        if let Some((motor, requested)) = self.pending {
+            if now.wrapping_sub(requested) < RESPONSE_TIMEOUT {
+                return None
+            }
+            self.health[motor].timeouts += 1;
+        }
+
+        let motor = self.next;
+        self.next = (motor + 1) % N;
+        self.pending = Some((motor, now));
+        self.len = 0;
+        Some(motor)
+    }
+
+    /// Read the bytes the UART has received, returning the motor whose telemetry was updated if
+    /// they complete a valid reply. Bytes arriving when no reply is expected are discarded
+    pub fn poll(&mut self, now: Micros) -> Result<Option<usize>, R::Error> {
+        while self.uart.read_ready()? {
+            let Some((motor, _)) = self.pending else {
+                self.uart.read(&mut [0u8 ; FRAME_LEN])?;
+                continue
+            };
+
+            let read = self.uart.read(&mut self.frame[self.len..])?;
+            self.len += read;
+            if self.len < FRAME_LEN {
+                if read == 0 {
+                    break
+                }
+                continue
+            }
+
+            self.pending = None;
+            self.len = 0;
+            let health = &mut self.health[motor];
+            match EscTelemetry::parse(&self.frame) {
+                Ok(telemetry) => {
+                    health.telemetry = Some(telemetry);
+                    health.updated = now;
+                    health.frames += 1;
+                    return Ok(Some(motor))
+                },
+                Err(TelemetryError::Checksum) => health.crc_errors += 1,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the telemetry and reply counts of each motor
+    pub const fn health(&self) -> &[EscHealth ; N] {
+        &self.health
+    }
+
+    /// Get the total current drawn by the ESCs that have reported, in amps
+    pub fn current(&self) -> f32 {
+        self.reports().map(|t| t.current).sum()
+    }
+
+    /// Get the total charge used by the ESCs that have reported, in mAh
+    pub fn consumption(&self) -> u32 {
+        self.reports().map(|t| t.consumption as u32).sum()
+    }
+
+    /// Get the mean supply voltage reported by the ESCs, or `None` if none have reported
+    pub fn voltage(&self) -> Option<f32> {
+        let (sum, count) = self.reports().fold((0f32, 0), |(sum, count), t| (sum + t.voltage, count + 1));
+        (count > 0).then(|| sum / count as f32)
+    }
+
+    /// Release the UART
+    pub fn free(self) -> R {
+        self.uart
+    }
+
+    fn reports(&self) -> impl Iterator<Item = &EscTelemetry> {
+        self.health.iter().filter_map(|health| health.telemetry.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::MockUart;
+
+    fn frame(temperature: u8, voltage: u16, current: u16, consumption: u16, erpm: u16) -> [u8 ; FRAME_LEN] {
+        let mut frame = [0u8 ; FRAME_LEN];
+        frame[0] = temperature;
+        for (i, word) in [voltage, current, consumption, erpm].into_iter().enumerate() {
+            frame[1 + i * 2..3 + i * 2].copy_from_slice(&word.to_be_bytes());
+        }
+        frame[FRAME_LEN - 1] = crc8_smbus_slice(&frame[..FRAME_LEN - 1]);
+        frame
+    }
+
+    #[test]
+    fn test_parse() {
+        let telemetry = EscTelemetry::parse(&frame(45, 1620, 1234, 350, 240)).unwrap();
+        assert_eq!(telemetry.temperature, 45);
+        assert!((telemetry.voltage - 16.2).abs() < 1e-4);
+        assert!((telemetry.current - 12.34).abs() < 1e-4);
+        assert_eq!((telemetry.consumption, telemetry.erpm), (350, 24_000));
+        assert_eq!(telemetry.rpm(14), 3428);
+
+        let mut corrupt = frame(45, 1620, 1234, 350, 240);
+        corrupt[2] ^= 1;
+        assert_eq!(EscTelemetry::parse(&corrupt), Err(TelemetryError::Checksum));
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut reader = EscTelemetryReader::<_, 3>::new(MockUart::<64>::new());
+        assert_eq!(reader.request(0), Some(0));
+        assert_eq!(reader.request(1000), None);
+
+        // The reply arrives in pieces
+        let reply = frame(40, 1600, 500, 10, 100);
+        reader.uart.receive(&reply[..4]);
+        assert_eq!(reader.poll(1500), Ok(None));
+        reader.uart.receive(&reply[4..]);
+        assert_eq!(reader.poll(2000), Ok(Some(0)));
+        assert_eq!(reader.health()[0].updated, 2000);
+
+        // Motor 1 never replies, so motor 2 is asked after the timeout
+        assert_eq!(reader.request(2000), Some(1));
+        assert_eq!(reader.request(2000 + RESPONSE_TIMEOUT - 1), None);
+        assert_eq!(reader.request(2000 + RESPONSE_TIMEOUT), Some(2));
+        assert_eq!(reader.health()[1].timeouts, 1);
+
+        reader.uart.receive(&frame(50, 1580, 700, 20, 100));
+        assert_eq!(reader.poll(8000), Ok(Some(2)));
+        assert_eq!(reader.request(8000), Some(0));
+
+        assert!((reader.current() - 12.0).abs() < 1e-4);
+        assert_eq!(reader.consumption(), 30);
+        assert!((reader.voltage().unwrap() - 15.9).abs() < 1e-4);
+        assert!(reader.health()[0].is_fresh(8000, 6000));
+        assert!(!reader.health()[0].is_fresh(8001, 6000));
+        assert!(!reader.health()[1].is_fresh(8000, 6000));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut reader = EscTelemetryReader::<_, 2>::new(MockUart::<64>::new());
+
+        // Noise before any request is ignored
+        reader.uart.receive(&[0xFF ; 3]);
+        assert_eq!(reader.poll(0), Ok(None));
+        assert!(reader.uart.rx.is_empty());
+
+        let mut reply = frame(40, 1600, 500, 10, 100);
+        reply[0] = 41;
+        reader.request(0);
+        reader.uart.receive(&reply);
+        assert_eq!(reader.poll(1000), Ok(None));
+        assert_eq!(reader.health()[0].crc_errors, 1);
+        assert_eq!(reader.health()[0].telemetry, None);
+        assert_eq!(reader.voltage(), None);
+
+        // A corrupt reply still completes the request, so the next motor is asked immediately
+        assert_eq!(reader.request(1000), Some(1));
+    }
+}
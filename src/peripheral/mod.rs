@@ -1,4 +1,7 @@
 pub mod bmi270;
+pub mod dshot;
+pub mod esc;
+pub mod spiflash;
 
 pub trait Register: From<u8> + Into<u8> {
     const ADDRESS: u32;
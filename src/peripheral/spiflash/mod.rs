@@ -0,0 +1,283 @@
+//! Driver for JEDEC SPI NOR flash memories such as the Winbond W25Q and Micron M25P families
+
+use bitflags::bitflags;
+use embedded_hal::spi::{Operation, SpiDevice};
+
+use crate::interface::flash::NorFlash;
+
+const WRITE_ENABLE: u8 = 0x06;
+const READ_STATUS: u8 = 0x05;
+const READ_DATA: u8 = 0x03;
+const PAGE_PROGRAM: u8 = 0x02;
+const SECTOR_ERASE_4K: u8 = 0x20;
+const BLOCK_ERASE_64K: u8 = 0xD8;
+const CHIP_ERASE: u8 = 0xC7;
+const READ_JEDEC_ID: u8 = 0x9F;
+
+/// Size of a program page, the same for all supported devices
+pub const PAGE_SIZE: u32 = 256;
+
+/// Largest memory addressable with the 3-byte addresses this driver uses
+pub const MAX_CAPACITY: u32 = 1 << 24;
+
+bitflags! {
+    /// Status register 1
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Status: u8 {
+        /// A program or erase is in progress
+        const BUSY = 1 << 0;
+        /// Program and erase commands will be accepted
+        const WRITE_ENABLED = 1 << 1;
+        /// Block protect bits
+        const BP0 = 1 << 2;
+        const BP1 = 1 << 3;
+        const BP2 = 1 << 4;
+    }
+}
+
+/// Identification returned by the JEDEC read ID command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JedecId {
+    pub manufacturer: u8,
+    pub memory_type: u8,
+    /// Log2 of the capacity in bytes
+    pub capacity: u8,
+}
+
+impl JedecId {
+    pub const WINBOND: u8 = 0xEF;
+    pub const MICRON: u8 = 0x20;
+    pub const MACRONIX: u8 = 0xC2;
+
+    /// Get the size of the smallest erasable region and the command that erases it, or `None`
+    /// if the device is not known
+    pub const fn erase_region(&self) -> Option<(u32, u8)> {
+        match (self.manufacturer, self.memory_type) {
+            // M25P devices only support 64 KiB sector erase
+            (Self::MICRON, 0x20) => Some((64 * 1024, BLOCK_ERASE_64K)),
+            (Self::MICRON, 0xBA | 0xBB) => Some((4096, SECTOR_ERASE_4K)),
+            (Self::WINBOND, 0x40 | 0x60 | 0x70) => Some((4096, SECTOR_ERASE_4K)),
+            (Self::MACRONIX, 0x20) => Some((4096, SECTOR_ERASE_4K)),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned by the [SpiFlash] driver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpiFlashError<E> {
+    Spi(E),
+    /// The device did not identify as a supported flash memory
+    UnknownDevice(JedecId),
+    /// An address was beyond the end of the memory or a program crossed a page boundary
+    OutOfRange,
+}
+
+impl<E> From<E> for SpiFlashError<E> {
+    fn from(e: E) -> Self {
+        Self::Spi(e)
+    }
+}
+
+/// Driver for a JEDEC SPI NOR flash on an SPI bus.
+///
+/// Memories larger than [MAX_CAPACITY] are limited to their first 16 MiB, as they need 4-byte
+/// addressing for the rest
+pub struct SpiFlash<S: SpiDevice> {
+    spi: S,
+    id: JedecId,
+    capacity: u32,
+    erase_size: u32,
+    erase_command: u8,
+}
+
+impl<S: SpiDevice> SpiFlash<S> {
+    /// Identify the flash memory on an SpiDevice, failing if it is not a supported device
+    pub fn new(mut spi: S) -> Result<Self, SpiFlashError<S::Error>> {
+        let id = read_id(&mut spi)?;
+        let Some((erase_size, erase_command)) = id.erase_region() else {
+            return Err(SpiFlashError::UnknownDevice(id))
+        };
+
+        Ok(Self {
+            spi,
+            id,
+            capacity: 1u32.checked_shl(id.capacity as u32).unwrap_or(MAX_CAPACITY).min(MAX_CAPACITY),
+            erase_size,
+            erase_command,
+        })
+    }
+
+    pub const fn id(&self) -> JedecId {
+        self.id
+    }
+
+    /// Release the SpiDevice
+    pub fn free(self) -> S {
+        self.spi
+    }
+
+    pub fn status(&mut self) -> Result<Status, S::Error> {
+        let mut buf = [0u8];
+        self.spi.transaction(&mut [
+            Operation::Write(&[READ_STATUS]),
+            Operation::Read(&mut buf),
+        ])?;
+
+        Ok(Status::from_bits_retain(buf[0]))
+    }
+
+    fn write_enable(&mut self) -> Result<(), S::Error> {
+        self.spi.write(&[WRITE_ENABLE])
+    }
+
+    /// Check that `len` bytes starting at `address` are within the device
+    fn check_range(&self, address: u32, len: usize) -> Result<(), SpiFlashError<S::Error>> {
+        let end = u32::try_from(len).ok().and_then(|len| address.checked_add(len));
+        match end {
+            Some(end) if end <= self.capacity => Ok(()),
+            _ => Err(SpiFlashError::OutOfRange),
+        }
+    }
+
+    /// Send a command with an address, after enabling writes
+    fn command(&mut self, command: u8, address: u32, data: &[u8]) -> Result<(), SpiFlashError<S::Error>> {
+        self.check_range(address, data.len())?;
+
+        self.write_enable()?;
+        self.spi.transaction(&mut [
+            Operation::Write(&header(command, address)),
+            Operation::Write(data),
+        ])?;
+
+        Ok(())
+    }
+}
+
+fn read_id<S: SpiDevice>(spi: &mut S) -> Result<JedecId, S::Error> {
+    let mut buf = [0u8 ; 3];
+    spi.transaction(&mut [
+        Operation::Write(&[READ_JEDEC_ID]),
+        Operation::Read(&mut buf),
+    ])?;
+
+    Ok(JedecId {
+        manufacturer: buf[0],
+        memory_type: buf[1],
+        capacity: buf[2],
+    })
+}
+
+/// Build a command followed by a 3-byte big endian address
+fn header(command: u8, address: u32) -> [u8 ; 4] {
+    let [_, a2, a1, a0] = address.to_be_bytes();
+    [command, a2, a1, a0]
+}
+
+impl<S: SpiDevice> NorFlash for SpiFlash<S> {
+    type Error = SpiFlashError<S::Error>;
+
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    fn erase_size(&self) -> u32 {
+        self.erase_size
+    }
+
+    fn page_size(&self) -> u32 {
+        PAGE_SIZE
+    }
+
+    fn is_busy(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.status()?.contains(Status::BUSY))
+    }
+
+    fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_range(address, buf.len())?;
+
+        self.spi.transaction(&mut [
+            Operation::Write(&header(READ_DATA, address)),
+            Operation::Read(buf),
+        ])?;
+
+        Ok(())
+    }
+
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error> {
+        if (address % PAGE_SIZE) as usize + data.len() > PAGE_SIZE as usize {
+            return Err(SpiFlashError::OutOfRange)
+        }
+
+        self.command(PAGE_PROGRAM, address, data)
+    }
+
+    fn erase(&mut self, address: u32) -> Result<(), Self::Error> {
+        let sector = address - address % self.erase_size;
+        self.check_range(sector, self.erase_size as usize)?;
+        self.command(self.erase_command, sector, &[])
+    }
+
+    fn erase_all(&mut self) -> Result<(), Self::Error> {
+        self.write_enable()?;
+        self.spi.write(&[CHIP_ERASE])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+
+    use super::*;
+
+    #[test]
+    fn test_identify_and_program() {
+        let expectations = [
+            Transaction::transaction_start(),
+            Transaction::write(READ_JEDEC_ID),
+            Transaction::read_vec(vec![0xEF, 0x40, 0x18]),
+            Transaction::transaction_end(),
+            Transaction::transaction_start(),
+            Transaction::write(WRITE_ENABLE),
+            Transaction::transaction_end(),
+            Transaction::transaction_start(),
+            Transaction::write_vec(vec![PAGE_PROGRAM, 0x01, 0x23, 0x00]),
+            Transaction::write_vec(vec![0xAA, 0x55]),
+            Transaction::transaction_end(),
+            Transaction::transaction_start(),
+            Transaction::write(READ_STATUS),
+            Transaction::read(0x03),
+            Transaction::transaction_end(),
+        ];
+        let mut flash = SpiFlash::new(Mock::new(&expectations)).unwrap();
+        assert_eq!(flash.capacity(), 16 * 1024 * 1024);
+        assert_eq!(flash.erase_size(), 4096);
+
+        flash.program(0x12300, &[0xAA, 0x55]).unwrap();
+        assert_eq!(flash.is_busy(), Ok(true));
+        assert_eq!(flash.program(0x123FF, &[0, 0]), Err(SpiFlashError::OutOfRange));
+
+        // Ranges that wrap past the end of the address space are rejected without any transfer
+        assert_eq!(flash.program(u32::MAX, &[0]), Err(SpiFlashError::OutOfRange));
+        assert_eq!(flash.read(u32::MAX - 1, &mut [0 ; 4]), Err(SpiFlashError::OutOfRange));
+        assert_eq!(flash.erase(flash.capacity()), Err(SpiFlashError::OutOfRange));
+        flash.free().done();
+    }
+
+    #[test]
+    fn test_unknown_device() {
+        let expectations = [
+            Transaction::transaction_start(),
+            Transaction::write(READ_JEDEC_ID),
+            Transaction::read_vec(vec![0xFF, 0xFF, 0xFF]),
+            Transaction::transaction_end(),
+        ];
+        let mut spi = Mock::new(&expectations);
+        assert!(matches!(
+            SpiFlash::new(spi.clone()),
+            Err(SpiFlashError::UnknownDevice(JedecId { manufacturer: 0xFF, .. }))
+        ));
+        spi.done();
+    }
+}
@@ -0,0 +1,625 @@
+//! CRSF, the serial protocol spoken by ExpressLRS and Crossfire receivers.
+//!
+//! The receiver sends frames on a UART at 420 000 or 921 600 baud, each addressed to the
+//! flight controller:
+//!
+//! ```text
+//! 0xC8 | length | type | payload... | CRC-8
+//! ```
+//!
+//! The length counts the type, payload and CRC bytes, and the CRC is CRC-8 DVB-S2 over the type
+//! and payload. Extended frame types, used to configure devices, begin their payload with the
+//! destination and origin addresses. Multi-byte values are big-endian
+
+use embedded_io::{Read, ReadReady, Write};
+
+use crate::{math::crc::crc8_dvb_s2_slice, Micros};
+
+use super::{packed_to_micros, unpack_channels, LinkQuality, RcChannels, RcReceiver, PACKED_CHANNELS};
+
+pub mod telemetry;
+
+pub use telemetry::{Sensors, Telemetry, TelemetryScheduler};
+
+/// Byte starting each frame, the address of the flight controller
+pub const SYNC: u8 = address::FLIGHT_CONTROLLER;
+
+/// Longest frame including the sync, length and CRC bytes
+pub const MAX_FRAME_LEN: usize = 64;
+
+/// Baud rate receivers use by default
+pub const BAUD_RATE: u32 = 420_000;
+
+/// Baud rate used by ExpressLRS receivers at packet rates above 500 Hz
+pub const BAUD_RATE_FAST: u32 = 921_600;
+
+/// Number of RC channels in a channels frame
+pub const CHANNELS: usize = PACKED_CHANNELS;
+
+/// Time without an RC channels frame after which the link is considered lost
+pub const FRAME_TIMEOUT: Micros = 100_000;
+
+/// Channel value at the center of the stick range
+pub const CHANNEL_MID: u16 = 992;
+
+/// Device addresses, used as the sync byte and in extended frames
+pub mod address {
+    pub const BROADCAST: u8 = 0x00;
+    pub const FLIGHT_CONTROLLER: u8 = 0xC8;
+    pub const RADIO_TRANSMITTER: u8 = 0xEA;
+    pub const RECEIVER: u8 = 0xEC;
+    pub const TRANSMITTER: u8 = 0xEE;
+}
+
+/// Frame type bytes
+pub mod frame_type {
+    pub const LINK_STATISTICS: u8 = 0x14;
+    pub const RC_CHANNELS_PACKED: u8 = 0x16;
+    pub const DEVICE_PING: u8 = 0x28;
+    pub const DEVICE_INFO: u8 = 0x29;
+    pub const PARAMETER_SETTINGS_ENTRY: u8 = 0x2B;
+    pub const PARAMETER_READ: u8 = 0x2C;
+    pub const PARAMETER_WRITE: u8 = 0x2D;
+
+    /// Lowest type of the extended frames, which begin with destination and origin addresses
+    pub const EXTENDED: u8 = 0x28;
+}
+
+/// Error parsing the payload of a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrsfError {
+    /// The payload ended in the middle of a field
+    Truncated,
+    /// The payload is longer than its type allows
+    Length,
+    /// A device name was not valid UTF-8
+    InvalidUtf8,
+}
+
+/// Quality of the radio link in each direction, sent by the receiver several times a second
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkStatistics {
+    /// Signal strength at each of the receiver's antennas in dBm
+    pub uplink_rssi: [i16 ; 2],
+    /// Percentage of packets received from the transmitter
+    pub uplink_quality: u8,
+    /// Signal to noise ratio of packets from the transmitter in dB
+    pub uplink_snr: i8,
+    /// Index of the receiver antenna in use
+    pub active_antenna: u8,
+    /// Packet rate, specific to the RF hardware
+    pub rf_mode: u8,
+    /// Index of the transmitter's output power, see [tx_power_mw](Self::tx_power_mw)
+    pub tx_power: u8,
+    /// Signal strength at the transmitter in dBm
+    pub downlink_rssi: i16,
+    /// Percentage of telemetry packets received by the transmitter
+    pub downlink_quality: u8,
+    pub downlink_snr: i8,
+}
+
+impl LinkStatistics {
+    const LEN: usize = 10;
+
+    fn parse(payload: &[u8]) -> Result<Self, CrsfError> {
+        let p: &[u8 ; Self::LEN] = fixed(payload)?;
+        Ok(Self {
+            uplink_rssi: [-(p[0] as i16), -(p[1] as i16)],
+            uplink_quality: p[2],
+            uplink_snr: p[3] as i8,
+            active_antenna: p[4],
+            rf_mode: p[5],
+            tx_power: p[6],
+            downlink_rssi: -(p[7] as i16),
+            downlink_quality: p[8],
+            downlink_snr: p[9] as i8,
+        })
+    }
+
+    /// Get the transmitter's output power in milliwatts, or `None` if its index is unknown
+    pub const fn tx_power_mw(&self) -> Option<u16> {
+        const POWER: [u16 ; 9] = [0, 10, 25, 100, 500, 1000, 2000, 250, 50];
+        match self.tx_power as usize {
+            i if i < POWER.len() => Some(POWER[i]),
+            _ => None,
+        }
+    }
+}
+
+/// Description of a device, sent in reply to a ping
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceInfo<'a> {
+    pub name: &'a str,
+    pub serial: u32,
+    pub hardware_version: u32,
+    pub software_version: u32,
+    /// Number of configurable parameters the device has
+    pub parameters: u8,
+    pub parameter_version: u8,
+}
+
+impl<'a> DeviceInfo<'a> {
+    fn parse(payload: &'a [u8]) -> Result<Self, CrsfError> {
+        let end = payload.iter().position(|b| *b == 0).ok_or(CrsfError::Truncated)?;
+        let name = core::str::from_utf8(&payload[..end]).map_err(|_| CrsfError::InvalidUtf8)?;
+        let p: &[u8 ; 14] = fixed(&payload[end + 1..])?;
+        let word = |i: usize| u32::from_be_bytes([p[i], p[i + 1], p[i + 2], p[i + 3]]);
+        Ok(Self {
+            name,
+            serial: word(0),
+            hardware_version: word(4),
+            software_version: word(8),
+            parameters: p[12],
+            parameter_version: p[13],
+        })
+    }
+}
+
+/// Frame decoded from the receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frame<'a> {
+    /// Raw 11-bit channel values, see [packed_to_micros]
+    RcChannels([u16 ; CHANNELS]),
+    LinkStatistics(LinkStatistics),
+    /// Request for devices to reply with their [DeviceInfo]
+    DevicePing { destination: u8, origin: u8 },
+    DeviceInfo { destination: u8, origin: u8, info: DeviceInfo<'a> },
+    /// Chunk of a parameter's description, with the number of chunks that follow it
+    ParameterEntry { destination: u8, origin: u8, index: u8, chunks_remaining: u8, data: &'a [u8] },
+    /// Request for a chunk of a parameter's description
+    ParameterRead { destination: u8, origin: u8, index: u8, chunk: u8 },
+    ParameterWrite { destination: u8, origin: u8, index: u8, value: &'a [u8] },
+    /// Frame of a type not decoded here
+    Other { frame_type: u8, payload: &'a [u8] },
+}
+
+impl<'a> Frame<'a> {
+    /// Parse the payload of a frame, not including the sync, length, type and CRC bytes
+    pub fn parse(frame_type: u8, payload: &'a [u8]) -> Result<Self, CrsfError> {
+        if frame_type < frame_type::EXTENDED {
+            return Ok(match frame_type {
+                frame_type::RC_CHANNELS_PACKED => Self::RcChannels(unpack_channels(fixed(payload)?)),
+                frame_type::LINK_STATISTICS => Self::LinkStatistics(LinkStatistics::parse(payload)?),
+                _ => Self::Other { frame_type, payload },
+            })
+        }
+
+        let [destination, origin, rest @ ..] = payload else { return Err(CrsfError::Truncated) };
+        let (destination, origin) = (*destination, *origin);
+        Ok(match (frame_type, rest) {
+            (frame_type::DEVICE_PING, []) => Self::DevicePing { destination, origin },
+            (frame_type::DEVICE_PING, _) => return Err(CrsfError::Length),
+            (frame_type::DEVICE_INFO, info) => Self::DeviceInfo { destination, origin, info: DeviceInfo::parse(info)? },
+            (frame_type::PARAMETER_SETTINGS_ENTRY, [index, chunks_remaining, data @ ..]) => {
+                Self::ParameterEntry { destination, origin, index: *index, chunks_remaining: *chunks_remaining, data }
+            },
+            (frame_type::PARAMETER_READ, [index, chunk]) => Self::ParameterRead { destination, origin, index: *index, chunk: *chunk },
+            (frame_type::PARAMETER_READ, [_, _, ..]) => return Err(CrsfError::Length),
+            (frame_type::PARAMETER_WRITE, [index, value @ ..]) => Self::ParameterWrite { destination, origin, index: *index, value },
+            (frame_type::PARAMETER_SETTINGS_ENTRY | frame_type::PARAMETER_READ | frame_type::PARAMETER_WRITE, _) => {
+                return Err(CrsfError::Truncated)
+            },
+            _ => Self::Other { frame_type, payload },
+        })
+    }
+}
+
+/// Take exactly `N` bytes from a payload
+fn fixed<const N: usize>(payload: &[u8]) -> Result<&[u8 ; N], CrsfError> {
+    payload.try_into().map_err(|_| match payload.len() < N {
+        true => CrsfError::Truncated,
+        false => CrsfError::Length,
+    })
+}
+
+/// Splits a byte stream into frames, skipping bytes until a valid frame is found
+pub struct Decoder {
+    buf: [u8 ; MAX_FRAME_LEN],
+    len: usize,
+    /// Length of the frame completed by the last call to `push`, removed on the next call
+    returned: usize,
+    errors: u32,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8 ; MAX_FRAME_LEN],
+            len: 0,
+            returned: 0,
+            errors: 0,
+        }
+    }
+
+    /// Get the number of frames discarded because their length or checksum was invalid
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Add a byte from the stream, returning the type and payload of a frame if this byte
+    /// completes one
+    pub fn push(&mut self, byte: u8) -> Option<(u8, &[u8])> {
+        self.discard(self.returned);
+        self.returned = 0;
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        loop {
+            match self.buf[..self.len].iter().position(|b| *b == SYNC) {
+                Some(start) => self.discard(start),
+                None => {
+                    self.len = 0;
+                    return None
+                },
+            }
+
+            if self.len < 2 {
+                return None
+            }
+
+            // The length must cover at least the type and CRC, and fit in the buffer
+            let frame_len = self.buf[1] as usize + 2;
+            if (4..=MAX_FRAME_LEN).contains(&frame_len) {
+                if self.len < frame_len {
+                    return None
+                }
+                if crc8_dvb_s2_slice(&self.buf[2..frame_len - 1]) == self.buf[frame_len - 1] {
+                    self.returned = frame_len;
+                    return self.frame()
+                }
+            }
+
+            // Skip the bad sync byte and look for another frame in the bytes after it
+            self.errors = self.errors.wrapping_add(1);
+            self.discard(1);
+        }
+    }
+
+    /// Get the type and payload of the frame completed by the last byte pushed, if it completed one
+    pub fn frame(&self) -> Option<(u8, &[u8])> {
+        match self.returned {
+            0 => None,
+            len => Some((self.buf[2], &self.buf[3..len - 1])),
+        }
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.buf.copy_within(count..self.len, 0);
+        self.len -= count;
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CRSF receiver on a UART, tracking the latest channels and link statistics and sending
+/// telemetry back to it
+pub struct Crsf<U> {
+    uart: U,
+    decoder: Decoder,
+    channels: [u16 ; CHANNELS],
+    link: Option<LinkStatistics>,
+    last_channels: Option<Micros>,
+    /// Frames with a valid checksum whose payload couldn't be parsed
+    invalid: u32,
+    /// Whether an RC frame has arrived since the last telemetry slot was used
+    slot: bool,
+    pub telemetry: TelemetryScheduler,
+    /// Time without an RC channels frame after which the link is considered lost
+    pub timeout: Micros,
+}
+
+impl<U> Crsf<U>
+where U: Read + ReadReady
+{
+    pub fn new(uart: U) -> Self {
+        Self {
+            uart,
+            decoder: Decoder::new(),
+            channels: [CHANNEL_MID ; CHANNELS],
+            link: None,
+            last_channels: None,
+            invalid: 0,
+            slot: false,
+            telemetry: TelemetryScheduler::default(),
+            timeout: FRAME_TIMEOUT,
+        }
+    }
+
+    /// Read received bytes until they complete a frame, returning the result of parsing it, or
+    /// `None` once no more bytes are waiting. Call repeatedly to handle every frame received since
+    /// the last call
+    pub fn read(&mut self, now: Micros) -> Result<Option<Result<Frame<'_>, CrsfError>>, U::Error> {
+        loop {
+            let mut byte = [0u8];
+            if !self.uart.read_ready()? || self.uart.read(&mut byte)? == 0 {
+                return Ok(None)
+            }
+            if self.decoder.push(byte[0]).is_some() {
+                break
+            }
+        }
+
+        let frame = self.decoder.frame().map_or(Err(CrsfError::Truncated), |(frame_type, payload)| Frame::parse(frame_type, payload));
+        match &frame {
+            Ok(Frame::RcChannels(channels)) => {
+                self.channels = *channels;
+                self.last_channels = Some(now);
+                self.slot = true;
+            },
+            Ok(Frame::LinkStatistics(link)) => self.link = Some(*link),
+            Ok(_) => {},
+            Err(_) => self.invalid = self.invalid.wrapping_add(1),
+        }
+
+        Ok(Some(frame))
+    }
+
+    /// Handle every frame received since the last call, discarding all but channels and link
+    /// statistics
+    pub fn poll(&mut self, now: Micros) -> Result<(), U::Error> {
+        while self.read(now)?.is_some() {}
+        Ok(())
+    }
+
+    /// Whether no RC channels frame has been received within [timeout](Self::timeout) of `now`
+    pub fn is_failsafe(&self, now: Micros) -> bool {
+        match self.last_channels {
+            Some(last) => now.wrapping_sub(last) > self.timeout,
+            None => true,
+        }
+    }
+
+    /// Get the latest raw channel values, or `None` if the link is in failsafe
+    pub fn channels(&self, now: Micros) -> Option<&[u16 ; CHANNELS]> {
+        (!self.is_failsafe(now)).then_some(&self.channels)
+    }
+
+    /// Get the latest channel values in µs, or `None` if the link is in failsafe
+    pub fn rc_channels(&self, now: Micros) -> Option<RcChannels> {
+        self.channels(now).map(|channels| RcChannels::new(&channels.map(packed_to_micros)))
+    }
+
+    /// Get the latest link statistics, or `None` if none have been received
+    pub const fn link_statistics(&self) -> Option<LinkStatistics> {
+        self.link
+    }
+
+    /// Get the number of frames discarded because they were corrupt or malformed
+    pub const fn errors(&self) -> u32 {
+        self.decoder.errors().wrapping_add(self.invalid)
+    }
+
+    /// Release the UART
+    pub fn free(self) -> U {
+        self.uart
+    }
+}
+
+impl<U> RcReceiver for Crsf<U>
+where U: Read + ReadReady
+{
+    type Error = U::Error;
+
+    fn poll(&mut self, now: Micros) -> Result<(), Self::Error> {
+        self.poll(now)
+    }
+
+    fn rc_channels(&self, now: Micros) -> Option<RcChannels> {
+        self.rc_channels(now)
+    }
+
+    fn last_frame(&self) -> Option<Micros> {
+        self.last_channels
+    }
+
+    /// Uplink quality and the signal strength at the antenna in use
+    fn link_quality(&self) -> LinkQuality {
+        match self.link {
+            Some(link) => LinkQuality {
+                quality: Some(link.uplink_quality),
+                rssi: Some(link.uplink_rssi[link.active_antenna.min(1) as usize]),
+            },
+            None => LinkQuality::default(),
+        }
+    }
+}
+
+impl<U> Crsf<U>
+where U: Read + ReadReady + Write
+{
+    /// Send a telemetry frame in the gap after the last RC frame, if one has arrived since the
+    /// last call and the scheduler has a sensor due. `values` is asked for the values of the
+    /// chosen sensor, and nothing is sent if it has none. Returns whether a frame was sent
+    pub fn send_telemetry<'a>(&mut self, values: impl FnOnce(Sensors) -> Option<Telemetry<'a>>) -> Result<bool, U::Error> {
+        if !core::mem::take(&mut self.slot) {
+            return Ok(false)
+        }
+        let Some(telemetry) = self.telemetry.next_sensor().and_then(values) else {
+            return Ok(false)
+        };
+
+        let mut buf = [0u8 ; MAX_FRAME_LEN];
+        self.uart.write_all(telemetry.encode(&mut buf))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interface::mock::MockUart, rc::tests::pack_channels};
+
+    fn frame(frame_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![SYNC, payload.len() as u8 + 2, frame_type];
+        frame.extend_from_slice(payload);
+        frame.push(crc8_dvb_s2_slice(&frame[2..]));
+        frame
+    }
+
+    /// Deterministic xorshift generator standing in for a fuzzer's input
+    fn noise(seed: &mut u32) -> u8 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 17;
+        *seed ^= *seed << 5;
+        *seed as u8
+    }
+
+    #[test]
+    fn test_channels() {
+        let channels: [u16 ; CHANNELS] = core::array::from_fn(|i| 172 + i as u16 * 109);
+        let payload = pack_channels(&channels);
+        assert_eq!(payload.len(), 22);
+        assert_eq!(Frame::parse(frame_type::RC_CHANNELS_PACKED, &payload), Ok(Frame::RcChannels(channels)));
+        assert_eq!(Frame::parse(frame_type::RC_CHANNELS_PACKED, &payload[1..]), Err(CrsfError::Truncated));
+    }
+
+    #[test]
+    fn test_link_statistics() {
+        let payload = [60, 70, 100, 8, 1, 7, 3, 50, 98, (-5i8) as u8];
+        let Ok(Frame::LinkStatistics(link)) = Frame::parse(frame_type::LINK_STATISTICS, &payload) else {
+            panic!("link statistics not parsed");
+        };
+        assert_eq!(link.uplink_rssi, [-60, -70]);
+        assert_eq!((link.uplink_quality, link.uplink_snr, link.downlink_snr), (100, 8, -5));
+        assert_eq!(link.tx_power_mw(), Some(100));
+        assert_eq!(LinkStatistics { tx_power: 9, ..link }.tx_power_mw(), None);
+    }
+
+    #[test]
+    fn test_device_frames() {
+        let ping = [address::BROADCAST, address::RADIO_TRANSMITTER];
+        assert_eq!(
+            Frame::parse(frame_type::DEVICE_PING, &ping),
+            Ok(Frame::DevicePing { destination: address::BROADCAST, origin: address::RADIO_TRANSMITTER }),
+        );
+
+        let mut info = vec![address::RADIO_TRANSMITTER, address::RECEIVER];
+        info.extend_from_slice(b"ELRS RX\0");
+        info.extend_from_slice(&[0x45, 0x4C, 0x52, 0x53, 0, 0, 0, 1, 0, 3, 5, 0, 12, 0]);
+        let Ok(Frame::DeviceInfo { info, .. }) = Frame::parse(frame_type::DEVICE_INFO, &info) else {
+            panic!("device info not parsed");
+        };
+        assert_eq!(info.name, "ELRS RX");
+        assert_eq!((info.serial, info.hardware_version, info.software_version), (0x454C_5253, 1, 0x0003_0500));
+        assert_eq!((info.parameters, info.parameter_version), (12, 0));
+
+        let read = [address::RECEIVER, address::RADIO_TRANSMITTER, 4, 0];
+        assert!(matches!(Frame::parse(frame_type::PARAMETER_READ, &read), Ok(Frame::ParameterRead { index: 4, chunk: 0, .. })));
+        let write = [address::RECEIVER, address::RADIO_TRANSMITTER, 4, 2];
+        assert!(matches!(Frame::parse(frame_type::PARAMETER_WRITE, &write), Ok(Frame::ParameterWrite { index: 4, value: [2], .. })));
+        let entry = [address::RADIO_TRANSMITTER, address::RECEIVER, 4, 1, 0xAA, 0xBB];
+        assert!(matches!(
+            Frame::parse(frame_type::PARAMETER_SETTINGS_ENTRY, &entry),
+            Ok(Frame::ParameterEntry { index: 4, chunks_remaining: 1, data: [0xAA, 0xBB], .. }),
+        ));
+
+        assert_eq!(Frame::parse(frame_type::DEVICE_PING, &ping[..1]), Err(CrsfError::Truncated));
+        assert_eq!(Frame::parse(frame_type::PARAMETER_READ, &read[..3]), Err(CrsfError::Truncated));
+        assert_eq!(Frame::parse(frame_type::DEVICE_INFO, &[0, 0, 0xFF, 0]), Err(CrsfError::InvalidUtf8));
+    }
+
+    #[test]
+    fn test_decoder_resync() {
+        let link = frame(frame_type::LINK_STATISTICS, &[60, 70, 100, 8, 1, 7, 3, 50, 98, 0]);
+
+        // A sync byte with an invalid length, a corrupted frame, then a good frame
+        let mut bytes = vec![SYNC, 1, SYNC, 0xFF];
+        let mut corrupt = link.clone();
+        corrupt[5] ^= 1;
+        bytes.extend(&corrupt);
+        bytes.extend(&link);
+
+        let mut decoder = Decoder::new();
+        let frames: Vec<_> = bytes.iter()
+            .filter_map(|b| decoder.push(*b).map(|(frame_type, payload)| (frame_type, payload.to_vec())))
+            .collect();
+        assert_eq!(frames, [(frame_type::LINK_STATISTICS, link[3..link.len() - 1].to_vec())]);
+        assert!(decoder.errors() >= 3);
+    }
+
+    #[test]
+    fn test_receiver_failsafe() {
+        let channels = [1811 ; CHANNELS];
+        let mut crsf = Crsf::new(MockUart::<256>::new());
+        assert!(crsf.is_failsafe(0));
+        assert_eq!(crsf.channels(0), None);
+
+        crsf.uart.receive(&frame(frame_type::RC_CHANNELS_PACKED, &pack_channels(&channels)));
+        crsf.uart.receive(&frame(frame_type::DEVICE_PING, &[address::BROADCAST, address::RADIO_TRANSMITTER]));
+        assert!(matches!(crsf.read(1000), Ok(Some(Ok(Frame::RcChannels(_))))));
+        assert!(matches!(crsf.read(1000), Ok(Some(Ok(Frame::DevicePing { .. })))));
+        assert_eq!(crsf.read(1000), Ok(None));
+
+        assert_eq!(crsf.channels(1000 + FRAME_TIMEOUT), Some(&channels));
+        assert_eq!(crsf.rc_channels(1000).map(|rc| rc.as_slice().to_vec()), Some(vec![2012 ; CHANNELS]));
+        assert!(crsf.is_failsafe(1001 + FRAME_TIMEOUT));
+
+        // A frame with a valid checksum but a truncated payload is counted and skipped
+        crsf.uart.receive(&frame(frame_type::RC_CHANNELS_PACKED, &[0 ; 4]));
+        crsf.uart.receive(&frame(frame_type::LINK_STATISTICS, &[60, 70, 100, 8, 1, 7, 3, 50, 98, 0]));
+        crsf.poll(200_000).unwrap();
+        assert_eq!(crsf.errors(), 1);
+        assert_eq!(crsf.link_statistics().map(|link| link.uplink_quality), Some(100));
+        assert_eq!(crsf.link_quality(), LinkQuality { quality: Some(100), rssi: Some(-70) });
+        assert!(crsf.is_failsafe(200_000));
+    }
+
+    #[test]
+    fn test_telemetry_slots() {
+        let mut crsf = Crsf::new(MockUart::<256>::new());
+        crsf.telemetry = TelemetryScheduler::new(Sensors::BATTERY | Sensors::FLIGHT_MODE);
+        let values = |sensor| match sensor {
+            Sensors::FLIGHT_MODE => Some(Telemetry::FlightMode("ACRO")),
+            _ => None,
+        };
+
+        // Nothing is sent until an RC frame opens a slot, and only once per slot
+        assert_eq!(crsf.send_telemetry(values), Ok(false));
+        let channels = frame(frame_type::RC_CHANNELS_PACKED, &pack_channels(&[CHANNEL_MID ; CHANNELS]));
+        for _ in 0..2 {
+            crsf.uart.receive(&channels);
+            crsf.poll(0).unwrap();
+            crsf.send_telemetry(values).unwrap();
+            assert_eq!(crsf.send_telemetry(values), Ok(false));
+        }
+
+        // The battery slot had no values, so only the flight mode was sent
+        let mut expected = [0u8 ; MAX_FRAME_LEN];
+        assert_eq!(&crsf.uart.tx[..], Telemetry::FlightMode("ACRO").encode(&mut expected));
+    }
+
+    #[test]
+    fn test_fuzz_noise() {
+        // Random bytes never panic, and frames embedded between bursts of them are still found
+        let link = frame(frame_type::LINK_STATISTICS, &[60, 70, 100, 8, 1, 7, 3, 50, 98, 0]);
+        let mut seed = 0x1234_5678;
+        let mut decoder = Decoder::new();
+        let mut found = 0;
+        for _ in 0..200 {
+            for _ in 0..(noise(&mut seed) % 80) {
+                if let Some((frame_type, payload)) = decoder.push(noise(&mut seed)) {
+                    let _ = Frame::parse(frame_type, payload);
+                }
+            }
+            // A trailing partial frame from the noise may swallow the start of the frame
+            for &b in link.iter().chain(&link) {
+                if let Some((frame_type, payload)) = decoder.push(b) {
+                    found += (Frame::parse(frame_type, payload).is_ok() && frame_type == frame_type::LINK_STATISTICS) as u32;
+                }
+            }
+        }
+        assert!(found >= 200, "{found}");
+
+        // Every frame type parses or fails cleanly on payloads of every length
+        let payload: Vec<u8> = (0..MAX_FRAME_LEN).map(|_| noise(&mut seed)).collect();
+        for frame_type in 0..=u8::MAX {
+            for len in 0..payload.len() {
+                let _ = Frame::parse(frame_type, &payload[..len]);
+            }
+        }
+    }
+}
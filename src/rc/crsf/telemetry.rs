@@ -0,0 +1,295 @@
+//! CRSF telemetry frames sent from the flight controller to the receiver, which relays them to
+//! the transmitter in the downlink slots of the radio link.
+//!
+//! The receiver only listens between the RC frames it sends, so one telemetry frame is sent
+//! after an RC frame arrives, with a [TelemetryScheduler] choosing which sensor it describes.
+
+use bitflags::bitflags;
+use nalgebra::UnitQuaternion;
+
+use crate::{interface::gnss::GnssPosition, math::crc::crc8_dvb_s2_slice};
+
+use super::{MAX_FRAME_LEN, SYNC};
+
+/// Telemetry frame type bytes
+pub mod frame_type {
+    pub const GPS: u8 = 0x02;
+    pub const VARIO: u8 = 0x07;
+    pub const BATTERY_SENSOR: u8 = 0x08;
+    pub const BARO_ALTITUDE: u8 = 0x09;
+    pub const ATTITUDE: u8 = 0x1E;
+    pub const FLIGHT_MODE: u8 = 0x21;
+}
+
+/// Longest payload of a frame, leaving room for the sync, length, type and CRC bytes
+const MAX_PAYLOAD: usize = MAX_FRAME_LEN - 4;
+
+bitflags! {
+    /// Kinds of telemetry frame, used to choose which are sent
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Sensors: u8 {
+        const ATTITUDE = 1 << 0;
+        const BATTERY = 1 << 1;
+        const FLIGHT_MODE = 1 << 2;
+        const GPS = 1 << 3;
+        const VARIO = 1 << 4;
+        const BARO_ALTITUDE = 1 << 5;
+    }
+}
+
+/// Values to send in a telemetry frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Telemetry<'a> {
+    /// Euler angles in radians
+    Attitude { roll: f32, pitch: f32, yaw: f32 },
+    Battery {
+        /// Voltage in volts
+        voltage: f32,
+        /// Current in amps
+        current: f32,
+        /// Charge used in mAh
+        consumed: u32,
+        /// Percentage of charge remaining
+        remaining: u8,
+    },
+    /// Name of the flight mode, shown on the radio's telemetry screen
+    FlightMode(&'a str),
+    Gps {
+        position: GnssPosition,
+        /// Speed over the ground in m/s
+        ground_speed: f32,
+        /// Direction of travel in degrees clockwise from north
+        course: f32,
+        satellites: u8,
+    },
+    /// Vertical speed in m/s, positive upwards
+    Vario(f32),
+    /// Altitude in metres
+    BaroAltitude(f32),
+}
+
+impl Telemetry<'_> {
+    /// Build an attitude frame from an attitude estimate
+    pub fn attitude(attitude: &UnitQuaternion<f32>) -> Self {
+        let (roll, pitch, yaw) = attitude.euler_angles();
+        Self::Attitude { roll, pitch, yaw }
+    }
+
+    /// Get the kind of frame these values are sent in
+    pub const fn sensor(&self) -> Sensors {
+        match self {
+            Self::Attitude { .. } => Sensors::ATTITUDE,
+            Self::Battery { .. } => Sensors::BATTERY,
+            Self::FlightMode(_) => Sensors::FLIGHT_MODE,
+            Self::Gps { .. } => Sensors::GPS,
+            Self::Vario(_) => Sensors::VARIO,
+            Self::BaroAltitude(_) => Sensors::BARO_ALTITUDE,
+        }
+    }
+
+    /// Encode a complete frame, returning the bytes of `buf` it fills
+    pub fn encode<'b>(&self, buf: &'b mut [u8 ; MAX_FRAME_LEN]) -> &'b [u8] {
+        let mut payload = Payload { buf: [0u8 ; MAX_PAYLOAD], len: 0 };
+        let frame_type = match *self {
+            Self::Attitude { roll, pitch, yaw } => {
+                // In units of 100 µrad, with pitch first
+                for angle in [pitch, roll, yaw] {
+                    payload.put(&(nearest(angle * 10_000f32) as i16).to_be_bytes());
+                }
+                frame_type::ATTITUDE
+            },
+            Self::Battery { voltage, current, consumed, remaining } => {
+                payload.put(&(nearest(voltage * 10f32) as u16).to_be_bytes());
+                payload.put(&(nearest(current * 10f32) as u16).to_be_bytes());
+                payload.put(&consumed.min(0xFF_FFFF).to_be_bytes()[1..]);
+                payload.put(&[remaining.min(100)]);
+                frame_type::BATTERY_SENSOR
+            },
+            Self::FlightMode(name) => {
+                let mut end = name.len().min(MAX_PAYLOAD - 1);
+                while !name.is_char_boundary(end) {
+                    end -= 1;
+                }
+                payload.put(&name.as_bytes()[..end]);
+                payload.put(&[0]);
+                frame_type::FLIGHT_MODE
+            },
+            Self::Gps { position, ground_speed, course, satellites } => {
+                payload.put(&position.latitude.to_be_bytes());
+                payload.put(&position.longitude.to_be_bytes());
+                // Speed in 0.1 km/h, course in 0.01°, and altitude in metres offset by 1000 m
+                let course = match course % 360f32 {
+                    course if course < 0f32 => course + 360f32,
+                    course => course,
+                };
+                payload.put(&(nearest(ground_speed * 36f32) as u16).to_be_bytes());
+                payload.put(&(nearest(course * 100f32) as u16 % 36_000).to_be_bytes());
+                payload.put(&((position.altitude_msl / 1000 + 1000).clamp(0, u16::MAX as i32) as u16).to_be_bytes());
+                payload.put(&[satellites]);
+                frame_type::GPS
+            },
+            Self::Vario(speed) => {
+                payload.put(&(nearest(speed * 100f32) as i16).to_be_bytes());
+                frame_type::VARIO
+            },
+            Self::BaroAltitude(altitude) => {
+                // Decimetres offset by 1000 m, or whole metres with the top bit set when that
+                // doesn't fit in 15 bits
+                let decimetres = nearest(altitude * 10f32) as i32 + 10_000;
+                let packed = match decimetres {
+                    ..0 => 0,
+                    0..0x8000 => decimetres as u16,
+                    _ => 0x8000 | (nearest(altitude) as u16).min(0x7FFF),
+                };
+                payload.put(&packed.to_be_bytes());
+                frame_type::BARO_ALTITUDE
+            },
+        };
+
+        let len = payload.len;
+        buf[0] = SYNC;
+        buf[1] = len as u8 + 2;
+        buf[2] = frame_type;
+        buf[3..len + 3].copy_from_slice(&payload.buf[..len]);
+        buf[len + 3] = crc8_dvb_s2_slice(&buf[2..len + 3]);
+        &buf[..len + 4]
+    }
+}
+
+struct Payload {
+    buf: [u8 ; MAX_PAYLOAD],
+    len: usize,
+}
+
+impl Payload {
+    fn put(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+/// Move `x` half a unit away from zero, so that the saturating `as` cast to an integer, which
+/// truncates, rounds it to the nearest
+fn nearest(x: f32) -> f32 {
+    match x < 0f32 {
+        true => x - 0.5,
+        false => x + 0.5,
+    }
+}
+
+/// Chooses the sensor described by each telemetry frame, cycling through the enabled sensors
+#[derive(Clone, Copy, Debug)]
+pub struct TelemetryScheduler {
+    /// Sensors whose frames are sent
+    pub enabled: Sensors,
+    /// Number of RC frames between telemetry frames, to share the downlink with the receiver's
+    /// own telemetry
+    pub divider: u8,
+    next: u8,
+    skipped: u8,
+}
+
+impl TelemetryScheduler {
+    pub const fn new(enabled: Sensors) -> Self {
+        Self { enabled, divider: 1, next: 0, skipped: 0 }
+    }
+
+    /// Get the sensor to send a frame for in the slot after an RC frame, or `None` if none is
+    /// due in this slot
+    pub fn next_sensor(&mut self) -> Option<Sensors> {
+        if self.enabled.is_empty() {
+            return None
+        }
+        self.skipped += 1;
+        if self.skipped < self.divider {
+            return None
+        }
+        self.skipped = 0;
+
+        loop {
+            let sensor = Sensors::from_bits_truncate(1 << self.next);
+            self.next = (self.next + 1) % Sensors::all().bits().count_ones() as u8;
+            if self.enabled.contains(sensor) {
+                return Some(sensor)
+            }
+        }
+    }
+}
+
+impl Default for TelemetryScheduler {
+    fn default() -> Self {
+        Self::new(Sensors::all())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc::crsf::{Decoder, Frame};
+
+    /// Encode a frame and check that it decodes, returning its type and payload
+    fn encode(telemetry: Telemetry) -> (u8, Vec<u8>) {
+        let mut buf = [0u8 ; MAX_FRAME_LEN];
+        let bytes = telemetry.encode(&mut buf).to_vec();
+        let mut decoder = Decoder::new();
+        let frames: Vec<_> = bytes.iter()
+            .filter_map(|b| decoder.push(*b).map(|(frame_type, payload)| (frame_type, payload.to_vec())))
+            .collect();
+        assert_eq!(frames.len(), 1, "{bytes:x?}");
+        assert!(matches!(Frame::parse(frames[0].0, &frames[0].1), Ok(Frame::Other { .. })));
+        frames[0].clone()
+    }
+
+    #[test]
+    fn test_attitude_and_battery() {
+        let attitude = UnitQuaternion::from_euler_angles(0.1f32, -0.2, 3.0);
+        let (frame_type, payload) = encode(Telemetry::attitude(&attitude));
+        assert_eq!(frame_type, frame_type::ATTITUDE);
+        let angles: Vec<i16> = payload.chunks(2).map(|b| i16::from_be_bytes([b[0], b[1]])).collect();
+        assert_eq!(angles, [-2000, 1000, 30_000]);
+
+        let battery = Telemetry::Battery { voltage: 16.8, current: 23.45, consumed: 1300, remaining: 72 };
+        assert_eq!(encode(battery), (frame_type::BATTERY_SENSOR, vec![0, 168, 0, 235, 0x00, 0x05, 0x14, 72]));
+    }
+
+    #[test]
+    fn test_flight_mode() {
+        assert_eq!(encode(Telemetry::FlightMode("ANGL")), (frame_type::FLIGHT_MODE, b"ANGL\0".to_vec()));
+
+        let long = "é".repeat(40);
+        let (_, payload) = encode(Telemetry::FlightMode(&long));
+        assert_eq!(payload.len(), MAX_PAYLOAD - 1);
+        assert!(core::str::from_utf8(&payload[..payload.len() - 1]).is_ok());
+    }
+
+    #[test]
+    fn test_gps_and_altitude() {
+        let position = GnssPosition { latitude: 515_007_000, longitude: -1_246_000, altitude_msl: 35_400 };
+        let gps = Telemetry::Gps { position, ground_speed: 10.0, course: -90.0, satellites: 12 };
+        let (frame_type, payload) = encode(gps);
+        assert_eq!(frame_type, frame_type::GPS);
+        assert_eq!(i32::from_be_bytes(payload[0..4].try_into().unwrap()), 515_007_000);
+        assert_eq!(i32::from_be_bytes(payload[4..8].try_into().unwrap()), -1_246_000);
+        assert_eq!(payload[8..], [1, 104, 105, 120, 4, 11, 12]);
+
+        assert_eq!(encode(Telemetry::Vario(-1.5)).1, (-150i16).to_be_bytes());
+        assert_eq!(encode(Telemetry::Vario(400.0)).1, i16::MAX.to_be_bytes());
+        assert_eq!(encode(Telemetry::BaroAltitude(123.4)).1, 11_234u16.to_be_bytes());
+        assert_eq!(encode(Telemetry::BaroAltitude(3000.0)).1, (0x8000u16 | 3000).to_be_bytes());
+        assert_eq!(encode(Telemetry::BaroAltitude(-2000.0)).1, [0, 0]);
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut scheduler = TelemetryScheduler::new(Sensors::BATTERY | Sensors::FLIGHT_MODE | Sensors::BARO_ALTITUDE);
+        let sent: Vec<_> = (0..4).map(|_| scheduler.next_sensor()).collect();
+        assert_eq!(sent, [Some(Sensors::BATTERY), Some(Sensors::FLIGHT_MODE), Some(Sensors::BARO_ALTITUDE), Some(Sensors::BATTERY)]);
+
+        scheduler.divider = 3;
+        let sent: Vec<_> = (0..6).map(|_| scheduler.next_sensor()).collect();
+        assert_eq!(sent, [None, None, Some(Sensors::FLIGHT_MODE), None, None, Some(Sensors::BARO_ALTITUDE)]);
+
+        scheduler.enabled = Sensors::empty();
+        assert!((0..10).all(|_| scheduler.next_sensor().is_none()));
+    }
+}
@@ -0,0 +1,303 @@
+//! Spektrum DSM2 and DSMX serial receivers, also known as remote receivers or satellites.
+//!
+//! The receiver sends a 16-byte frame every 11 or 22 ms on a UART at 115 200 baud, 8N1:
+//!
+//! ```text
+//! fades | system | 7 channel words (u16, big-endian)
+//! ```
+//!
+//! Each word holds a channel number and value, with 10 bits of value at 1024 resolution or 11
+//! bits at 2048, and 0xFFFF marks an unused word. Receivers with more than 7 channels spread
+//! them across consecutive frames. There is no header or checksum, so frames are found by the
+//! gap between them.
+
+use embedded_io::{Read, ReadReady};
+
+use crate::Micros;
+
+use super::{FrameHistory, LinkQuality, RcChannels, RcReceiver};
+
+pub const BAUD_RATE: u32 = 115_200;
+
+/// Length of a frame
+pub const FRAME_LEN: usize = 16;
+
+/// Most channels a receiver sends
+pub const MAX_CHANNELS: usize = 12;
+
+/// Shortest gap between bytes that starts a new frame. Frames take 1.4 ms to send and are at
+/// least 11 ms apart, so bytes must be read more often than this
+pub const FRAME_GAP: Micros = 5000;
+
+/// Time without a frame after which the link is considered lost
+pub const FRAME_TIMEOUT: Micros = 100_000;
+
+/// Word marking that no channel is sent in its place
+const UNUSED: u16 = 0xFFFF;
+
+/// Range of channel values each word can hold
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+    /// 10-bit values, with channel numbers in bits 10 to 13
+    Bits10,
+    /// 11-bit values, with channel numbers in bits 11 to 14
+    #[default]
+    Bits11,
+}
+
+impl Resolution {
+    /// Get the resolution and frame interval in µs of a known system byte, sent by remote
+    /// receivers in place of the high byte of the fade count
+    pub const fn from_system(system: u8) -> Option<(Self, Micros)> {
+        match system {
+            // DSM2 at 22 ms and 11 ms
+            0x01 => Some((Self::Bits10, 22_000)),
+            0x12 => Some((Self::Bits11, 11_000)),
+            // DSMX at 22 ms and 11 ms
+            0xA2 => Some((Self::Bits11, 22_000)),
+            0xB2 => Some((Self::Bits11, 11_000)),
+            _ => None,
+        }
+    }
+
+    /// Split a channel word into its channel number and value in µs
+    pub const fn decode(&self, word: u16) -> (usize, u16) {
+        match self {
+            Self::Bits10 => ((word >> 10 & 0xF) as usize, 988 + (word & 0x3FF)),
+            Self::Bits11 => ((word >> 11 & 0xF) as usize, 988 + (word & 0x7FF) / 2),
+        }
+    }
+}
+
+/// Frame decoded from the receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DsmFrame {
+    /// Number of packets the receiver has missed, wrapping at 256
+    pub fades: u8,
+    /// Protocol and frame interval of remote receivers, see [Resolution::from_system]
+    pub system: u8,
+    pub words: [u16 ; 7],
+}
+
+impl DsmFrame {
+    pub fn parse(frame: &[u8 ; FRAME_LEN]) -> Self {
+        Self {
+            fades: frame[0],
+            system: frame[1],
+            words: core::array::from_fn(|i| u16::from_be_bytes([frame[2 + i * 2], frame[3 + i * 2]])),
+        }
+    }
+
+    /// Get the channel numbers and values in µs of each used word
+    pub fn channels(&self, resolution: Resolution) -> impl Iterator<Item = (usize, u16)> {
+        self.words.into_iter()
+            .filter(|word| *word != UNUSED)
+            .map(move |word| resolution.decode(word))
+    }
+}
+
+/// Splits a byte stream into frames at the gaps between them
+pub struct Decoder {
+    buf: [u8 ; FRAME_LEN],
+    len: usize,
+    last_byte: Option<Micros>,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self { buf: [0u8 ; FRAME_LEN], len: 0, last_byte: None }
+    }
+
+    /// Add a byte from the stream received at `now`, returning a frame if this byte completes one
+    pub fn push(&mut self, byte: u8, now: Micros) -> Option<DsmFrame> {
+        if self.last_byte.is_none_or(|last| now.wrapping_sub(last) >= FRAME_GAP) {
+            self.len = 0;
+        }
+        self.last_byte = Some(now);
+
+        // Bytes after a complete frame and before the next gap are ignored
+        if self.len >= FRAME_LEN {
+            return None
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        (self.len == FRAME_LEN).then(|| DsmFrame::parse(&self.buf))
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// DSM receiver on a UART, collecting the channels spread across its frames
+pub struct Dsm<U> {
+    uart: U,
+    decoder: Decoder,
+    channels: [u16 ; MAX_CHANNELS],
+    count: usize,
+    last_frame: Option<Micros>,
+    fades: Option<u8>,
+    history: FrameHistory,
+    /// Resolution used when the receiver doesn't send a known system byte
+    pub resolution: Resolution,
+    /// Time without a frame after which the link is considered lost
+    pub timeout: Micros,
+}
+
+impl<U> Dsm<U>
+where U: Read + ReadReady
+{
+    pub fn new(uart: U) -> Self {
+        Self {
+            uart,
+            decoder: Decoder::new(),
+            channels: [0u16 ; MAX_CHANNELS],
+            count: 0,
+            last_frame: None,
+            fades: None,
+            history: FrameHistory::default(),
+            resolution: Resolution::default(),
+            timeout: FRAME_TIMEOUT,
+        }
+    }
+
+    /// Read received bytes until they complete a frame, returning it, or `None` once no more
+    /// bytes are waiting
+    pub fn read(&mut self, now: Micros) -> Result<Option<DsmFrame>, U::Error> {
+        let mut byte = [0u8];
+        while self.uart.read_ready()? && self.uart.read(&mut byte)? > 0 {
+            if let Some(frame) = self.decoder.push(byte[0], now) {
+                self.handle(&frame, now);
+                return Ok(Some(frame))
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn handle(&mut self, frame: &DsmFrame, now: Micros) {
+        let resolution = Resolution::from_system(frame.system).map_or(self.resolution, |(resolution, _)| resolution);
+        for (channel, value) in frame.channels(resolution) {
+            if channel < MAX_CHANNELS {
+                self.channels[channel] = value;
+                self.count = self.count.max(channel + 1);
+            }
+        }
+
+        // Each fade is a packet missed since the last frame
+        let missed = self.fades.map_or(0, |fades| frame.fades.wrapping_sub(fades));
+        for _ in 0..missed.min(10) {
+            self.history.push(false);
+        }
+        self.history.push(true);
+        self.fades = Some(frame.fades);
+        self.last_frame = Some(now);
+    }
+
+    /// Whether no frame has been received within [timeout](Self::timeout) of `now`
+    pub fn is_failsafe(&self, now: Micros) -> bool {
+        match self.last_frame {
+            Some(last) => now.wrapping_sub(last) > self.timeout,
+            None => true,
+        }
+    }
+
+    /// Release the UART
+    pub fn free(self) -> U {
+        self.uart
+    }
+}
+
+impl<U> RcReceiver for Dsm<U>
+where U: Read + ReadReady
+{
+    type Error = U::Error;
+
+    fn poll(&mut self, now: Micros) -> Result<(), Self::Error> {
+        while self.read(now)?.is_some() {}
+        Ok(())
+    }
+
+    fn rc_channels(&self, now: Micros) -> Option<RcChannels> {
+        match self.is_failsafe(now) || self.count == 0 {
+            true => None,
+            false => Some(RcChannels::new(&self.channels[..self.count])),
+        }
+    }
+
+    fn last_frame(&self) -> Option<Micros> {
+        self.last_frame
+    }
+
+    fn link_quality(&self) -> LinkQuality {
+        LinkQuality { quality: self.history.quality(), rssi: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::MockUart;
+
+    fn frame(fades: u8, system: u8, words: [u16 ; 7]) -> Vec<u8> {
+        let mut frame = vec![fades, system];
+        frame.extend(words.iter().flat_map(|w| w.to_be_bytes()));
+        frame
+    }
+
+    fn word_11(channel: u16, value: u16) -> u16 {
+        channel << 11 | value
+    }
+
+    #[test]
+    fn test_resolution() {
+        assert_eq!(Resolution::Bits11.decode(word_11(3, 1024)), (3, 1500));
+        assert_eq!(Resolution::Bits11.decode(word_11(11, 2047)), (11, 2011));
+        assert_eq!(Resolution::Bits10.decode(2 << 10 | 512), (2, 1500));
+        assert_eq!(Resolution::from_system(0x01), Some((Resolution::Bits10, 22_000)));
+        assert_eq!(Resolution::from_system(0xB2), Some((Resolution::Bits11, 11_000)));
+        assert_eq!(Resolution::from_system(0x00), None);
+    }
+
+    #[test]
+    fn test_decoder_gaps() {
+        let bytes = frame(0, 0xB2, [word_11(0, 1024) ; 7]);
+        let mut decoder = Decoder::new();
+
+        // A partial frame, then a gap before the whole frame
+        assert!(bytes[..9].iter().all(|b| decoder.push(*b, 0).is_none()));
+        let frames: Vec<_> = bytes.iter().filter_map(|b| decoder.push(*b, FRAME_GAP)).collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].system, 0xB2);
+
+        // Extra bytes before the next gap are ignored
+        assert!(decoder.push(0, FRAME_GAP + 100).is_none());
+        let frames: Vec<_> = bytes.iter().filter_map(|b| decoder.push(*b, 3 * FRAME_GAP)).collect();
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn test_receiver() {
+        let mut dsm = Dsm::new(MockUart::<64>::new());
+        assert_eq!(dsm.rc_channels(0), None);
+
+        // Twelve channels across two frames of DSMX at 11 ms
+        let first = [0, 1, 2, 3, 4, 5, 6].map(|c| word_11(c, 1024));
+        let second = [7, 8, 9, 10, 11].map(|c| word_11(c, 2047));
+        let second = [second[0], second[1], second[2], second[3], second[4], UNUSED, UNUSED];
+        dsm.uart.receive(&frame(3, 0xB2, first));
+        dsm.poll(0).unwrap();
+        dsm.uart.receive(&frame(5, 0xB2, second));
+        dsm.poll(11_000).unwrap();
+
+        let channels = dsm.rc_channels(11_000).unwrap();
+        assert_eq!(channels.len(), MAX_CHANNELS);
+        assert_eq!((channels.get(0), channels.get(11)), (Some(1500), Some(2011)));
+
+        // Two fades between the frames, so two of four packets were received
+        assert_eq!(dsm.link_quality().quality, Some(50));
+        assert_eq!(dsm.rc_channels(11_001 + FRAME_TIMEOUT), None);
+    }
+}
@@ -0,0 +1,231 @@
+//! IBUS, the serial protocol of FlySky receivers.
+//!
+//! The receiver sends a 32-byte frame every 7 ms on a UART at 115 200 baud, 8N1:
+//!
+//! ```text
+//! 0x20 | 0x40 | 14 channels (u16) | checksum (u16)
+//! ```
+//!
+//! Values are little-endian pulse widths in µs, and the checksum is 0xFFFF minus the sum of
+//! every byte before it
+
+use embedded_io::{Read, ReadReady};
+
+use crate::Micros;
+
+use super::{RcChannels, RcReceiver};
+
+pub const BAUD_RATE: u32 = 115_200;
+
+/// Length of a frame including its header and checksum
+pub const FRAME_LEN: usize = 32;
+
+/// Header bytes starting each frame, the frame length and the channels command
+pub const HEADER: [u8 ; 2] = [FRAME_LEN as u8, 0x40];
+
+/// Number of channels in a frame
+pub const CHANNELS: usize = 14;
+
+/// Time without a frame after which the link is considered lost. Receivers either stop sending
+/// or send their failsafe values when they lose the link, so this only detects the former
+pub const FRAME_TIMEOUT: Micros = 100_000;
+
+/// Parse a frame into its channel values in µs, or return `None` if its header or checksum is
+/// wrong
+pub fn parse(frame: &[u8 ; FRAME_LEN]) -> Option<[u16 ; CHANNELS]> {
+    let word = |i: usize| u16::from_le_bytes([frame[i], frame[i + 1]]);
+    let sum = frame[..FRAME_LEN - 2].iter().fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+    if frame[..2] != HEADER || 0xFFFF - sum != word(FRAME_LEN - 2) {
+        return None
+    }
+
+    // The top nibble is used by some receivers to carry extra channels
+    Some(core::array::from_fn(|i| word(2 + i * 2) & 0x0FFF))
+}
+
+/// Splits a byte stream into frames, skipping bytes until a valid frame is found
+pub struct Decoder {
+    buf: [u8 ; FRAME_LEN],
+    len: usize,
+    errors: u32,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self { buf: [0u8 ; FRAME_LEN], len: 0, errors: 0 }
+    }
+
+    /// Get the number of frames discarded because their checksum did not match
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Add a byte from the stream, returning the channels of a frame if this byte completes one
+    pub fn push(&mut self, byte: u8) -> Option<[u16 ; CHANNELS]> {
+        if self.len < HEADER.len() && byte != HEADER[self.len] {
+            // The byte may still start a frame if the header was only half matched
+            self.len = 0;
+            if byte != HEADER[0] {
+                return None
+            }
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME_LEN {
+            return None
+        }
+
+        if let Some(channels) = parse(&self.buf) {
+            self.len = 0;
+            return Some(channels)
+        }
+
+        // Start again from the next header in the bytes received, which may be cut off at the end
+        self.errors = self.errors.wrapping_add(1);
+        let start = (1..FRAME_LEN)
+            .find(|&i| self.buf[i] == HEADER[0] && self.buf.get(i + 1).is_none_or(|b| *b == HEADER[1]))
+            .unwrap_or(FRAME_LEN);
+        self.buf.copy_within(start.., 0);
+        self.len = FRAME_LEN - start;
+        None
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// IBUS receiver on a UART
+pub struct Ibus<U> {
+    uart: U,
+    decoder: Decoder,
+    channels: Option<[u16 ; CHANNELS]>,
+    last_frame: Option<Micros>,
+    /// Time without a frame after which the link is considered lost
+    pub timeout: Micros,
+}
+
+impl<U> Ibus<U>
+where U: Read + ReadReady
+{
+    pub fn new(uart: U) -> Self {
+        Self {
+            uart,
+            decoder: Decoder::new(),
+            channels: None,
+            last_frame: None,
+            timeout: FRAME_TIMEOUT,
+        }
+    }
+
+    /// Read received bytes until they complete a frame, returning its channels, or `None` once
+    /// no more bytes are waiting
+    pub fn read(&mut self, now: Micros) -> Result<Option<[u16 ; CHANNELS]>, U::Error> {
+        let mut byte = [0u8];
+        while self.uart.read_ready()? && self.uart.read(&mut byte)? > 0 {
+            if let Some(channels) = self.decoder.push(byte[0]) {
+                self.channels = Some(channels);
+                self.last_frame = Some(now);
+                return Ok(Some(channels))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Whether no frame has been received within [timeout](Self::timeout) of `now`
+    pub fn is_failsafe(&self, now: Micros) -> bool {
+        match self.last_frame {
+            Some(last) => now.wrapping_sub(last) > self.timeout,
+            None => true,
+        }
+    }
+
+    /// Get the number of frames discarded because they were corrupt
+    pub const fn errors(&self) -> u32 {
+        self.decoder.errors()
+    }
+
+    /// Release the UART
+    pub fn free(self) -> U {
+        self.uart
+    }
+}
+
+impl<U> RcReceiver for Ibus<U>
+where U: Read + ReadReady
+{
+    type Error = U::Error;
+
+    fn poll(&mut self, now: Micros) -> Result<(), Self::Error> {
+        while self.read(now)?.is_some() {}
+        Ok(())
+    }
+
+    fn rc_channels(&self, now: Micros) -> Option<RcChannels> {
+        match self.is_failsafe(now) {
+            true => None,
+            false => self.channels.map(|channels| RcChannels::new(&channels)),
+        }
+    }
+
+    fn last_frame(&self) -> Option<Micros> {
+        self.last_frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::MockUart;
+
+    fn frame(channels: &[u16 ; CHANNELS]) -> Vec<u8> {
+        let mut frame = HEADER.to_vec();
+        frame.extend(channels.iter().flat_map(|c| c.to_le_bytes()));
+        let sum = frame.iter().fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+        frame.extend((0xFFFF - sum).to_le_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_parse() {
+        let channels: [u16 ; CHANNELS] = core::array::from_fn(|i| 1000 + i as u16 * 70);
+        let mut bytes: [u8 ; FRAME_LEN] = frame(&channels).try_into().unwrap();
+        assert_eq!(parse(&bytes), Some(channels));
+
+        bytes[5] ^= 0x10;
+        assert_eq!(parse(&bytes), None);
+    }
+
+    #[test]
+    fn test_decoder_resync() {
+        // Channel values of 0x4020 look like a header in the middle of the frame
+        let first = frame(&[0x4020 ; CHANNELS]);
+        let second = frame(&[1500 ; CHANNELS]);
+        let mut bytes = vec![0x20, 0x20];
+        bytes.extend(&second[..7]);
+        bytes.extend(&second[8..]);
+        bytes.extend(&first);
+        bytes.extend(&second);
+
+        let mut decoder = Decoder::new();
+        let frames: Vec<_> = bytes.iter().filter_map(|b| decoder.push(*b)).collect();
+        assert_eq!(frames, [[0x0020 ; CHANNELS], [1500 ; CHANNELS]]);
+        assert!(decoder.errors() > 0);
+    }
+
+    #[test]
+    fn test_receiver() {
+        let mut ibus = Ibus::new(MockUart::<64>::new());
+        assert_eq!(ibus.rc_channels(0), None);
+
+        ibus.uart.receive(&frame(&[1500 ; CHANNELS]));
+        ibus.poll(1000).unwrap();
+        let channels = ibus.rc_channels(1000).unwrap();
+        assert_eq!((channels.len(), channels.normalized(13)), (CHANNELS, Some(0.0)));
+        assert_eq!(ibus.link_quality().quality, None);
+        assert_eq!(ibus.rc_channels(1001 + FRAME_TIMEOUT), None);
+    }
+}
@@ -0,0 +1,392 @@
+//! Conversion of receiver channels into stick positions and flight modes.
+//!
+//! [RcMapper] picks the stick channels out of those sent by the receiver, scales each from its
+//! calibrated endpoints, and smooths the steps between frames so the control loop, which runs
+//! many times faster than frames arrive, sees a continuous command. The remaining channels
+//! switch [Modes] on and off.
+
+use heapless::Vec;
+
+use crate::{
+    interface::rc::{RcCommand, RcInput},
+    math::filter::{Biquad, Pt1},
+    Micros,
+};
+
+use super::{modes::{ModeActivation, Modes, MAX_ACTIVATIONS}, RcChannels, RcReceiver, MAX_CHANNELS};
+
+/// Fraction of the frame rate used as the smoothing cutoff when none is set
+pub const AUTO_CUTOFF_RATIO: f32 = 0.25;
+
+/// Lowest smoothing cutoff in Hz chosen from the frame rate
+pub const MIN_AUTO_CUTOFF: f32 = 5.0;
+
+/// Longest gap between frames in µs that is counted towards the frame rate, so that a brief
+/// loss of the link doesn't lower it
+const MAX_FRAME_INTERVAL: Micros = 50_000;
+
+/// Cutoff in Hz of the average of the frame interval
+const FRAME_RATE_CUTOFF: f32 = 0.5;
+
+/// Receiver channel carrying each stick, counting from zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelMap {
+    pub roll: usize,
+    pub pitch: usize,
+    pub yaw: usize,
+    pub throttle: usize,
+}
+
+impl ChannelMap {
+    /// Aileron, elevator, throttle, rudder, the order used by most transmitters
+    pub const AETR: Self = Self { roll: 0, pitch: 1, throttle: 2, yaw: 3 };
+    /// Throttle, aileron, elevator, rudder, the order used by Spektrum and Graupner
+    pub const TAER: Self = Self { throttle: 0, roll: 1, pitch: 2, yaw: 3 };
+
+    /// Parse the functions of the first four channels from letters such as `AETR`, each of `A`,
+    /// `E`, `T` and `R` appearing once
+    pub fn parse(map: &str) -> Option<Self> {
+        let map = map.as_bytes();
+        if map.len() != 4 {
+            return None
+        }
+
+        // With four letters, finding each of the four means none are repeated
+        let find = |letter: u8| map.iter().position(|b| b.to_ascii_uppercase() == letter);
+        Some(Self { roll: find(b'A')?, pitch: find(b'E')?, throttle: find(b'T')?, yaw: find(b'R')? })
+    }
+}
+
+impl Default for ChannelMap {
+    fn default() -> Self {
+        Self::AETR
+    }
+}
+
+/// Values in µs a channel reaches at the ends and centre of its stick's travel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelCalibration {
+    pub min: u16,
+    pub center: u16,
+    pub max: u16,
+    /// The stick moves the channel the opposite way
+    pub reversed: bool,
+}
+
+impl ChannelCalibration {
+    /// Scale a value to [-1, 1], scaling each side of the centre to its own endpoint
+    pub fn normalize(&self, value: u16) -> f32 {
+        let offset = value as f32 - self.center as f32;
+        let travel = match offset < 0f32 {
+            true => self.center.saturating_sub(self.min),
+            false => self.max.saturating_sub(self.center),
+        };
+        let position = (offset / travel.max(1) as f32).clamp(-1f32, 1f32);
+        if self.reversed { -position } else { position }
+    }
+
+    /// Scale a value to [0, 1] between the endpoints, ignoring the centre, as used for throttle
+    pub fn fraction(&self, value: u16) -> f32 {
+        let range = self.max.saturating_sub(self.min).max(1) as f32;
+        let fraction = ((value as f32 - self.min as f32) / range).clamp(0f32, 1f32);
+        if self.reversed { 1f32 - fraction } else { fraction }
+    }
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        Self { min: 1000, center: 1500, max: 2000, reversed: false }
+    }
+}
+
+/// Filter smoothing stick positions between frames
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Smoothing {
+    /// Hold each frame's values until the next
+    Off,
+    /// First order lowpass, with the least delay
+    #[default]
+    Pt1,
+    /// Second order lowpass, removing more of the steps for the same delay
+    Biquad,
+}
+
+/// Filters of one stick, only one of which is used depending on the [Smoothing]
+#[derive(Clone, Copy, Debug, Default)]
+struct AxisFilter {
+    pt1: Pt1<f32>,
+    biquad: Biquad<f32>,
+}
+
+impl AxisFilter {
+    fn update(&mut self, smoothing: Smoothing, input: f32, cutoff: f32, dt: f32) -> f32 {
+        match smoothing {
+            Smoothing::Off => input,
+            Smoothing::Pt1 => self.pt1.update(input, cutoff, dt),
+            Smoothing::Biquad => self.biquad.update(input, cutoff, dt),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pt1.reset();
+        self.biquad.reset();
+    }
+}
+
+/// Pilot commands and modes from the channels of an [RcReceiver]
+pub struct RcMapper<R> {
+    receiver: R,
+    pub map: ChannelMap,
+    /// Calibration of each receiver channel, of which only the sticks' are used
+    pub calibration: [ChannelCalibration ; MAX_CHANNELS],
+    pub activations: Vec<ModeActivation, MAX_ACTIVATIONS>,
+    pub smoothing: Smoothing,
+    /// Smoothing cutoff in Hz, or `None` to choose one from the measured frame rate
+    pub cutoff: Option<f32>,
+    filters: [AxisFilter ; 4],
+    /// Average time between frames in seconds
+    frame_interval: Pt1<f32>,
+    last_frame: Option<Micros>,
+    last_update: Option<Micros>,
+    command: Option<RcCommand<f32>>,
+    modes: Option<Modes>,
+    errors: u32,
+}
+
+impl<R: RcReceiver> RcMapper<R> {
+    /// Map the channels of `receiver` in AETR order with default calibration and no modes
+    pub fn new(receiver: R) -> Self {
+        Self {
+            receiver,
+            map: ChannelMap::default(),
+            calibration: [ChannelCalibration::default() ; MAX_CHANNELS],
+            activations: Vec::new(),
+            smoothing: Smoothing::default(),
+            cutoff: None,
+            filters: [AxisFilter::default() ; 4],
+            frame_interval: Pt1::new(),
+            last_frame: None,
+            last_update: None,
+            command: None,
+            modes: None,
+            errors: 0,
+        }
+    }
+
+    pub const fn receiver(&self) -> &R {
+        &self.receiver
+    }
+
+    pub fn receiver_mut(&mut self) -> &mut R {
+        &mut self.receiver
+    }
+
+    /// Get the average rate in Hz that frames are received at, once two have been
+    pub fn frame_rate(&self) -> Option<f32> {
+        self.frame_interval.output().map(|interval| 1f32 / interval)
+    }
+
+    /// Get the smoothing cutoff in Hz in use, or `None` if it is chosen from the frame rate and
+    /// no frame rate has been measured yet
+    pub fn smoothing_cutoff(&self) -> Option<f32> {
+        self.cutoff.or_else(|| self.frame_rate().map(|rate| (rate * AUTO_CUTOFF_RATIO).max(MIN_AUTO_CUTOFF)))
+    }
+
+    /// Get the number of times polling the receiver failed
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Release the receiver
+    pub fn free(self) -> R {
+        self.receiver
+    }
+
+    fn measure_frame_rate(&mut self) {
+        let Some(frame) = self.receiver.last_frame() else { return };
+        let Some(last) = self.last_frame.replace(frame) else { return };
+        let interval = frame.wrapping_sub(last);
+        if interval > 0 && interval <= MAX_FRAME_INTERVAL {
+            let interval = interval as f32 / 1_000_000f32;
+            self.frame_interval.update(interval, FRAME_RATE_CUTOFF, interval);
+        }
+    }
+
+    /// Stick positions from the channels, or `None` if the receiver doesn't send a stick channel
+    fn positions(&self, channels: &RcChannels) -> Option<[f32 ; 4]> {
+        let value = |channel: usize| channels.get(channel).map(|value| (self.calibration[channel], value));
+        let [roll, pitch, yaw, throttle] = [self.map.roll, self.map.pitch, self.map.yaw, self.map.throttle].map(value);
+        let position = |(calibration, value): (ChannelCalibration, u16)| calibration.normalize(value);
+        let (calibration, value) = throttle?;
+        Some([position(roll?), position(pitch?), position(yaw?), calibration.fraction(value)])
+    }
+}
+
+impl<R: RcReceiver> RcInput<f32> for RcMapper<R> {
+    /// Poll the receiver and map its latest channels. Errors from the receiver are counted and
+    /// otherwise ignored, leaving its failsafe timeout to report a link that stays down
+    fn command(&mut self, now: Micros) -> Option<RcCommand<f32>> {
+        if self.receiver.poll(now).is_err() {
+            self.errors = self.errors.wrapping_add(1);
+        }
+        self.measure_frame_rate();
+
+        let dt = match self.last_update.replace(now) {
+            Some(last) if last == now => return self.command,
+            Some(last) => now.wrapping_sub(last) as f32 / 1_000_000f32,
+            None => 0f32,
+        };
+
+        let channels = self.receiver.rc_channels(now);
+        let Some(positions) = channels.as_ref().and_then(|channels| self.positions(channels)) else {
+            // Start smoothing afresh from the first frame after the link returns
+            self.filters.iter_mut().for_each(AxisFilter::reset);
+            self.command = None;
+            self.modes = None;
+            return None
+        };
+
+        let cutoff = self.smoothing_cutoff().unwrap_or(0f32);
+        let [roll, pitch, yaw, throttle] = core::array::from_fn(|i| {
+            self.filters[i].update(self.smoothing, positions[i], cutoff, dt)
+        });
+        self.command = Some(RcCommand {
+            roll: roll.clamp(-1f32, 1f32),
+            pitch: pitch.clamp(-1f32, 1f32),
+            yaw: yaw.clamp(-1f32, 1f32),
+            throttle: throttle.clamp(0f32, 1f32),
+        });
+        self.modes = channels.map(|channels| Modes::from_activations(&self.activations, &channels));
+        self.command
+    }
+
+    fn modes(&self) -> Option<Modes> {
+        self.modes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestReceiver {
+        channels: Option<RcChannels>,
+        last_frame: Option<Micros>,
+    }
+
+    impl TestReceiver {
+        fn receive(&mut self, now: Micros, channels: &[u16]) {
+            self.channels = Some(RcChannels::new(channels));
+            self.last_frame = Some(now);
+        }
+    }
+
+    impl RcReceiver for TestReceiver {
+        type Error = ();
+
+        fn poll(&mut self, _: Micros) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn rc_channels(&self, _: Micros) -> Option<RcChannels> {
+            self.channels
+        }
+
+        fn last_frame(&self) -> Option<Micros> {
+            self.last_frame
+        }
+    }
+
+    fn mapper() -> RcMapper<TestReceiver> {
+        RcMapper::new(TestReceiver { channels: None, last_frame: None })
+    }
+
+    #[test]
+    fn test_channel_map() {
+        assert_eq!(ChannelMap::parse("AETR"), Some(ChannelMap::AETR));
+        assert_eq!(ChannelMap::parse("taer"), Some(ChannelMap::TAER));
+        assert_eq!(ChannelMap::parse("RTEA"), Some(ChannelMap { yaw: 0, throttle: 1, pitch: 2, roll: 3 }));
+        assert_eq!(ChannelMap::parse("AETT"), None);
+        assert_eq!(ChannelMap::parse("AETR1"), None);
+    }
+
+    #[test]
+    fn test_calibration() {
+        let calibration = ChannelCalibration { min: 1100, center: 1520, max: 1900, reversed: false };
+        assert_eq!(calibration.normalize(1520), 0.0);
+        assert_eq!(calibration.normalize(1310), -0.5);
+        assert_eq!(calibration.normalize(1710), 0.5);
+        assert_eq!(calibration.normalize(2100), 1.0);
+        assert_eq!(calibration.fraction(1100), 0.0);
+        assert_eq!(calibration.fraction(1300), 0.25);
+
+        let reversed = ChannelCalibration { reversed: true, ..calibration };
+        assert_eq!(reversed.normalize(1710), -0.5);
+        assert_eq!(reversed.fraction(1300), 0.75);
+
+        // Degenerate endpoints don't divide by zero
+        let flat = ChannelCalibration { min: 1500, center: 1500, max: 1500, reversed: false };
+        assert_eq!((flat.normalize(1400), flat.fraction(1600)), (-1.0, 1.0));
+    }
+
+    #[test]
+    fn test_command() {
+        let mut rc = mapper();
+        rc.smoothing = Smoothing::Off;
+        rc.map = ChannelMap::TAER;
+        rc.calibration[3].reversed = true;
+        rc.activations.push(ModeActivation::new(Modes::ARM, 4, 1700, 2100)).unwrap();
+        assert_eq!(rc.command(0), None);
+        assert_eq!(rc.modes(), None);
+
+        rc.receiver.receive(1000, &[1250, 2000, 1500, 1250, 1800]);
+        let command = rc.command(1000).unwrap();
+        assert_eq!(command, RcCommand { roll: 1.0, pitch: 0.0, yaw: 0.5, throttle: 0.25 });
+        assert_eq!(rc.modes(), Some(Modes::ARM));
+
+        // A stick channel the receiver doesn't send is the same as a lost link
+        rc.receiver.receive(2000, &[1250, 2000, 1500]);
+        assert_eq!((rc.command(2000), rc.modes()), (None, None));
+    }
+
+    #[test]
+    fn test_auto_smoothing() {
+        let mut rc = mapper();
+        rc.smoothing = Smoothing::Biquad;
+        assert_eq!(rc.smoothing_cutoff(), None);
+
+        // 150 Hz frames with a control loop at 1 kHz
+        let mut frame = 0;
+        let mut max_step = 0f32;
+        let mut last = None;
+        for t in (0..300_000).step_by(1000) {
+            if t >= frame {
+                let roll = if frame < 150_000 { 1000 } else { 2000 };
+                rc.receiver.receive(t, &[roll, 1500, 1000, 1500]);
+                frame += 6667;
+            }
+            let roll = rc.command(t).unwrap().roll;
+            if let Some(last) = last.replace(roll) {
+                max_step = max_step.max(roll - last);
+            }
+        }
+
+        let rate = rc.frame_rate().unwrap();
+        assert!((140f32..170f32).contains(&rate), "{rate}");
+        assert_eq!(rc.smoothing_cutoff(), Some(rate * AUTO_CUTOFF_RATIO));
+        assert_eq!(rc.command(299_000).map(|c| c.roll), last);
+        assert!((last.unwrap() - 1f32).abs() < 1e-3);
+
+        // The full stick step is spread over many loops rather than arriving at once
+        assert!(max_step < 0.3, "{max_step}");
+
+        // Losing the link restarts the filters from the next frame
+        rc.receiver.channels = None;
+        assert_eq!(rc.command(300_000), None);
+        rc.receiver.receive(301_000, &[1000, 1500, 1000, 1500]);
+        assert_eq!(rc.command(301_000).map(|c| c.roll), Some(-1.0));
+
+        rc.cutoff = Some(20f32);
+        assert_eq!(rc.smoothing_cutoff(), Some(20f32));
+    }
+}
@@ -0,0 +1,200 @@
+//! Receiver protocols carrying pilot commands from the RC link
+
+use crate::Micros;
+
+pub mod crsf;
+pub mod dsm;
+pub mod ibus;
+pub mod mapping;
+pub mod modes;
+pub mod ppm;
+pub mod sbus;
+
+/// Most channels carried by any receiver protocol
+pub const MAX_CHANNELS: usize = 18;
+
+/// Number of channels packed into 11 bits each by CRSF and SBUS
+pub const PACKED_CHANNELS: usize = 16;
+
+/// Length of the packed channel data
+pub const PACKED_LEN: usize = PACKED_CHANNELS * 11 / 8;
+
+/// Channel values from a receiver as pulse widths in µs, nominally 1000 to 2000 with the
+/// stick center at 1500, whatever the protocol they were received with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RcChannels {
+    values: [u16 ; MAX_CHANNELS],
+    len: usize,
+}
+
+impl RcChannels {
+    /// Create a set of channels, keeping only the first [MAX_CHANNELS] values
+    pub fn new(values: &[u16]) -> Self {
+        let len = values.len().min(MAX_CHANNELS);
+        let mut channels = Self { values: [0u16 ; MAX_CHANNELS], len };
+        channels.values[..len].copy_from_slice(&values[..len]);
+        channels
+    }
+
+    /// Get the value of a channel, counting from zero, or `None` if the receiver doesn't send it
+    pub fn get(&self, channel: usize) -> Option<u16> {
+        self.as_slice().get(channel).copied()
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.values[..self.len]
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the value of a channel scaled so that 1000 to 2000 µs is -1 to 1, or `None` if the
+    /// receiver doesn't send it
+    pub fn normalized(&self, channel: usize) -> Option<f32> {
+        self.get(channel).map(|value| (value as f32 - 1500f32) / 500f32)
+    }
+}
+
+/// Quality of the RC link, as far as the protocol reports it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkQuality {
+    /// Percentage of recent packets received from the transmitter
+    pub quality: Option<u8>,
+    /// Signal strength at the receiver in dBm
+    pub rssi: Option<i16>,
+}
+
+/// Receiver decoding pilot commands from the RC link, whatever its protocol
+pub trait RcReceiver {
+    type Error: core::fmt::Debug;
+
+    /// Process everything received since the last call
+    fn poll(&mut self, now: Micros) -> Result<(), Self::Error>;
+
+    /// Get the latest channel values, or `None` if the link is lost
+    fn rc_channels(&self, now: Micros) -> Option<RcChannels>;
+
+    /// Get the time the last channels were received, or `None` if none have been or the
+    /// receiver doesn't track it, in which case the frame rate isn't measured
+    fn last_frame(&self) -> Option<Micros> {
+        None
+    }
+
+    fn link_quality(&self) -> LinkQuality {
+        LinkQuality::default()
+    }
+}
+
+/// Record of which of the last 100 expected frames were received, for protocols that report
+/// lost frames rather than link quality
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct FrameHistory {
+    received: u128,
+    len: u32,
+}
+
+impl FrameHistory {
+    const LEN: u32 = 100;
+
+    pub fn push(&mut self, received: bool) {
+        self.received = (self.received << 1 | received as u128) & ((1 << Self::LEN) - 1);
+        self.len = (self.len + 1).min(Self::LEN);
+    }
+
+    /// Get the percentage of frames received, or `None` if none were expected yet
+    pub fn quality(&self) -> Option<u8> {
+        (self.len > 0).then(|| (self.received.count_ones() * 100 / self.len) as u8)
+    }
+}
+
+/// Unpack 16 11-bit channels, packed least significant bit first
+pub fn unpack_channels(packed: &[u8 ; PACKED_LEN]) -> [u16 ; PACKED_CHANNELS] {
+    let mut channels = [0u16 ; PACKED_CHANNELS];
+    let (mut bits, mut count, mut i) = (0u32, 0, 0);
+    for &byte in packed {
+        bits |= (byte as u32) << count;
+        count += 8;
+        if count >= 11 {
+            channels[i] = (bits & 0x7FF) as u16;
+            bits >>= 11;
+            count -= 11;
+            i += 1;
+        }
+    }
+
+    channels
+}
+
+/// Convert an 11-bit CRSF or SBUS channel value into a pulse width in µs, where 172 to 1811 is
+/// the 988 to 2012 µs range of the transmitter's sticks
+pub const fn packed_to_micros(value: u16) -> u16 {
+    (value as u32 * 1024 / 1639 + 881) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack channels as CRSF and SBUS send them
+    pub fn pack_channels(channels: &[u16 ; PACKED_CHANNELS]) -> Vec<u8> {
+        let (mut bits, mut count, mut packed) = (0u32, 0, Vec::new());
+        for &channel in channels {
+            bits |= (channel as u32 & 0x7FF) << count;
+            count += 11;
+            while count >= 8 {
+                packed.push(bits as u8);
+                bits >>= 8;
+                count -= 8;
+            }
+        }
+        packed
+    }
+
+    #[test]
+    fn test_packed_channels() {
+        let channels: [u16 ; PACKED_CHANNELS] = core::array::from_fn(|i| 172 + i as u16 * 109);
+        let packed = pack_channels(&channels);
+        assert_eq!(unpack_channels(&packed.try_into().unwrap()), channels);
+
+        assert_eq!(packed_to_micros(172), 988);
+        assert_eq!(packed_to_micros(992), 1500);
+        assert_eq!(packed_to_micros(1811), 2012);
+    }
+
+    #[test]
+    fn test_rc_channels() {
+        let channels = RcChannels::new(&[1500 ; 20]);
+        assert_eq!(channels.len(), MAX_CHANNELS);
+        assert_eq!(channels.get(17), Some(1500));
+        assert_eq!(channels.get(18), None);
+        assert!(RcChannels::new(&[]).is_empty());
+
+        let channels = RcChannels::new(&[1000, 1500, 1750]);
+        assert_eq!(channels.normalized(0), Some(-1.0));
+        assert_eq!(channels.normalized(2), Some(0.5));
+        assert_eq!(channels.normalized(3), None);
+    }
+
+    #[test]
+    fn test_frame_history() {
+        let mut history = FrameHistory::default();
+        assert_eq!(history.quality(), None);
+        history.push(true);
+        history.push(false);
+        assert_eq!(history.quality(), Some(50));
+
+        for _ in 0..150 {
+            history.push(true);
+        }
+        assert_eq!(history.quality(), Some(100));
+        for i in 0..100 {
+            history.push(i % 4 != 0);
+        }
+        assert_eq!(history.quality(), Some(75));
+    }
+}
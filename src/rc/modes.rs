@@ -0,0 +1,64 @@
+//! Flight modes and features switched on by ranges of aux channels
+
+pub use crate::interface::rc::Modes;
+
+use super::RcChannels;
+
+/// Most mode activations that can be configured
+pub const MAX_ACTIVATIONS: usize = 20;
+
+impl Modes {
+    /// Get the modes switched on by any of `activations` with the given channels
+    pub fn from_activations(activations: &[ModeActivation], channels: &RcChannels) -> Self {
+        activations.iter()
+            .filter(|activation| activation.is_active(channels))
+            .fold(Self::empty(), |modes, activation| modes | activation.modes)
+    }
+}
+
+/// Range of values of a channel that switches on one or more modes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModeActivation {
+    pub modes: Modes,
+    /// Receiver channel counting from zero, so the first aux channel is usually 4
+    pub channel: usize,
+    /// Lowest and highest values in µs that switch the modes on, inclusive
+    pub min: u16,
+    pub max: u16,
+}
+
+impl ModeActivation {
+    pub const fn new(modes: Modes, channel: usize, min: u16, max: u16) -> Self {
+        Self { modes, channel, min, max }
+    }
+
+    /// Whether the channel is within the range, which it isn't if the receiver doesn't send it
+    pub fn is_active(&self, channels: &RcChannels) -> bool {
+        channels.get(self.channel).is_some_and(|value| (self.min..=self.max).contains(&value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activations() {
+        let activations = [
+            ModeActivation::new(Modes::ARM, 4, 1700, 2100),
+            ModeActivation::new(Modes::ANGLE, 5, 900, 1300),
+            ModeActivation::new(Modes::HORIZON, 5, 1300, 1700),
+            ModeActivation::new(Modes::BEEPER | Modes::BLACKBOX, 6, 1700, 2100),
+            ModeActivation::new(Modes::TURTLE, 12, 1700, 2100),
+        ];
+
+        let channels = RcChannels::new(&[1500, 1500, 1000, 1500, 1000, 1500, 2000]);
+        let modes = Modes::from_activations(&activations, &channels);
+        assert_eq!(modes, Modes::HORIZON | Modes::BEEPER | Modes::BLACKBOX);
+
+        // Ranges include both ends
+        let channels = RcChannels::new(&[1500, 1500, 1000, 1500, 1700, 1300, 1000]);
+        let modes = Modes::from_activations(&activations, &channels);
+        assert_eq!(modes, Modes::ARM | Modes::ANGLE | Modes::HORIZON);
+    }
+}
@@ -0,0 +1,188 @@
+//! PPM, a train of pulses on one wire carrying every channel.
+//!
+//! The time between consecutive edges of the same polarity is the pulse width of a channel,
+//! and a frame ends with a gap longer than any channel. Edge times are captured by a timer and
+//! passed to [Ppm::edge], typically from the capture interrupt.
+
+use crate::Micros;
+
+use super::{LinkQuality, RcChannels, RcReceiver, MAX_CHANNELS};
+
+/// Shortest gap between pulses that ends a frame
+pub const SYNC_GAP: Micros = 2700;
+
+/// Shortest and longest valid channel pulse widths
+pub const PULSE_RANGE: (Micros, Micros) = (750, 2250);
+
+/// Fewest channels in a valid frame
+pub const MIN_CHANNELS: usize = 4;
+
+/// Time without a frame after which the link is considered lost
+pub const FRAME_TIMEOUT: Micros = 100_000;
+
+/// PPM decoder turning captured edge times into channels
+pub struct Ppm {
+    last_edge: Option<Micros>,
+    /// Widths of the frame being received, or `None` until the next sync gap after an error
+    pulses: Option<([u16 ; MAX_CHANNELS], usize)>,
+    /// Number of channels in the last complete frame
+    previous_count: usize,
+    channels: Option<RcChannels>,
+    last_frame: Option<Micros>,
+    errors: u32,
+    /// Time without a frame after which the link is considered lost
+    pub timeout: Micros,
+}
+
+impl Ppm {
+    pub const fn new() -> Self {
+        Self {
+            last_edge: None,
+            pulses: None,
+            previous_count: 0,
+            channels: None,
+            last_frame: None,
+            errors: 0,
+            timeout: FRAME_TIMEOUT,
+        }
+    }
+
+    /// Handle a captured edge at `time`, returning the channels if it completes a frame. A frame
+    /// is accepted once it has the same number of channels as the frame before it
+    pub fn edge(&mut self, time: Micros) -> Option<RcChannels> {
+        let width = time.wrapping_sub(self.last_edge.replace(time)?);
+        if width >= SYNC_GAP {
+            // Consecutive gaps, such as a pause in the signal, don't count as an empty frame
+            let complete = self.pulses.replace(([0u16 ; MAX_CHANNELS], 0)).filter(|(_, count)| {
+                let stable = *count == self.previous_count;
+                if *count > 0 {
+                    self.previous_count = *count;
+                }
+                stable && *count >= MIN_CHANNELS
+            });
+            let (pulses, count) = complete?;
+            self.channels = Some(RcChannels::new(&pulses[..count]));
+            self.last_frame = Some(time);
+            return self.channels
+        }
+
+        let (pulses, count) = self.pulses.as_mut()?;
+        if !(PULSE_RANGE.0..=PULSE_RANGE.1).contains(&width) || *count >= MAX_CHANNELS {
+            // A noise spike or a missed edge, so drop the rest of the frame
+            self.pulses = None;
+            self.errors = self.errors.wrapping_add(1);
+            return None
+        }
+        pulses[*count] = width as u16;
+        *count += 1;
+        None
+    }
+
+    /// Whether no frame has been received within [timeout](Self::timeout) of `now`
+    pub fn is_failsafe(&self, now: Micros) -> bool {
+        match self.last_frame {
+            Some(last) => now.wrapping_sub(last) > self.timeout,
+            None => true,
+        }
+    }
+
+    /// Get the number of frames dropped because a pulse was out of range
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+}
+
+impl Default for Ppm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RcReceiver for Ppm {
+    type Error = core::convert::Infallible;
+
+    /// Edges are handled as they are captured, so there is nothing to do here
+    fn poll(&mut self, _: Micros) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn rc_channels(&self, now: Micros) -> Option<RcChannels> {
+        match self.is_failsafe(now) {
+            true => None,
+            false => self.channels,
+        }
+    }
+
+    fn last_frame(&self) -> Option<Micros> {
+        self.last_frame
+    }
+
+    fn link_quality(&self) -> LinkQuality {
+        LinkQuality::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send a frame of pulses followed by the sync gap, returning the time after it
+    fn send(ppm: &mut Ppm, start: Micros, widths: &[u16]) -> (Micros, Option<RcChannels>) {
+        let mut time = start;
+        let mut result = None;
+        for &width in widths.iter().chain(&[4000]) {
+            time = time.wrapping_add(width as Micros);
+            result = ppm.edge(time);
+        }
+        (time, result)
+    }
+
+    #[test]
+    fn test_frames() {
+        let widths = [1500, 1000, 2000, 1200, 1800, 1500, 1500, 1500];
+        let mut ppm = Ppm::new();
+        ppm.edge(0);
+
+        // The first frame after the initial sync only establishes the channel count
+        let (time, _) = send(&mut ppm, 0, &widths);
+        let (time, result) = send(&mut ppm, time, &widths);
+        assert_eq!(result, None);
+        let (time, result) = send(&mut ppm, time, &widths);
+        assert_eq!(result.map(|c| c.as_slice().to_vec()), Some(widths.to_vec()));
+        assert_eq!(ppm.rc_channels(time).and_then(|c| c.normalized(2)), Some(1.0));
+
+        // The signal pauses, then the timer wraps during a frame
+        ppm.edge(u32::MAX - 3000);
+        let (time, result) = send(&mut ppm, u32::MAX - 3000, &widths);
+        assert!(result.is_some());
+        assert!(ppm.rc_channels(time + FRAME_TIMEOUT).is_some());
+        assert!(ppm.rc_channels(time + FRAME_TIMEOUT + 1).is_none());
+    }
+
+    #[test]
+    fn test_invalid_pulses() {
+        let widths = [1500 ; 6];
+        let mut ppm = Ppm::new();
+        ppm.edge(0);
+        let (mut time, _) = send(&mut ppm, 0, &widths);
+        (time, _) = send(&mut ppm, time, &widths);
+
+        // A spike splits a pulse in two, so the frame is dropped
+        let (time, result) = send(&mut ppm, time, &[1500, 100, 1400, 1500, 1500, 1500, 1500]);
+        assert_eq!((result, ppm.errors()), (None, 1));
+        let (time, result) = send(&mut ppm, time, &widths);
+        assert!(result.is_some());
+
+        // A frame with fewer channels isn't trusted until it repeats
+        let (time, result) = send(&mut ppm, time, &widths[..5]);
+        assert_eq!(result, None);
+        let (_, result) = send(&mut ppm, time, &widths[..5]);
+        assert_eq!(result.map(|c| c.len()), Some(5));
+
+        // Too few channels to be a receiver
+        let mut ppm = Ppm::new();
+        ppm.edge(0);
+        let (time, _) = send(&mut ppm, 0, &widths[..3]);
+        assert_eq!(send(&mut ppm, time, &widths[..3]).1, None);
+    }
+}
@@ -0,0 +1,325 @@
+//! SBUS, the serial protocol of FrSky and Futaba receivers.
+//!
+//! The receiver sends a 25-byte frame every 7 or 14 ms on an inverted UART at 100 000 baud,
+//! with 8 data bits, even parity and 2 stop bits:
+//!
+//! ```text
+//! 0x0F | 16 channels packed in 22 bytes | flags | 0x00
+//! ```
+//!
+//! There is no checksum, so frames are found by their header and footer bytes. SBUS2 receivers
+//! replace the footer with 0x04, 0x14, 0x24 or 0x34 to mark the telemetry slots that follow
+
+use embedded_io::{Read, ReadReady};
+
+use crate::Micros;
+
+use super::{
+    packed_to_micros, unpack_channels, FrameHistory, LinkQuality, RcChannels, RcReceiver, MAX_CHANNELS,
+    PACKED_CHANNELS, PACKED_LEN,
+};
+
+pub const BAUD_RATE: u32 = 100_000;
+
+/// Length of a frame including its header and footer
+pub const FRAME_LEN: usize = 25;
+
+/// Byte starting each frame
+pub const HEADER: u8 = 0x0F;
+
+/// Time without a frame after which the link is considered lost
+pub const FRAME_TIMEOUT: Micros = 100_000;
+
+/// Raw values of the on and off states of the digital channels
+const DIGITAL: (u16, u16) = (1811, 172);
+
+// Flag byte bits
+const FLAG_CHANNEL_17: u8 = 1 << 0;
+const FLAG_CHANNEL_18: u8 = 1 << 1;
+const FLAG_FRAME_LOST: u8 = 1 << 2;
+const FLAG_FAILSAFE: u8 = 1 << 3;
+
+/// Frame decoded from the receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SbusFrame {
+    /// Raw 11-bit channel values, see [packed_to_micros]
+    pub channels: [u16 ; PACKED_CHANNELS],
+    /// States of the on/off channels 17 and 18
+    pub digital: [bool ; 2],
+    /// The receiver missed a packet from the transmitter and repeated its last channels
+    pub frame_lost: bool,
+    /// The receiver has lost the link and is sending its failsafe channels
+    pub failsafe: bool,
+}
+
+impl SbusFrame {
+    /// Parse a frame, or return `None` if its header or footer is wrong
+    pub fn parse(frame: &[u8 ; FRAME_LEN]) -> Option<Self> {
+        if frame[0] != HEADER || !is_footer(frame[FRAME_LEN - 1]) {
+            return None
+        }
+
+        let flags = frame[FRAME_LEN - 2];
+        let packed = frame[1..PACKED_LEN + 1].try_into().ok()?;
+        Some(Self {
+            channels: unpack_channels(packed),
+            digital: [flags & FLAG_CHANNEL_17 != 0, flags & FLAG_CHANNEL_18 != 0],
+            frame_lost: flags & FLAG_FRAME_LOST != 0,
+            failsafe: flags & FLAG_FAILSAFE != 0,
+        })
+    }
+
+    /// Get all 18 channels in µs, with the digital channels at the ends of the stick range
+    pub fn rc_channels(&self) -> RcChannels {
+        let mut values = [0u16 ; MAX_CHANNELS];
+        for (value, raw) in values.iter_mut().zip(self.channels) {
+            *value = packed_to_micros(raw);
+        }
+        for (value, on) in values[PACKED_CHANNELS..].iter_mut().zip(self.digital) {
+            *value = packed_to_micros(if on { DIGITAL.0 } else { DIGITAL.1 });
+        }
+
+        RcChannels::new(&values)
+    }
+}
+
+/// Whether a byte ends an SBUS or SBUS2 frame
+const fn is_footer(byte: u8) -> bool {
+    byte == 0x00 || byte & 0xCF == 0x04
+}
+
+/// Splits a byte stream into frames, skipping bytes until a header and footer line up
+pub struct Decoder {
+    buf: [u8 ; FRAME_LEN],
+    len: usize,
+    errors: u32,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self { buf: [0u8 ; FRAME_LEN], len: 0, errors: 0 }
+    }
+
+    /// Get the number of frames discarded because their footer was wrong
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Add a byte from the stream, returning a frame if this byte completes one
+    pub fn push(&mut self, byte: u8) -> Option<SbusFrame> {
+        if self.len == 0 && byte != HEADER {
+            return None
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME_LEN {
+            return None
+        }
+
+        if let Some(frame) = SbusFrame::parse(&self.buf) {
+            self.len = 0;
+            return Some(frame)
+        }
+
+        // A corrupted byte or a header byte inside a frame, so start again from the next header
+        self.errors = self.errors.wrapping_add(1);
+        let start = self.buf[1..].iter().position(|b| *b == HEADER).map_or(FRAME_LEN, |i| i + 1);
+        self.buf.copy_within(start.., 0);
+        self.len = FRAME_LEN - start;
+        None
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// SBUS receiver on a UART, tracking the latest frame and the state of the link
+pub struct Sbus<U> {
+    uart: U,
+    decoder: Decoder,
+    frame: Option<SbusFrame>,
+    last_frame: Option<Micros>,
+    frames_lost: u32,
+    history: FrameHistory,
+    /// Time without a frame after which the link is considered lost
+    pub timeout: Micros,
+}
+
+impl<U> Sbus<U>
+where U: Read + ReadReady
+{
+    pub fn new(uart: U) -> Self {
+        Self {
+            uart,
+            decoder: Decoder::new(),
+            frame: None,
+            last_frame: None,
+            frames_lost: 0,
+            history: FrameHistory::default(),
+            timeout: FRAME_TIMEOUT,
+        }
+    }
+
+    /// Read received bytes until they complete a frame, returning it, or `None` once no more
+    /// bytes are waiting
+    pub fn read(&mut self, now: Micros) -> Result<Option<SbusFrame>, U::Error> {
+        let mut byte = [0u8];
+        while self.uart.read_ready()? && self.uart.read(&mut byte)? > 0 {
+            if let Some(frame) = self.decoder.push(byte[0]) {
+                self.frames_lost = self.frames_lost.wrapping_add(frame.frame_lost as u32);
+                self.history.push(!(frame.frame_lost || frame.failsafe));
+                self.frame = Some(frame);
+                self.last_frame = Some(now);
+                return Ok(Some(frame))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Handle every frame received since the last call
+    pub fn poll(&mut self, now: Micros) -> Result<(), U::Error> {
+        while self.read(now)?.is_some() {}
+        Ok(())
+    }
+
+    /// Whether the receiver reports failsafe, or no frame has been received within
+    /// [timeout](Self::timeout) of `now`
+    pub fn is_failsafe(&self, now: Micros) -> bool {
+        match (self.frame, self.last_frame) {
+            (Some(frame), Some(last)) => frame.failsafe || now.wrapping_sub(last) > self.timeout,
+            _ => true,
+        }
+    }
+
+    /// Get the latest channel values in µs, or `None` if the link is in failsafe
+    pub fn rc_channels(&self, now: Micros) -> Option<RcChannels> {
+        match self.is_failsafe(now) {
+            true => None,
+            false => self.frame.map(|frame| frame.rc_channels()),
+        }
+    }
+
+    /// Get the latest frame, including while in failsafe
+    pub const fn frame(&self) -> Option<SbusFrame> {
+        self.frame
+    }
+
+    /// Get the number of frames the receiver flagged as repeated after a lost packet
+    pub const fn frames_lost(&self) -> u32 {
+        self.frames_lost
+    }
+
+    /// Get the number of frames discarded because they were corrupt
+    pub const fn errors(&self) -> u32 {
+        self.decoder.errors()
+    }
+
+    /// Release the UART
+    pub fn free(self) -> U {
+        self.uart
+    }
+}
+
+impl<U> RcReceiver for Sbus<U>
+where U: Read + ReadReady
+{
+    type Error = U::Error;
+
+    fn poll(&mut self, now: Micros) -> Result<(), Self::Error> {
+        self.poll(now)
+    }
+
+    fn rc_channels(&self, now: Micros) -> Option<RcChannels> {
+        self.rc_channels(now)
+    }
+
+    fn last_frame(&self) -> Option<Micros> {
+        self.last_frame
+    }
+
+    /// Quality from the frames the receiver flagged as lost
+    fn link_quality(&self) -> LinkQuality {
+        LinkQuality { quality: self.history.quality(), rssi: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interface::mock::MockUart, rc::tests::pack_channels};
+
+    fn frame(channels: &[u16 ; PACKED_CHANNELS], flags: u8) -> Vec<u8> {
+        let mut frame = vec![HEADER];
+        frame.extend(pack_channels(channels));
+        frame.extend([flags, 0x00]);
+        frame
+    }
+
+    #[test]
+    fn test_parse() {
+        let channels: [u16 ; PACKED_CHANNELS] = core::array::from_fn(|i| 172 + i as u16 * 109);
+        let bytes = frame(&channels, FLAG_CHANNEL_18 | FLAG_FRAME_LOST);
+        let parsed = SbusFrame::parse(&bytes.clone().try_into().unwrap()).unwrap();
+        assert_eq!(parsed.channels, channels);
+        assert_eq!(parsed.digital, [false, true]);
+        assert!(parsed.frame_lost && !parsed.failsafe);
+
+        let rc = parsed.rc_channels();
+        assert_eq!(rc.len(), MAX_CHANNELS);
+        assert_eq!((rc.get(0), rc.get(16), rc.get(17)), (Some(988), Some(988), Some(2012)));
+
+        // SBUS2 footers are accepted, anything else isn't
+        let mut sbus2: [u8 ; FRAME_LEN] = bytes.try_into().unwrap();
+        sbus2[FRAME_LEN - 1] = 0x24;
+        assert!(SbusFrame::parse(&sbus2).is_some());
+        sbus2[FRAME_LEN - 1] = 0x25;
+        assert!(SbusFrame::parse(&sbus2).is_none());
+    }
+
+    #[test]
+    fn test_decoder_resync() {
+        let first = frame(&[HEADER as u16 ; PACKED_CHANNELS], 0);
+        let second = frame(&[992 ; PACKED_CHANNELS], 0);
+
+        // Noise, a frame with a dropped byte, then two good frames. The channel data of the
+        // first is full of header bytes, which must not be mistaken for the start of a frame
+        let mut bytes = vec![0xFF, 0x00];
+        bytes.extend(&second[..10]);
+        bytes.extend(&second[11..]);
+        bytes.extend(&first);
+        bytes.extend(&second);
+
+        let mut decoder = Decoder::new();
+        let frames: Vec<_> = bytes.iter().filter_map(|b| decoder.push(*b)).collect();
+        assert_eq!(frames.len(), 2, "{frames:?}");
+        assert_eq!(frames[0].channels, [HEADER as u16 ; PACKED_CHANNELS]);
+        assert_eq!(frames[1].channels, [992 ; PACKED_CHANNELS]);
+        assert!(decoder.errors() > 0);
+    }
+
+    #[test]
+    fn test_failsafe() {
+        let mut sbus = Sbus::new(MockUart::<128>::new());
+        assert!(sbus.is_failsafe(0));
+
+        sbus.uart.receive(&frame(&[992 ; PACKED_CHANNELS], FLAG_FRAME_LOST));
+        assert!(sbus.read(1000).unwrap().is_some());
+        assert_eq!(sbus.read(1000), Ok(None));
+        assert_eq!(sbus.rc_channels(1000).and_then(|rc| rc.get(3)), Some(1500));
+        assert_eq!(sbus.frames_lost(), 1);
+        assert_eq!(sbus.link_quality().quality, Some(0));
+        assert!(!sbus.is_failsafe(1000 + FRAME_TIMEOUT));
+        assert!(sbus.is_failsafe(1001 + FRAME_TIMEOUT));
+
+        // The receiver's failsafe flag takes effect immediately
+        sbus.uart.receive(&frame(&[992 ; PACKED_CHANNELS], FLAG_FAILSAFE | FLAG_FRAME_LOST));
+        sbus.poll(2000).unwrap();
+        assert!(sbus.is_failsafe(2000));
+        assert_eq!(sbus.rc_channels(2000), None);
+        assert!(sbus.frame().unwrap().failsafe);
+    }
+}
@@ -1,3 +1,358 @@
+//! Flight state machine tracking whether the vehicle may drive its motors
 
+use bitflags::bitflags;
 
+use crate::interface::rc::Modes;
 
+bitflags! {
+    /// Reasons that the vehicle is currently prevented from arming
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct ArmingDisabled: u32 {
+        /// No gyro has been detected or it has stopped responding
+        const NO_GYRO = 1 << 0;
+        /// Sensors are being calibrated
+        const CALIBRATING = 1 << 1;
+        /// The boot sequence has not completed
+        const BOOT = 1 << 2;
+        /// No valid frames are being received from the transmitter
+        const RX_LOSS = 1 << 3;
+        /// Failsafe was triggered and has not been cleared
+        const FAILSAFE = 1 << 4;
+        /// Throttle is not at its lowest position
+        const THROTTLE = 1 << 5;
+        /// The vehicle is tilted too far from level
+        const ANGLE = 1 << 6;
+        /// The arm switch was already on when arming became possible and must be cycled
+        const ARM_SWITCH = 1 << 7;
+        /// A crash was detected and the vehicle must be disarmed first
+        const CRASH = 1 << 8;
+        /// The control loop is not keeping up with the configured rate
+        const LOAD = 1 << 9;
+        /// The configuration interface is in use
+        const CLI = 1 << 10;
+        /// A setting was changed that requires a reboot
+        const REBOOT_REQUIRED = 1 << 11;
+    }
+}
+
+/// Phase of flight the vehicle is in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlightState {
+    /// Peripherals are being initialized
+    #[default]
+    Booting,
+    /// The vehicle must be held still while sensor offsets are measured
+    Calibrating,
+    /// Motors are stopped and the vehicle may be armed
+    Disarmed,
+    /// Motors are driven by the control loop
+    Armed,
+    /// The RC link was lost while armed
+    Failsafe,
+    /// A crash was detected while armed
+    Crashed,
+    /// The vehicle is armed but resting on the ground
+    Landed,
+    /// Motors are reversed and driven straight from the sticks to flip a crashed vehicle back
+    /// over, rather than by the control loop
+    Turtle,
+}
+
+impl FlightState {
+    /// Check if motors may be driven by the control loop in this state
+    pub const fn is_armed(&self) -> bool {
+        matches!(self, Self::Armed | Self::Landed)
+    }
+}
+
+/// Input to the state machine that may cause a transition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    BootComplete,
+    CalibrationStarted,
+    CalibrationComplete,
+    ArmRequested,
+    /// Arming in turtle mode was requested
+    TurtleRequested,
+    DisarmRequested,
+    LinkLost,
+    LinkRecovered,
+    CrashDetected,
+    LandingDetected,
+    TakeoffDetected,
+}
+
+/// Error returned when an event is not valid in the current state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionError {
+    /// Arming was requested while one or more reasons prevented it
+    ArmingDisabled(ArmingDisabled),
+    /// The event has no transition from the current state
+    Invalid { state: FlightState, event: Event },
+    /// Turtle mode was requested but the motors can't be reversed, so the vehicle stays disarmed
+    MotorsNotReversible,
+}
+
+/// Callbacks invoked by the [StateMachine] as it handles events, and by the
+/// [FlightController](crate::FlightController) as the pilot switches modes
+pub trait StateHooks {
+    /// Called after the state changes from `from` to `to` because of `event`
+    fn on_transition(&mut self, _from: FlightState, _to: FlightState, _event: Event) {}
+
+    /// Called when an arming request is refused for the given reasons
+    fn on_arming_refused(&mut self, _reasons: ArmingDisabled) {}
+
+    /// Called when the modes switched on by the pilot change from `previous` to `modes`. Losing
+    /// the RC link leaves the modes unchanged until it returns
+    fn on_modes_changed(&mut self, _previous: Modes, _modes: Modes) {}
+}
+
+impl StateHooks for () {}
+
+/// Flight state and the set of reasons preventing arming
+pub struct StateMachine<H: StateHooks = ()> {
+    state: FlightState,
+    arming_disabled: ArmingDisabled,
+    hooks: H,
+}
+
+impl<H: StateHooks> StateMachine<H> {
+    /// Create a state machine in the [FlightState::Booting] state, calling `hooks` on events
+    pub const fn new(hooks: H) -> Self {
+        Self {
+            state: FlightState::Booting,
+            arming_disabled: ArmingDisabled::BOOT,
+            hooks,
+        }
+    }
+
+    pub const fn state(&self) -> FlightState {
+        self.state
+    }
+
+    /// Get all reasons currently preventing the vehicle from arming
+    pub const fn arming_disabled(&self) -> ArmingDisabled {
+        self.arming_disabled
+    }
+
+    /// Check if the vehicle could be armed now, returning the reasons it can't if not
+    pub fn can_arm(&self) -> Result<(), ArmingDisabled> {
+        match self.arming_disabled.is_empty() {
+            true => Ok(()),
+            false => Err(self.arming_disabled),
+        }
+    }
+
+    /// Set or clear reasons preventing arming. Reasons do not affect a vehicle that is already armed
+    pub fn set_arming_disabled(&mut self, reasons: ArmingDisabled, disabled: bool) {
+        self.arming_disabled.set(reasons, disabled);
+    }
+
+    pub const fn hooks(&self) -> &H {
+        &self.hooks
+    }
+
+    pub fn hooks_mut(&mut self) -> &mut H {
+        &mut self.hooks
+    }
+
+    /// Handle an event, returning the new state if a transition was made
+    pub fn handle(&mut self, event: Event) -> Result<FlightState, TransitionError> {
+        use FlightState as S;
+
+        let next = match (self.state, event) {
+            (S::Booting, Event::BootComplete) => {
+                self.arming_disabled.remove(ArmingDisabled::BOOT);
+                S::Disarmed
+            },
+            (S::Booting | S::Disarmed, Event::CalibrationStarted) => {
+                self.arming_disabled.insert(ArmingDisabled::CALIBRATING);
+                S::Calibrating
+            },
+            (S::Calibrating, Event::CalibrationComplete) => {
+                self.arming_disabled.remove(ArmingDisabled::CALIBRATING | ArmingDisabled::BOOT);
+                S::Disarmed
+            },
+            (S::Disarmed, Event::ArmRequested) => {
+                if let Err(reasons) = self.can_arm() {
+                    self.hooks.on_arming_refused(reasons);
+                    return Err(TransitionError::ArmingDisabled(reasons))
+                }
+                S::Armed
+            },
+            // A crashed vehicle is likely upside down, so its tilt doesn't prevent turtle mode
+            (S::Disarmed, Event::TurtleRequested) => {
+                let reasons = self.arming_disabled - ArmingDisabled::ANGLE;
+                if !reasons.is_empty() {
+                    self.hooks.on_arming_refused(reasons);
+                    return Err(TransitionError::ArmingDisabled(reasons))
+                }
+                S::Turtle
+            },
+            (S::Armed | S::Landed | S::Turtle, Event::DisarmRequested) => S::Disarmed,
+            (S::Armed | S::Landed | S::Turtle, Event::LinkLost) => {
+                self.arming_disabled.insert(ArmingDisabled::FAILSAFE | ArmingDisabled::RX_LOSS);
+                S::Failsafe
+            },
+            (S::Armed, Event::CrashDetected) => {
+                self.arming_disabled.insert(ArmingDisabled::CRASH);
+                S::Crashed
+            },
+            (S::Armed, Event::LandingDetected) => S::Landed,
+            (S::Landed, Event::TakeoffDetected) => S::Armed,
+            // The arm switch is likely still on after the link returns, so require it to be cycled
+            (S::Failsafe, Event::LinkRecovered) => {
+                self.arming_disabled.remove(ArmingDisabled::FAILSAFE | ArmingDisabled::RX_LOSS);
+                self.arming_disabled.insert(ArmingDisabled::ARM_SWITCH);
+                S::Disarmed
+            },
+            (S::Failsafe, Event::DisarmRequested) => S::Disarmed,
+            (S::Crashed, Event::DisarmRequested) => {
+                self.arming_disabled.remove(ArmingDisabled::CRASH);
+                S::Disarmed
+            },
+            (S::Disarmed, Event::DisarmRequested) => S::Disarmed,
+            (S::Booting | S::Calibrating | S::Disarmed | S::Crashed, Event::LinkLost) => {
+                self.arming_disabled.insert(ArmingDisabled::RX_LOSS);
+                self.state
+            },
+            (S::Booting | S::Calibrating | S::Disarmed | S::Crashed, Event::LinkRecovered) => {
+                if self.arming_disabled.contains(ArmingDisabled::FAILSAFE) {
+                    self.arming_disabled.insert(ArmingDisabled::ARM_SWITCH);
+                }
+                self.arming_disabled.remove(ArmingDisabled::FAILSAFE | ArmingDisabled::RX_LOSS);
+                self.state
+            },
+            (state, event) => return Err(TransitionError::Invalid { state, event }),
+        };
+
+        let prev = core::mem::replace(&mut self.state, next);
+        if prev != next {
+            self.hooks.on_transition(prev, next, event);
+        }
+
+        Ok(next)
+    }
+}
+
+impl Default for StateMachine<()> {
+    fn default() -> Self {
+        Self::new(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        transitions: Vec<(FlightState, FlightState)>,
+        refused: Vec<ArmingDisabled>,
+    }
+
+    impl StateHooks for Recorder {
+        fn on_transition(&mut self, from: FlightState, to: FlightState, _: Event) {
+            self.transitions.push((from, to));
+        }
+
+        fn on_arming_refused(&mut self, reasons: ArmingDisabled) {
+            self.refused.push(reasons);
+        }
+    }
+
+    fn disarmed() -> StateMachine<Recorder> {
+        let mut sm = StateMachine::new(Recorder::default());
+        sm.handle(Event::CalibrationStarted).unwrap();
+        sm.handle(Event::CalibrationComplete).unwrap();
+        sm
+    }
+
+    #[test]
+    fn test_boot_prevents_arming() {
+        let mut sm = StateMachine::new(Recorder::default());
+        assert_eq!(sm.can_arm(), Err(ArmingDisabled::BOOT));
+        assert_eq!(
+            sm.handle(Event::ArmRequested),
+            Err(TransitionError::Invalid { state: FlightState::Booting, event: Event::ArmRequested })
+        );
+
+        sm.handle(Event::BootComplete).unwrap();
+        assert_eq!(sm.handle(Event::ArmRequested), Ok(FlightState::Armed));
+    }
+
+    #[test]
+    fn test_arming_refused_reasons() {
+        let mut sm = disarmed();
+        sm.set_arming_disabled(ArmingDisabled::THROTTLE | ArmingDisabled::ANGLE, true);
+        assert_eq!(
+            sm.handle(Event::ArmRequested),
+            Err(TransitionError::ArmingDisabled(ArmingDisabled::THROTTLE | ArmingDisabled::ANGLE))
+        );
+        assert_eq!(sm.hooks().refused, [ArmingDisabled::THROTTLE | ArmingDisabled::ANGLE]);
+
+        sm.set_arming_disabled(ArmingDisabled::ANGLE, false);
+        sm.set_arming_disabled(ArmingDisabled::THROTTLE, false);
+        assert_eq!(sm.handle(Event::ArmRequested), Ok(FlightState::Armed));
+        assert_eq!(
+            sm.hooks().transitions.last(),
+            Some(&(FlightState::Disarmed, FlightState::Armed))
+        );
+    }
+
+    #[test]
+    fn test_failsafe_requires_arm_switch_cycle() {
+        let mut sm = disarmed();
+        sm.handle(Event::ArmRequested).unwrap();
+        assert_eq!(sm.handle(Event::LinkLost), Ok(FlightState::Failsafe));
+        assert!(!sm.state().is_armed());
+
+        assert_eq!(sm.handle(Event::LinkRecovered), Ok(FlightState::Disarmed));
+        assert_eq!(sm.can_arm(), Err(ArmingDisabled::ARM_SWITCH));
+
+        sm.set_arming_disabled(ArmingDisabled::ARM_SWITCH, false);
+        assert_eq!(sm.handle(Event::ArmRequested), Ok(FlightState::Armed));
+    }
+
+    #[test]
+    fn test_disarm_during_failsafe() {
+        let mut sm = disarmed();
+        sm.handle(Event::ArmRequested).unwrap();
+        sm.handle(Event::LinkLost).unwrap();
+        assert_eq!(sm.handle(Event::DisarmRequested), Ok(FlightState::Disarmed));
+        assert_eq!(sm.can_arm(), Err(ArmingDisabled::FAILSAFE | ArmingDisabled::RX_LOSS));
+
+        assert_eq!(sm.handle(Event::LinkRecovered), Ok(FlightState::Disarmed));
+        assert_eq!(sm.can_arm(), Err(ArmingDisabled::ARM_SWITCH));
+    }
+
+    #[test]
+    fn test_crash_and_landing() {
+        let mut sm = disarmed();
+        sm.handle(Event::ArmRequested).unwrap();
+        assert_eq!(sm.handle(Event::LandingDetected), Ok(FlightState::Landed));
+        assert!(sm.state().is_armed());
+        assert_eq!(sm.handle(Event::TakeoffDetected), Ok(FlightState::Armed));
+
+        assert_eq!(sm.handle(Event::CrashDetected), Ok(FlightState::Crashed));
+        assert_eq!(sm.can_arm(), Err(ArmingDisabled::CRASH));
+        assert_eq!(sm.handle(Event::DisarmRequested), Ok(FlightState::Disarmed));
+        assert_eq!(sm.can_arm(), Ok(()));
+    }
+
+    #[test]
+    fn test_turtle_ignores_tilt() {
+        let mut sm = disarmed();
+        sm.set_arming_disabled(ArmingDisabled::ANGLE | ArmingDisabled::THROTTLE, true);
+        assert_eq!(sm.handle(Event::TurtleRequested), Err(TransitionError::ArmingDisabled(ArmingDisabled::THROTTLE)));
+
+        sm.set_arming_disabled(ArmingDisabled::THROTTLE, false);
+        assert_eq!(sm.handle(Event::TurtleRequested), Ok(FlightState::Turtle));
+        assert!(!sm.state().is_armed());
+        assert_eq!(sm.handle(Event::CrashDetected), Err(TransitionError::Invalid { state: FlightState::Turtle, event: Event::CrashDetected }));
+        assert_eq!(sm.handle(Event::DisarmRequested), Ok(FlightState::Disarmed));
+
+        sm.handle(Event::TurtleRequested).unwrap();
+        assert_eq!(sm.handle(Event::LinkLost), Ok(FlightState::Failsafe));
+    }
+}
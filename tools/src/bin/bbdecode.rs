@@ -0,0 +1,247 @@
+//! Decode blackbox logs into CSV files, one per log in the input, and print a summary of each
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bingo_fc::{
+    interface::flash::ERASED,
+    log::blackbox::{
+        decode::{DecodeError, Frame, FrameDecoder, Layout},
+        encoding::Reader,
+        fields::{self, FIELDS, FLIGHT_MODE_FAILSAFE},
+        Event,
+    },
+};
+
+const USAGE: &str = "Usage: bingo-bbdecode <log file> [output directory]";
+
+/// Text that every log starts with
+const LOG_START: &[u8] = b"H Product:";
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next().map(PathBuf::from) else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE
+    };
+    let out_dir = args.next().map(PathBuf::from).unwrap_or_else(|| {
+        path.parent().map(Path::to_path_buf).unwrap_or_default()
+    });
+
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to read {}: {e}", path.display());
+            return ExitCode::FAILURE
+        },
+    };
+
+    let logs = split_logs(&data);
+    if logs.is_empty() {
+        eprintln!("No logs found in {}", path.display());
+        return ExitCode::FAILURE
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut result = ExitCode::SUCCESS;
+    for (i, log) in logs.iter().enumerate() {
+        let csv = out_dir.join(format!("{stem}.{:02}.csv", i + 1));
+        println!("Log {} -> {}", i + 1, csv.display());
+        match decode(log, &csv) {
+            Ok(summary) => summary.print(),
+            Err(e) => {
+                eprintln!("  Failed to decode: {e}");
+                result = ExitCode::FAILURE;
+            },
+        }
+    }
+
+    result
+}
+
+/// Split a file into the logs it holds, each starting with its header
+fn split_logs(data: &[u8]) -> Vec<&[u8]> {
+    let starts: Vec<_> = data.windows(LOG_START.len())
+        .enumerate()
+        .filter(|(_, window)| *window == LOG_START)
+        .map(|(i, _)| i)
+        .collect();
+
+    starts.iter()
+        .enumerate()
+        .map(|(i, &start)| &data[start..starts.get(i + 1).copied().unwrap_or(data.len())])
+        .collect()
+}
+
+/// Statistics gathered while decoding a log
+#[derive(Default)]
+struct Summary {
+    names: Vec<String>,
+    looptime: Option<u32>,
+    p_divisor: u32,
+    intra_frames: usize,
+    inter_frames: usize,
+    corrupt: usize,
+    first_time: Option<u32>,
+    last_time: u32,
+    /// Intervals between consecutive recorded frames in microseconds
+    intervals: Vec<f64>,
+    /// Largest absolute gyro and setpoint rate on each axis in deg/s
+    max_gyro: [i32 ; 3],
+    max_setpoint: [i32 ; 3],
+    events: Vec<(u32, String)>,
+    ended: bool,
+}
+
+impl Summary {
+    fn column(&self, field: usize) -> Option<usize> {
+        self.names.iter().position(|name| name == FIELDS[field].name)
+    }
+
+    fn print(&self) {
+        let frames = self.intra_frames + self.inter_frames;
+        let duration = self.last_time.wrapping_sub(self.first_time.unwrap_or(self.last_time));
+        println!("  Frames:      {frames} ({} I, {} P), {} corrupt", self.intra_frames, self.inter_frames, self.corrupt);
+        println!("  Duration:    {:.3} s", duration as f64 / 1e6);
+        println!("  Max gyro:    roll {} pitch {} yaw {} deg/s", self.max_gyro[0], self.max_gyro[1], self.max_gyro[2]);
+        println!("  Max rate:    roll {} pitch {} yaw {} deg/s", self.max_setpoint[0], self.max_setpoint[1], self.max_setpoint[2]);
+
+        if !self.intervals.is_empty() {
+            let count = self.intervals.len() as f64;
+            let mean = self.intervals.iter().sum::<f64>() / count;
+            let std_dev = (self.intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / count).sqrt();
+            let min = self.intervals.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = self.intervals.iter().copied().fold(0f64, f64::max);
+            let divisor = self.p_divisor.max(1) as f64;
+            print!("  Loop time:   {:.1} us", mean / divisor);
+            if let Some(looptime) = self.looptime {
+                print!(" (configured {looptime} us)");
+            }
+            println!();
+            println!("  Jitter:      {:.1} us std dev, frame interval {min:.0}..{max:.0} us", std_dev / divisor);
+        }
+
+        for (time, event) in &self.events {
+            println!("  {:>10.3} s  {event}", time.wrapping_sub(self.first_time.unwrap_or(*time)) as f64 / 1e6);
+        }
+        if !self.ended {
+            println!("  Log is missing its end marker");
+        }
+    }
+}
+
+fn decode(log: &[u8], csv: &Path) -> Result<Summary, String> {
+    let mut summary = Summary::default();
+    let mut layout = Layout::default();
+    let mut offset = 0;
+    while log[offset..].starts_with(b"H ") {
+        let len = log[offset..].iter().position(|&b| b == b'\n').map_or(log.len() - offset, |i| i + 1);
+        let line = String::from_utf8_lossy(&log[offset + 2..offset + len]);
+        offset += len;
+
+        layout.header(&line).map_err(|e| format!("invalid header line '{}': {e:?}", line.trim_end()))?;
+        let (key, value) = line.trim_end().split_once(':').unwrap_or_default();
+        match key {
+            "Field I name" => summary.names = value.split(',').map(str::to_owned).collect(),
+            "looptime" => summary.looptime = value.parse().ok(),
+            _ => {},
+        }
+    }
+    if summary.names.is_empty() {
+        return Err("header has no field names".into())
+    }
+    summary.p_divisor = layout.p_divisor;
+
+    let mut out = BufWriter::new(File::create(csv).map_err(|e| format!("failed to create {}: {e}", csv.display()))?);
+    writeln!(out, "{}", summary.names.join(",")).map_err(|e| e.to_string())?;
+
+    let iteration = summary.column(fields::LOOP_ITERATION);
+    let time = summary.column(fields::TIME);
+    let gyro = (0..3).map(|axis| summary.column(fields::GYRO + axis)).collect::<Vec<_>>();
+    let setpoint = (0..3).map(|axis| summary.column(fields::SETPOINT + axis)).collect::<Vec<_>>();
+
+    let frames = &log[offset..];
+    let mut decoder = FrameDecoder::new(layout);
+    let mut offset = 0;
+    let mut previous: Option<(i32, u32)> = None;
+    let mut failsafe = false;
+    while offset < frames.len() {
+        // No frame starts with an erased byte, so a run of them is padding rather than corruption
+        if frames[offset] == ERASED {
+            offset += frames[offset..].iter().position(|&b| b != ERASED).unwrap_or(frames.len() - offset);
+            continue
+        }
+
+        let mut reader = Reader::new(&frames[offset..]);
+        let frame = decoder.next(&mut reader);
+        match frame {
+            Ok(Frame::Main { intra, values }) => {
+                offset += reader.position();
+                match intra {
+                    true => summary.intra_frames += 1,
+                    false => summary.inter_frames += 1,
+                }
+
+                let row: Vec<_> = values.iter().map(i32::to_string).collect();
+                writeln!(out, "{}", row.join(",")).map_err(|e| e.to_string())?;
+
+                let get = |column: Option<usize>| column.map(|c| values[c]);
+                for axis in 0..3 {
+                    summary.max_gyro[axis] = summary.max_gyro[axis].max(get(gyro[axis]).unwrap_or(0).abs());
+                    summary.max_setpoint[axis] = summary.max_setpoint[axis].max(get(setpoint[axis]).unwrap_or(0).abs());
+                }
+
+                if let (Some(iteration), Some(time)) = (get(iteration), get(time)) {
+                    let time = time as u32;
+                    summary.first_time.get_or_insert(time);
+                    summary.last_time = time;
+                    if let Some((last_iteration, last_time)) = previous {
+                        // Only frames with no dropped frames between them show the loop timing
+                        if iteration.wrapping_sub(last_iteration) as u32 == summary.p_divisor.max(1) {
+                            summary.intervals.push(time.wrapping_sub(last_time) as f64);
+                        }
+                    }
+                    previous = Some((iteration, time));
+                }
+            },
+            Ok(Frame::Event(event)) => {
+                offset += reader.position();
+                let (time, text) = match event {
+                    Event::Armed { time } => (time, "Armed".to_owned()),
+                    Event::Disarmed { reason } => (summary.last_time, format!("Disarmed ({reason:?})")),
+                    Event::FlightMode { flags, .. } => {
+                        let entered = flags & FLIGHT_MODE_FAILSAFE != 0;
+                        let text = match (failsafe, entered) {
+                            (false, true) => "Failsafe".to_owned(),
+                            (true, false) => "Failsafe cleared".to_owned(),
+                            _ => format!("Flight mode flags {flags:#x}"),
+                        };
+                        failsafe = entered;
+                        (summary.last_time, text)
+                    },
+                };
+                summary.events.push((time, text));
+            },
+            Ok(Frame::End) => {
+                summary.ended = true;
+                break
+            },
+            // A frame cut off at the end of the log, which happens if power was lost
+            Err(DecodeError::Truncated) if offset + reader.position() >= frames.len() => break,
+            Err(_) => {
+                summary.corrupt += 1;
+                decoder.invalidate();
+                previous = None;
+                offset += 1;
+            },
+        }
+    }
+
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(summary)
+}
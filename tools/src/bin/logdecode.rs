@@ -0,0 +1,115 @@
+//! Decode binary log frames from a file, serial device, or standard input and print the
+//! formatted messages. Streams whose header names a different format string table than the one
+//! this tool was built with are rejected
+
+use std::{env, fs::File, io::{self, BufReader, Read}, process::ExitCode, time::Duration};
+
+use bingo_fc::log::binary::{parse_header, Decoder, Message, TABLE_HASH};
+
+const USAGE: &str = "Usage: bingo-logdecode <file | serial device | -> [baud rate]";
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE
+    };
+
+    let baud = match args.next().map(|b| b.parse::<u32>()) {
+        Some(Ok(baud)) => baud,
+        Some(Err(_)) => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE
+        },
+        None => 115_200,
+    };
+
+    let input: Box<dyn Read> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else if is_serial_device(&path) {
+        match serialport::new(&path, baud).timeout(Duration::from_secs(3600)).open() {
+            Ok(port) => port,
+            Err(e) => {
+                eprintln!("Failed to open serial device {path}: {e}");
+                return ExitCode::FAILURE
+            },
+        }
+    } else {
+        match File::open(&path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Failed to open {path}: {e}");
+                return ExitCode::FAILURE
+            },
+        }
+    };
+
+    let mut decoder = Decoder::new();
+    let mut checked = false;
+    for byte in BufReader::new(input).bytes() {
+        let byte = match byte {
+            Ok(byte) => byte,
+            Err(e) => {
+                eprintln!("Failed to read log: {e}");
+                return ExitCode::FAILURE
+            },
+        };
+
+        let Some(payload) = decoder.push(byte) else { continue };
+        match parse_header(payload) {
+            Some(TABLE_HASH) => checked = true,
+            Some(hash) => {
+                eprintln!(
+                    "Log uses format string table {hash:08x} but this decoder was built with {TABLE_HASH:08x}, \
+                     rebuild it from the same sources as the firmware"
+                );
+                return ExitCode::FAILURE
+            },
+            None => {
+                if !checked {
+                    eprintln!("No header seen yet, messages may not match their format strings");
+                    checked = true;
+                }
+                println!("{}", render(payload));
+            },
+        }
+    }
+
+    if decoder.errors() > 0 {
+        eprintln!("Skipped {} corrupted frames", decoder.errors());
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Check if a path names a serial port rather than a regular file, either a Unix tty device or
+/// a Windows port such as `COM3`
+fn is_serial_device(path: &str) -> bool {
+    let port = path.strip_prefix(r"\\.\").unwrap_or(path);
+    let is_com_port = port.get(..3).is_some_and(|prefix| prefix.eq_ignore_ascii_case("COM"))
+        && port.len() > 3
+        && port[3..].bytes().all(|b| b.is_ascii_digit());
+    path.starts_with("/dev/tty") || path.starts_with("/dev/cu.") || is_com_port
+}
+
+/// Format a frame's payload in the same layout as text log messages
+fn render(payload: &[u8]) -> String {
+    let message = match Message::parse(payload) {
+        Ok(message) => message,
+        Err(e) => return format!("<invalid frame: {e:?}>"),
+    };
+
+    let mut line = String::new();
+    if let Some(t) = message.timestamp {
+        line.push_str(&format!("{:>5}.{:06} ", t / 1_000_000, t % 1_000_000));
+    }
+    line.push_str(&format!("[{:<5}] ", message.level));
+
+    let mut text = String::new();
+    match message.format(&mut text) {
+        Ok(()) => line.push_str(&text),
+        Err(e) => line.push_str(&format!("<format string {}: {e:?}>", message.index)),
+    }
+
+    line
+}